@@ -65,17 +65,11 @@ impl ThreadPool {
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        println!("Sending terminate message to all workers.");
-
         for _ in &mut self.workers {
             self.sender.send(Message::Terminate).unwrap();
         }
 
-        println!("Shutting down all workers.");
-
         for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
-
             if let Some(thread) = worker.thread.take() {
                 thread.join().unwrap();
             }
@@ -84,7 +78,6 @@ impl Drop for ThreadPool {
 }
 
 struct Worker {
-    id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
@@ -92,27 +85,24 @@ impl Worker {
     fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) ->
         Worker {
 
-        let thread = thread::spawn(move ||{
+        let builder = thread::Builder::new().name(format!("worker {}", id));
+
+        let thread = builder.spawn(move ||{
             loop {
                 let message = receiver.lock().recv().unwrap();
 
                 match message {
                     Message::NewJob(job) => {
-                        println!("Worker {} got a job; executing.", id);
-
                         job.call_box();
                     },
                     Message::Terminate => {
-                        println!("Worker {} was told to terminate.", id);
-
                         break;
                     },
                 }
             }
-        });
+        }).unwrap();
 
         Worker {
-            id,
             thread: Some(thread),
         }
     }
@@ -145,42 +145,36 @@ impl VectorOps<Vec<f64>, Vec<f64>> for Vec<f64> {
 impl VectorOps<Vec<f64>, Vec<f64>> for Vec<Vec<f64>> {
     fn dot(&self, other: &Vec<f64>) -> Vec<f64> {
         self.iter()
-            .cloned()
             .map(|v1| vec_op! { v1 * other accumulate })
             .collect()
     }
 
     fn add(&self, other: &Vec<f64>) -> Vec<f64> {
         self.iter()
-            .cloned()
             .map(|v1| vec_op! { v1 + other accumulate })
             .collect()
     }
 
     fn sub(&self, other: &Vec<f64>) -> Vec<f64> {
         self.iter()
-            .cloned()
             .map(|v1| vec_op! { v1 - other accumulate })
             .collect()
     }
 
     fn div(&self, other: &Vec<f64>) -> Vec<f64> {
         self.iter()
-            .cloned()
             .map(|v1| vec_op! { v1 / other accumulate })
             .collect()
     }
 
     fn rem(&self, other: &Vec<f64>) -> Vec<f64> {
         self.iter()
-            .cloned()
             .map(|v1| vec_op! { v1 % other accumulate })
             .collect()
     }
 
     fn mul(&self, other: &Vec<f64>) -> Vec<f64> {
         self.iter()
-            .cloned()
             .map(|v1| vec_op! { v1 * other accumulate })
             .collect()
     }
@@ -286,19 +280,7 @@ impl<T: Neg<Output = T> + Copy + PartialOrd> DiagFlat<T> for Vec<Vec<T>> {
     fn diag(&self) -> Vec<T> {
         assert!(self.len() == self[0].len(), "Must be square matrix!");
 
-        let n = self.len();
-        let mut diag_flat = Vec::<T>::with_capacity(n);
-
-        for i in 0..n {
-            for j in 0..n {
-                match i == j {
-                    true => diag_flat[i] = self[i][j],
-                    false => continue,
-                }
-            }
-        }
-
-        diag_flat
+        self.iter().enumerate().map(|(i, row)| row[i]).collect()
     }
 
     fn is_diagonal(&self, epsilon: T) -> bool {
@@ -313,7 +295,7 @@ impl<T: Neg<Output = T> + Copy + PartialOrd> DiagFlat<T> for Vec<Vec<T>> {
 pub trait ConvergentF64 {
     fn is_convergent(
         &self,
-        other: &Vec<f64>,
+        other: &[f64],
         relative_tolerange: f64,
         absolute_tolerance: f64,
     ) -> bool;
@@ -322,14 +304,14 @@ pub trait ConvergentF64 {
 impl ConvergentF64 for Vec<f64> {
     fn is_convergent(
         &self,
-        other: &Vec<f64>,
+        other: &[f64],
         relative_tolerance: f64,
         absolute_tolerance: f64,
     ) -> bool {
         self.iter()
             .cloned()
             .zip(other.iter().cloned())
-            .all(|(a, b)| (a - b).abs() <= (absolute_tolerance + relative_tolerance + b.abs()))
+            .all(|(a, b)| (a - b).abs() <= (absolute_tolerance + relative_tolerance * b.abs()))
     }
 }
 
@@ -359,12 +341,102 @@ impl Dampen<f64, Vec<f64>> for Vec<f64> {
 
 
 
-#[derive(Clone)]
+/// An unknown of the MNA system along with its last value, i.e. the voltage of a net or the
+/// current through a branch.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnknownFactor {
+    Voltage(f64),
+    Current(f64),
+}
+
+impl UnknownFactor {
+    pub fn get_value(&self) -> f64 {
+        match self {
+            Self::Voltage(value) | Self::Current(value) => *value,
+        }
+    }
+
+    pub fn modify_value(&mut self, value: f64) {
+        match self {
+            Self::Voltage(v) | Self::Current(v) => *v = value,
+        }
+    }
+}
+
+pub trait UnknownFactors {
+    fn get_all(&self) -> Vec<f64>;
+    fn set_all(&mut self, values: Vec<f64>);
+}
+
+impl UnknownFactors for Vec<UnknownFactor> {
+    fn get_all(&self) -> Vec<f64> {
+        self.iter().map(|x| x.get_value()).collect()
+    }
+
+    fn set_all(&mut self, values: Vec<f64>) {
+        self.iter_mut()
+            .zip(values)
+            .for_each(|(unknown, value)| unknown.modify_value(value));
+    }
+}
+
+/// An entry of the right hand side. Constants come from linear elements and independent sources,
+/// the dampened ones from linearising nonlinear elements and are damped with `dampen_ln` so the
+/// iteration doesn't overshoot.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RhsValueType {
+    Constant(f64),
+    Dampened(f64),
+}
+
+impl RhsValueType {
+    pub fn get_value(&self) -> f64 {
+        match self {
+            Self::Constant(value) | Self::Dampened(value) => *value,
+        }
+    }
+}
+
+pub trait LinearizeRhs {
+    fn calculate_linearize_at_kth(&self, alpha: f64, k: usize) -> Vec<f64>;
+    fn update_all(&mut self, values: Vec<f64>);
+}
+
+impl LinearizeRhs for Vec<RhsValueType> {
+    /// The right hand side for the `k`th iteration, counting from 0.
+    fn calculate_linearize_at_kth(&self, alpha: f64, k: usize) -> Vec<f64> {
+        self.iter()
+            .map(|x| match x {
+                RhsValueType::Constant(value) => *value,
+                RhsValueType::Dampened(value) => value.dampen_ln(alpha, k + 1),
+            })
+            .collect()
+    }
+
+    fn update_all(&mut self, values: Vec<f64>) {
+        self.iter_mut()
+            .zip(values)
+            .for_each(|(rhs, value)| match rhs {
+                RhsValueType::Constant(_) => (),
+                RhsValueType::Dampened(v) => *v = value,
+            });
+    }
+}
+
+/// How the current through an element relates to the voltages on its terminals, which is what
+/// its stamps are worked out from.
+///
+/// - `Linear`: a constant conductance.
+/// - `NonLinear`: the current from `-in` to `-out` for the voltage from `-in` to `-out`.
+/// - `Dynamic`: the capacitance (or inductance) for the voltage across it at the `u64`th time step.
+/// - `MultiTerminalNonLinear`: the current from `-in` to `-out` for the voltages of `-in` and of
+///   `-base`, both against `-out`.
+/// - `MultiTerminalDyanmic`: as `Dynamic`, for the same two voltages.
 pub enum ElementStampType<'a> {
     Linear(f64),
-    NonLinear(&'a dyn Fn(f64) -> f64),
-    Dynamic(&'a dyn Fn(f64, u64) -> f64),
-    MultiTerminalNonLinear(&'a dyn Fn(f64, f64) -> f64),
-    MultiTerminalDyanmic(&'a dyn Fn(f64, f64, u64) -> f64),    
+    NonLinear(Box<dyn Fn(f64) -> f64 + 'a>),
+    Dynamic(Box<dyn Fn(f64, u64) -> f64 + 'a>),
+    MultiTerminalNonLinear(Box<dyn Fn(f64, f64) -> f64 + 'a>),
+    MultiTerminalDyanmic(Box<dyn Fn(f64, f64, u64) -> f64 + 'a>),
 }
 
@@ -1,8 +1,8 @@
-use std::{fs::File, io::read_to_string, path::Component, cell::{RefCell, Ref}};
+use std::{fmt, fs, ops::Range, path::{Path, PathBuf}};
 use scheesim_macro::*;
 
 #[derive(Clone)]
-enum ElementMarker {
+pub enum ElementMarker {
     ACSweep,
     DCSource,
     Resistor,
@@ -12,49 +12,239 @@ enum ElementMarker {
     Diode,
 }
 
-#[macro_export]
-macro_rules! error_out {
-    ($m:literal, $($f:ident),*) => {
-        {
-            let string = format!($m, $($f),*);
-            eprintln!("\x1b[1;31mError:\x1b[0m {}", string);
-            std::process::exit(1);
-        }
+/// A problem found in a netlist, pointing at the line and the columns of the lexeme that caused it.
+/// Every stage of parsing reports through this instead of bailing out, so they can all be collected.
+///
+/// What it carries is boxed, so the `Result`s it is returned in stay small.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    data: Box<DiagnosticData>,
+}
 
-    };
+#[derive(Clone, Debug)]
+struct DiagnosticData {
+    line_number: usize,
+    span: Range<usize>,
+    lexeme: String,
+    message: String,
+    hint: Option<String>,
+    file: Option<String>,
+    warning: bool,
 }
 
-macro_rules! copy_vec {
-    ($v:ident) => {
-        {
-            let mut vc = vec![];
+impl Diagnostic {
+    pub fn new(line_number: usize, message: String) -> Self {
+        Self { data: Box::new(DiagnosticData { line_number, span: 0..0, lexeme: String::new(), message, hint: None, file: None, warning: false }) }
+    }
+
+    /// A diagnostic about a single lexeme, spanning it from column 0. `LexemeLine` moves the span
+    /// to where the lexeme actually sits in the line.
+    pub fn at_lexeme(lexeme: &str, line_number: usize, message: String) -> Self {
+        Self { data: Box::new(DiagnosticData { line_number, span: 0..lexeme.chars().count(), lexeme: lexeme.to_string(), message, hint: None, file: None, warning: false }) }
+    }
+
+    pub fn at_span(line_number: usize, span: Range<usize>, lexeme: &str, message: String) -> Self {
+        Self { data: Box::new(DiagnosticData { line_number, span, lexeme: lexeme.to_string(), message, hint: None, file: None, warning: false }) }
+    }
+
+    pub fn with_hint(mut self, hint: Option<String>) -> Self {
+        self.data.hint = hint;
+        self
+    }
+
+    /// Marks the diagnostic as pointing into `file` rather than the netlist being parsed.
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.data.file = Some(file.to_string());
+        self
+    }
+
+    /// Marks the diagnostic as a warning, which is reported but doesn't stop the circuit.
+    pub fn as_warning(mut self) -> Self {
+        self.data.warning = true;
+        self
+    }
+
+    pub fn offset(mut self, column: usize) -> Self {
+        self.data.span = (self.data.span.start + column)..(self.data.span.end + column);
+        self
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.data.line_number
+    }
+
+    pub fn get_span(&self) -> &Range<usize> {
+        &self.data.span
+    }
+
+    pub fn get_lexeme(&self) -> &str {
+        &self.data.lexeme
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.data.message
+    }
+
+    pub fn get_hint(&self) -> Option<&String> {
+        self.data.hint.as_ref()
+    }
 
-            for itm in $v {
-                vc.push(itm.clone());
+    pub fn get_file(&self) -> Option<&String> {
+        self.data.file.as_ref()
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.data.warning
+    }
+
+    /// Where the diagnostic points, e.g. `lib.schnl, line 3, column 9`.
+    fn location(&self, with_column: bool) -> String {
+        let line = match with_column {
+            true => format!("line {}, column {}", self.data.line_number, self.data.span.start + 1),
+            false => format!("line {}", self.data.line_number),
+        };
+
+        match &self.data.file {
+            Some(file) => format!("{}, {}", file, line),
+            None => line,
+        }
+    }
+
+    /// Renders the diagnostic the way rustc does, quoting the offending line from `source` and
+    /// underlining the lexeme with carets.
+    ///
+    /// ```text
+    /// error: Wrong type of argument
+    ///  --> line 1, column 16
+    ///   |
+    /// 1 | ;lowPassFilter -inducance=3m
+    ///   |                ^^^^^^^^^^^^^
+    ///   = help: did you mean `-inductance`?
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let line = match self.data.line_number {
+            0 => None,
+            n => source.lines().nth(n - 1),
+        };
+
+        let mut rendered = match self.data.warning {
+            true => format!("warning: {}\n", self.data.message),
+            false => format!("error: {}\n", self.data.message),
+        };
+
+        match line {
+            Some(line) => {
+                let gutter = " ".repeat(self.data.line_number.to_string().len());
+
+                rendered.push_str(&format!("{}--> {}\n", gutter, self.location(!self.data.span.is_empty())));
+
+                rendered.push_str(&format!("{} |\n", gutter));
+                rendered.push_str(&format!("{} | {}\n", self.data.line_number, line));
+
+                if !self.data.span.is_empty() {
+                    // Tabs are kept so the carets line up with the quoted line.
+                    let padding = line
+                        .chars()
+                        .take(self.data.span.start)
+                        .map(|ch| match ch {
+                            '\t' => '\t',
+                            _ => ' ',
+                        })
+                        .collect::<String>();
+
+                    rendered.push_str(&format!("{} | {}{}\n", gutter, padding, "^".repeat(self.data.span.len())));
+                }
+
+                if let Some(hint) = &self.data.hint {
+                    rendered.push_str(&format!("{} = help: {}\n", gutter, hint));
+                }
             }
+            None => {
+                rendered.push_str(&format!("--> {}\n", self.location(false)));
+
+                if let Some(hint) = &self.data.hint {
+                    rendered.push_str(&format!("= help: {}\n", hint));
+                }
+            }
+        }
+
+        rendered
+    }
+}
+
+/// Levenshtein distance between two strings, used to suggest the name that was probably meant.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b_chars.len()).collect::<Vec<usize>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, cb) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + match ca == *cb {
+                true => 0,
+                false => 1,
+            };
+
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
 
-            vc
+        previous = current;
+    }
+
+    previous[b_chars.len()]
+}
+
+/// Picks the closest of `candidates` to `s`, if it's close enough to be a typo of it.
+fn suggest(s: &str, candidates: &[&str]) -> Option<String> {
+    let s = s.to_lowercase();
+    let threshold = (s.chars().count() / 3).max(1);
+
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&s, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| format!("did you mean `{}`?", candidate))
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.data.lexeme.is_empty() {
+            true => write!(f, "{}: {}", self.location(false), self.data.message),
+            false => write!(f, "{}: {} ('{}')", self.location(true), self.data.message, self.data.lexeme),
         }
-    };
+    }
 }
 
 impl ElementMarker {
-    pub fn from(s: &str, line_num: usize) -> Self {
+    pub const NAMES: [&'static str; 7] = [
+        ".acsweep",
+        ".dcsource",
+        ".resistor",
+        ".capacitor",
+        ".inductor",
+        ".transistor",
+        ".diode",
+    ];
+
+    pub fn from(s: &str, line_num: usize) -> Result<Self, Diagnostic> {
         match s.to_lowercase().as_str() {
-            ".acsweep" => Self::ACSweep,
-            ".dcsource" => Self::DCSource,
-            ".resistor" => Self::Resistor,
-            ".capacitor" => Self::Capacitor,
-            ".inductor" => Self::Inductor,
-            ".transistor" => Self::Transistor,
-            ".diode" => Self::Diode,
-            _ => error_out!("Unknown element: {} at line {}", s, line_num),
+            ".acsweep" => Ok(Self::ACSweep),
+            ".dcsource" => Ok(Self::DCSource),
+            ".resistor" => Ok(Self::Resistor),
+            ".capacitor" => Ok(Self::Capacitor),
+            ".inductor" => Ok(Self::Inductor),
+            ".transistor" => Ok(Self::Transistor),
+            ".diode" => Ok(Self::Diode),
+            _ => Err(Diagnostic::at_lexeme(s, line_num, "Unknown element".to_string())
+                .with_hint(suggest(s, &Self::NAMES))),
         }
     }
 }
 
 #[derive(Clone)]
-enum Unit {
+pub enum Unit {
     Quetta(f64),
     Ronna(f64),
     Yotta(f64),
@@ -83,60 +273,132 @@ enum Unit {
 }
 
 impl Unit {
-    pub fn from(s: &str, line_number: usize) -> Self {
-        let (digits, unit) = {
-            let mut digits = String::new();
-            let mut unit = String::new();
-
-            for (i, ch) in s.char_indices() {
-                if ch.is_numeric() || ch == '.' || ch == 'e' {
-                    digits.push(ch);
-                } else if vec![
-                    'Q', 'R', 'R', 'Y', 'Z', 'E', 'P', 'T', 'G', 'M', 'k', 'h', 'd', 'a', 'c', 'm',
-                    'u', 'n', 'p', 'f', 'z', 'y', 'r', 'q',
-                ]
-                .contains(&ch)
-                {
-                    unit.push(ch);
-                } else {
-                    error_out!("Error in SI unit '{}' in line {}", s, line_number);
+    /// Reads a value without a physical unit, e.g. `4.7k`, `-2e-3n` or `1meg`.
+    pub fn from(s: &str, line_number: usize) -> Result<Self, Diagnostic> {
+        Self::with_units(s, line_number, &[])
+    }
+
+    /// Reads a value such as `-2.2e3`, `33uF` or `10kOhm`: an optional sign, a number with an
+    /// optional exponent, an optional SI prefix (or SPICE's `meg` and `mil`) and, when `units`
+    /// isn't empty, optionally one of those physical units. Units are matched without regard to
+    /// case, prefixes are not apart from `K`, `meg` and `mil`, so `M` is mega and `m` is milli.
+    ///
+    /// As in SPICE, a letter that is both a prefix and a unit is the prefix, so `5a` is 5 aA and
+    /// `1f` with farads is 1 fF, while `1F` is 1 F. The longest prefix wins, `da` is deca rather
+    /// than deci followed by `a`, and a prefix is only taken when what follows it is a unit, so
+    /// `5dA` is deci-ampere. Values too large for an
+    /// `f64`, such as `1e400`, are rejected.
+    pub fn with_units(s: &str, line_number: usize, units: &[&str]) -> Result<Self, Diagnostic> {
+        Self::read(s, units)
+            .map(|(unit, _)| unit)
+            .map_err(|message| Diagnostic::at_lexeme(s, line_number, message))
+    }
+
+    /// Does the work of `with_units`, also handing back the physical unit as it was written.
+    fn read(s: &str, units: &[&str]) -> Result<(Self, String), String> {
+        let chars = s.trim().chars().collect::<Vec<char>>();
+        let mut i = 0;
+
+        if let Some('+') | Some('-') = chars.first() {
+            i += 1;
+        }
+
+        let digits_start = i;
+
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+            i += 1;
+        }
+
+        if !chars[digits_start..i].iter().any(|x| x.is_ascii_digit()) {
+            return Err(format!("Expected a number in '{}'", s.trim()));
+        }
+
+        // `e` only starts an exponent when digits follow, otherwise it's the exa prefix `E`.
+        if let Some('e') | Some('E') = chars.get(i) {
+            let digits_at = match chars.get(i + 1) {
+                Some('+') | Some('-') => i + 2,
+                _ => i + 1,
+            };
+
+            if chars.get(digits_at).is_some_and(|x| x.is_ascii_digit()) {
+                i = digits_at;
+
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
                 }
             }
+        }
 
-            (digits, unit)
-        };
+        let mantissa = chars[..i].iter().collect::<String>();
+        let value = mantissa.parse::<f64>().map_err(|_| format!("Malformed number '{}'", mantissa))?;
 
-        let unit_parsed = digits.parse::<f64>().unwrap();
-
-        match unit.as_str() {
-            "Q" => Self::Quetta(unit_parsed),
-            "R" => Self::Ronna(unit_parsed),
-            "Y" => Self::Yotta(unit_parsed),
-            "Z" => Self::Zetta(unit_parsed),
-            "E" => Self::Exa(unit_parsed),
-            "P" => Self::Peta(unit_parsed),
-            "T" => Self::Tera(unit_parsed),
-            "G" => Self::Giga(unit_parsed),
-            "M" => Self::Mega(unit_parsed),
-            "k" => Self::Kilo(unit_parsed),
-            "h" => Self::Hecto(unit_parsed),
-            "d" => Self::Deci(unit_parsed),
-            "a" => Self::Atto(unit_parsed),
-            "da" => Self::Deca(unit_parsed),
-            "c" => Self::Centi(unit_parsed),
-            "m" => Self::Milli(unit_parsed),
-            "u" => Self::Micro(unit_parsed),
-            "n" => Self::Nano(unit_parsed),
-            "p" => Self::Pico(unit_parsed),
-            "f" => Self::Femto(unit_parsed),
-            "z" => Self::Zepto(unit_parsed),
-            "y" => Self::Yocto(unit_parsed),
-            "r" => Self::Ronto(unit_parsed),
-            "q" => Self::Quecto(unit_parsed),
-            _ => Self::One(unit_parsed),
+        let rest = &chars[i..];
+        let whole = rest.iter().collect::<String>();
+
+        // The longest prefix is taken first, so `1f` is a femto and `1fF` a femtofarad.
+        let read = (0..=rest.len()).rev().find_map(|split| {
+            let prefix = rest[..split].iter().collect::<String>();
+            let unit = rest[split..].iter().collect::<String>();
+
+            match unit.is_empty() || units.iter().any(|x| x.to_lowercase() == unit.to_lowercase()) {
+                true => Self::from_prefix(value, &prefix).map(|scaled| (scaled, unit)),
+                false => None,
+            }
+        });
+
+        match read {
+            Some((scaled, _)) if !scaled.get_corresponding_value().is_finite() => {
+                return Err(format!("'{}' is too large to be represented", s.trim()))
+            }
+            Some(read) => return Ok(read),
+            None => (),
+        }
+
+        match units.is_empty() {
+            true => Err(format!("Unknown SI prefix '{}'", whole)),
+            false => Err(format!("Unknown SI prefix or unit '{}', expected {}", whole, units.join(" or "))),
         }
     }
 
+    /// Scales `value` by an SI prefix such as `k` or `da`, `None` if there is no such prefix.
+    /// SPICE's `meg` and `mil` (a thousandth of an inch) are taken in any case, and `K` as kilo.
+    pub fn from_prefix(value: f64, prefix: &str) -> Option<Self> {
+        let unit = match prefix {
+            _ if prefix.eq_ignore_ascii_case("meg") => Self::Mega(value),
+            _ if prefix.eq_ignore_ascii_case("mil") => Self::Micro(value * 25.4),
+            "" => Self::One(value),
+            "Q" => Self::Quetta(value),
+            "R" => Self::Ronna(value),
+            "Y" => Self::Yotta(value),
+            "Z" => Self::Zetta(value),
+            "E" => Self::Exa(value),
+            "P" => Self::Peta(value),
+            "T" => Self::Tera(value),
+            "G" => Self::Giga(value),
+            "M" => Self::Mega(value),
+            "k" | "K" => Self::Kilo(value),
+            "h" => Self::Hecto(value),
+            "d" => Self::Deci(value),
+            "a" => Self::Atto(value),
+            "da" => Self::Deca(value),
+            "c" => Self::Centi(value),
+            "m" => Self::Milli(value),
+            "u" | "µ" | "μ" => Self::Micro(value),
+            "n" => Self::Nano(value),
+            "p" => Self::Pico(value),
+            "f" => Self::Femto(value),
+            "z" => Self::Zepto(value),
+            "y" => Self::Yocto(value),
+            "r" => Self::Ronto(value),
+            "q" => Self::Quecto(value),
+            _ => return None,
+        };
+
+        Some(unit)
+    }
+
+    /// The value the prefix stands for. Small prefixes divide, since `1e18` is exact as an `f64`
+    /// and `1e-18` is not, so `100a` comes out as `1e-16` rather than next to it.
     pub fn get_corresponding_value(&self) -> f64 {
         match self {
             Unit::Quetta(u) => u * 1e30,
@@ -152,497 +414,1282 @@ impl Unit {
             Unit::Hecto(u) => u * 1e2,
             Unit::Deca(u) => u * 1e1,
             Unit::One(u) => u * 1e0,
-            Unit::Deci(u) => u * 1e-1,
-            Unit::Centi(u) => u * 1e-2,
-            Unit::Milli(u) => u * 1e-3,
-            Unit::Micro(u) => u * 1e-6,
-            Unit::Nano(u) => u * 1e-9,
-            Unit::Pico(u) => u * 1e-12,
-            Unit::Femto(u) => u * 1e-15,
-            Unit::Atto(u) => u * 1e-18,
-            Unit::Zepto(u) => u * 1e-21,
-            Unit::Yocto(u) => u * 1e-24,
-            Unit::Ronto(u) => u * 1e-27,
-            Unit::Quecto(u) => u * 1e-30,
+            Unit::Deci(u) => u / 1e1,
+            Unit::Centi(u) => u / 1e2,
+            Unit::Milli(u) => u / 1e3,
+            Unit::Micro(u) => u / 1e6,
+            Unit::Nano(u) => u / 1e9,
+            Unit::Pico(u) => u / 1e12,
+            Unit::Femto(u) => u / 1e15,
+            Unit::Atto(u) => u / 1e18,
+            Unit::Zepto(u) => u / 1e21,
+            Unit::Yocto(u) => u / 1e24,
+            Unit::Ronto(u) => u / 1e27,
+            Unit::Quecto(u) => u / 1e30,
         }
     }
 }
 
-#[derive(Clone)]
-enum JunctionChannel {
-    NPN,
-    PNP,
-    NP,
-    PN,
-    N,
-    P,
-}
+impl fmt::Display for Unit {
+    /// Writes the value with the engineering prefix that keeps it between 1 and 1000, e.g. `4.7k`
+    /// for `4700` whichever way it was written.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.get_corresponding_value();
 
-impl JunctionChannel {
-    pub fn from(s: &str, line_number: usize) -> Self {
-        match s.to_lowercase().as_str() {
-            "npn" => Self::NPN,
-            "pnp" => Self::PNP,
-            "np" => Self::NP,
-            "pn" => Self::PN,
-            "n" => Self::N,
-            "p" => Self::P,
-            _ => error_out!("Wrong junction or channel '{}' in line {}", s, line_number),
+        if value == 0.0 || !value.is_finite() {
+            return write!(f, "{}", value);
         }
+
+        let exponent = ((value.abs().log10() / 3.0).floor() as i32 * 3).clamp(-30, 30);
+        let mantissa = value / 10f64.powi(exponent);
+
+        // Twelve significant digits are plenty and hide the noise from scaling by powers of ten.
+        let decimals = (11 - mantissa.abs().log10().floor() as i32).max(0) as usize;
+        let digits = format!("{:.*}", decimals, mantissa);
+        let digits = match digits.contains('.') {
+            true => digits.trim_end_matches('0').trim_end_matches('.'),
+            false => &digits,
+        };
+
+        let prefix = match exponent {
+            30 => "Q",
+            27 => "R",
+            24 => "Y",
+            21 => "Z",
+            18 => "E",
+            15 => "P",
+            12 => "T",
+            9 => "G",
+            6 => "M",
+            3 => "k",
+            -3 => "m",
+            -6 => "u",
+            -9 => "n",
+            -12 => "p",
+            -15 => "f",
+            -18 => "a",
+            -21 => "z",
+            -24 => "y",
+            -27 => "r",
+            -30 => "q",
+            _ => "",
+        };
+
+        write!(f, "{}{}", digits, prefix)
     }
 }
 
-#[derive(Clone)]
-enum Connection {
-    Serial(String),
-    Parallel(String),
-    Ground,
-    Next,
-    Prev,
+/// The physical dimension of a quantity as powers of kilogram, metre, second and ampere, which is
+/// enough to relate every electrical unit to the others, e.g. an ohm is kg·m²·s⁻³·A⁻².
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dimension {
+    mass: i8,
+    length: i8,
+    time: i8,
+    current: i8,
 }
 
-impl Connection {
-    pub fn from(s: &str, serial: bool) -> Self {
-        match s.to_lowercase().as_str() {
-            "prev" | "previous" => Self::Prev,
-            "next" => Self::Next,
-            "grnd" | "ground" => Self::Ground,
-            _ => match serial {
-                true => Self::Serial(s.to_string()),
-                false => Self::Parallel(s.to_string()),
-            }
+impl Dimension {
+    pub const NONE: Self = Self { mass: 0, length: 0, time: 0, current: 0 };
+    pub const VOLT: Self = Self { mass: 1, length: 2, time: -3, current: -1 };
+    pub const AMPERE: Self = Self { mass: 0, length: 0, time: 0, current: 1 };
+    pub const OHM: Self = Self { mass: 1, length: 2, time: -3, current: -2 };
+    pub const FARAD: Self = Self { mass: -1, length: -2, time: 4, current: 2 };
+    pub const HENRY: Self = Self { mass: 1, length: 2, time: -2, current: -2 };
+    pub const HERTZ: Self = Self { mass: 0, length: 0, time: -1, current: 0 };
+    pub const WATT: Self = Self { mass: 1, length: 2, time: -3, current: 0 };
+    pub const SECOND: Self = Self { mass: 0, length: 0, time: 1, current: 0 };
+
+    /// The dimensions that have a unit of their own, with the ways to write it. The first one is
+    /// what values are written back with.
+    const NAMED: [(Self, &'static [&'static str]); 8] = [
+        (Self::VOLT, &["V"]),
+        (Self::AMPERE, &["A"]),
+        (Self::OHM, &["Ω", "ohm", "ohms"]),
+        (Self::FARAD, &["F"]),
+        (Self::HENRY, &["H"]),
+        (Self::HERTZ, &["Hz"]),
+        (Self::WATT, &["W"]),
+        (Self::SECOND, &["s"]),
+    ];
+
+    /// Every unit symbol there is, in no particular order.
+    pub fn all_symbols() -> Vec<&'static str> {
+        Self::NAMED.iter().flat_map(|(_, symbols)| symbols.iter().copied()).collect()
+    }
+
+    /// The dimension of a unit symbol such as `V` or `ohm`, in any case.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Self::NAMED
+            .iter()
+            .find(|(_, symbols)| symbols.iter().any(|x| x.to_lowercase() == symbol.to_lowercase()))
+            .map(|(dimension, _)| *dimension)
+    }
+
+    /// The ways to write the unit of this dimension, empty when it has no unit of its own.
+    pub fn get_symbols(&self) -> &'static [&'static str] {
+        Self::NAMED.iter().find(|(x, _)| x == self).map_or(&[], |(_, symbols)| *symbols)
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Adds up the powers, an error when one no longer fits.
+    pub fn multiply(&self, other: &Self) -> Result<Self, String> {
+        match (
+            self.mass.checked_add(other.mass),
+            self.length.checked_add(other.length),
+            self.time.checked_add(other.time),
+            self.current.checked_add(other.current),
+        ) {
+            (Some(mass), Some(length), Some(time), Some(current)) => Ok(Self { mass, length, time, current }),
+            _ => Err("Exponent out of range".to_string()),
+        }
+    }
+
+    pub fn divide(&self, other: &Self) -> Result<Self, String> {
+        self.multiply(&other.power(-1)?)
+    }
+
+    /// Multiplies the powers by `n`, an error when one no longer fits.
+    pub fn power(&self, n: i8) -> Result<Self, String> {
+        match (
+            self.mass.checked_mul(n),
+            self.length.checked_mul(n),
+            self.time.checked_mul(n),
+            self.current.checked_mul(n),
+        ) {
+            (Some(mass), Some(length), Some(time), Some(current)) => Ok(Self { mass, length, time, current }),
+            _ => Err("Exponent out of range".to_string()),
+        }
+    }
+
+    /// The `n`th root, `None` when a power doesn't divide by `n`.
+    pub fn root(&self, n: i8) -> Option<Self> {
+        match [self.mass, self.length, self.time, self.current].iter().all(|x| x % n == 0) {
+            true => Some(Self {
+                mass: self.mass / n,
+                length: self.length / n,
+                time: self.time / n,
+                current: self.current / n,
+            }),
+            false => None,
         }
     }
 }
 
-#[derive(Clone)]
-enum Currentage {
-    Solo(Unit),
-    Dom(Unit),
-    Sub(Unit),
+impl fmt::Display for Dimension {
+    /// Writes the unit symbol if there is one, `1` for plain numbers and the base units otherwise,
+    /// e.g. `kg·m^2·s^-4·A^-1`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(symbol) = self.get_symbols().first() {
+            return write!(f, "{}", symbol);
+        }
+
+        if self.is_none() {
+            return write!(f, "1");
+        }
+
+        let bases = [("kg", self.mass), ("m", self.length), ("s", self.time), ("A", self.current)]
+            .iter()
+            .filter(|(_, power)| *power != 0)
+            .map(|(base, power)| match power {
+                1 => base.to_string(),
+                _ => format!("{}^{}", base, power),
+            })
+            .collect::<Vec<String>>();
+
+        write!(f, "{}", bases.join("·"))
+    }
 }
 
-#[derive(Clone)]
-enum Argument {
-    Author(String),
-    Date(String),
-    In(Connection),
-    Out(Connection),
-    Base(Connection),
-    Voltage(Currentage),
-    MaxVoltage(Unit),
-    Power(Unit),
-    Current(Currentage),
-    Inductance(Unit),
-    Capacitance(Unit),
-    Resistance(Unit),
-    Frequency(Unit),
-    JunctionChannel(JunctionChannel),
-    Dynamic,
-    Nonlinear,
+/// A value along with its physical dimension, so arithmetic on it can refuse what doesn't make
+/// sense physically, like adding volts to farads.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quantity {
+    value: f64,
+    dimension: Dimension,
 }
 
-impl Argument {
-    pub fn from(s: &str, line_number: usize) -> Self {
-        let mut split_on_equal = s.split('=');
+impl Quantity {
+    pub fn from(value: f64, dimension: Dimension) -> Self {
+        Self { value, dimension }
+    }
 
-        let name = split_on_equal.next().unwrap().trim();
+    /// Reads a value that may end in any unit symbol, e.g. `4.7kOhm` or `20ms`.
+    pub fn from_literal(s: &str) -> Result<Self, String> {
+        let (unit, symbol) = Unit::read(s, &Dimension::all_symbols())?;
+        let dimension = Dimension::from_symbol(&symbol).unwrap_or(Dimension::NONE);
 
-        match name.to_lowercase().as_str() {
-            "-dynamic" => Self::Dynamic,
-            "-nonlinear" => Self::Nonlinear,
-            _ => match split_on_equal.next() {
-                Some(v) => {
-                    if name == "-junction" || name == "-channel" {
-                        let junction_channel = JunctionChannel::from(v, line_number);
-                        return Self::JunctionChannel(junction_channel);
-                    }
+        Ok(Self::from(unit.get_corresponding_value(), dimension))
+    }
 
-                    let value = v.trim().to_string();
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
 
-                    match name.to_lowercase().as_str() {
-                        "-author" => Self::Author(value),
-                        "-date" => Self::Date(value),
-                        "-in" => Self::In(Connection::from(&value, true)),
-                        "-base" => Self::Base(Connection::from(&value, true)),
-                        "-out" => Self::Out(Connection::from(&value, true)),
-                        "-parallel" => Self::Out(Connection::from(&value, false)),
+    pub fn get_dimension(&self) -> Dimension {
+        self.dimension
+    }
 
-                        _ => {
-                            let value_unit = Unit::from(&value, line_number);
+    pub fn add(&self, other: &Self) -> Result<Self, String> {
+        match self.dimension == other.dimension {
+            true => Ok(Self::from(self.value + other.value, self.dimension)),
+            false => Err(format!("Cannot add {} and {}", self.dimension, other.dimension)),
+        }
+    }
 
-                            match name.to_lowercase().as_str() {
-                                "-voltage" => Self::Voltage(Currentage::Solo(value_unit)),
-                                "-current" => Self::Current(Currentage::Solo(value_unit)),
-                                "-voltage*" => Self::Voltage(Currentage::Dom(value_unit)),
-                                "-current*" => Self::Current(Currentage::Dom(value_unit)),
-                                "-voltage^" => Self::Voltage(Currentage::Sub(value_unit)),
-                                "-current^" => Self::Current(Currentage::Sub(value_unit)),
-                                "-max_voltage" => Self::MaxVoltage(value_unit),
-                                "-power" => Self::Power(value_unit),
-                                "-inducance" => Self::Inductance(value_unit),
-                                "-capacitance" => Self::Capacitance(value_unit),
-                                "-resistance" => Self::Resistance(value_unit),
-                                "-frequency" => Self::Frequency(value_unit),
-                                _ => error_out!(
-                                    "Wrong type of argument: '{}' in line {}",
-                                    s,
-                                    line_number
-                                ),
-                            }
-                        }
-                    }
-                }
-                None => error_out!(
-                    "Wrong argument given: '{}' in line {}. Must have value sperated by =",
-                    s,
-                    line_number
-                ),
-            },
+    pub fn subtract(&self, other: &Self) -> Result<Self, String> {
+        match self.dimension == other.dimension {
+            true => Ok(Self::from(self.value - other.value, self.dimension)),
+            false => Err(format!("Cannot subtract {} from {}", other.dimension, self.dimension)),
         }
     }
 
-    pub fn is_key(&self, key: &'static str) -> bool {
-        match key {
-            "author" => {
-                match self {
-                    Self::Author(_) => true,
-                    _ => false,
-                }
-            },
-            "date" => {
-                match self {
-                    Self::Date(_) => true,
-                    _ => false,
-                }
-            },
-            "in" => {
-                match self {
-                    Self::In(_) => true,
-                    _ => false,
-                }
-            },
-            "out" => {
-                match self {
-                    Self::Out(_) => true,
-                    _ => false,
-                }
-            },
-            "base" => {
-                match self {
-                    Self::Base(_) => true,
-                    _ => false,
-                }
-            },
-            "voltage" => {
-                match self {
-                    Self::Voltage(_) => true,
-                    _ => false,
-                }
-            },
-            "voltage" => {
-                match self {
-                    Self::Voltage(_) => true,
-                    _ => false,
-                }
-            },
-            "voltage" => {
-                match self {
-                    Self::Voltage(_) => true,
-                    _ => false,
-                }
-            },
-            "max_voltage" => {
-                match self {
-                    Self::MaxVoltage(_) => true,
-                    _ => false,
-                }
-            },
-            "power" => {
-                match self {
-                    Self::Power(_) => true,
-                    _ => false,
-                }
-            },
-            "current" => {
-                match self {
-                    Self::Current(_) => true,
-                    _ => false,
-                }
-            },
-            "resistance" => {
-                match self {
-                    Self::Resistance(_) => true,
-                    _ => false,
-                }
-            },
-            "capacitance" => {
-                match self {
-                    Self::Capacitance(_) => true,
-                    _ => false,
-                }
-            },
-            "inductance" => {
-                match self {
-                    Self::Inductance(_) => true,
-                    _ => false,
-                }
-            },
-            "frequency" => {
-                match self {
-                    Self::Frequency(_) => true,
-                    _ => false,
-                }
-            },
-            "junction_channel" => {
-                match self {
-                    Self::JunctionChannel(_) => true,
-                    _ => false,
-                }
-            },
-            "dynamic" => {
-                match self {
-                    Self::Dynamic => true,
-                    _ => false,
-                }
-            },
-            "nonlinear" => {
-                match self {
-                    Self::Nonlinear => true,
-                    _ => false,
-                }
-            }
-        }  
+    pub fn multiply(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::from(self.value * other.value, self.dimension.multiply(&other.dimension)?))
     }
-}
 
-trait FilterArgList {
-    fn filter(&self, key: &'static str) -> Vec<Argument>;
-}
+    pub fn divide(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::from(self.value / other.value, self.dimension.divide(&other.dimension)?))
+    }
 
-impl FilterArgList for Vec<Argument> {
-    fn filter(&self, key: &'static str) -> Vec<Argument> {
-        self
-            .clone()
-            .into_iter()
-            .filter(|x| {
-                x.is_key(key)
-            })
-            .collect()
+    /// Raises to a plain number, which has to be a whole one unless this is a plain number too.
+    pub fn power(&self, exponent: &Self) -> Result<Self, String> {
+        if !exponent.dimension.is_none() {
+            return Err(format!("Cannot raise to a power in {}", exponent.dimension));
+        }
+
+        match (self.dimension.is_none(), exponent.value.fract() == 0.0 && exponent.value.abs() <= 127.0) {
+            (true, _) => Ok(Self::from(self.value.powf(exponent.value), Dimension::NONE)),
+            (false, true) => Ok(Self::from(
+                self.value.powf(exponent.value),
+                self.dimension.power(exponent.value as i8)?,
+            )),
+            (false, false) => Err(format!("Cannot raise {} to the power of {}", self.dimension, exponent.value)),
+        }
     }
 }
 
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dimension.is_none() {
+            true => write!(f, "{}", Unit::One(self.value)),
+            false => write!(f, "{}{}", Unit::One(self.value), self.dimension),
+        }
+    }
+}
 
+/// Arithmetic written inside `{...}` argument values, e.g. `{1/(2*pi*fc*r1)}`. Numbers take SI
+/// prefixes like everywhere else and may carry a unit, `{2*1kOhm}`, names refer to parameters and
+/// `pi` is predefined. Units are checked as the expression is worked out.
 #[derive(Clone)]
-enum Lexeme {
-    NetlistName(String),
-    Element(ElementMarker),
-    NodeName(String),
-    ProfileName(String),
-    Arg(Argument),
-    Pobe,
-    EndMarker,
-    Comment,
+pub enum Expression {
+    Number(Quantity),
+    Name(String),
+    Negate(Box<Expression>),
+    Binary(char, Box<Expression>, Box<Expression>),
+    Call(String, Box<Expression>),
 }
 
-impl Lexeme {
-    pub fn from(s: &str, line_number: usize) -> Self {
-        let mut char_iter = s.chars();
+#[derive(Clone, PartialEq)]
+enum ExpressionToken {
+    Number(Quantity),
+    Name(String),
+    Symbol(char),
+}
 
-        match char_iter.next().unwrap() {
-            ';' => {
-                if s.chars().filter(|ch| *ch == ';').count() > 3 {
-                    error_out!(
-                        "Wrong lexeme '{}' at line {}: Cannot contain more than 3 semicolons.",
-                        s,
-                        line_number
-                    );
+impl fmt::Display for ExpressionToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(x) => write!(f, "{}", x),
+            Self::Name(x) => write!(f, "{}", x),
+            Self::Symbol(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+fn tokenize_expression(s: &str) -> Result<Vec<ExpressionToken>, String> {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+
+        match ch {
+            _ if ch.is_whitespace() => i += 1,
+            '+' | '-' | '*' | '/' | '^' | '(' | ')' => {
+                tokens.push(ExpressionToken::Symbol(ch));
+                i += 1;
+            }
+            _ if ch.is_ascii_digit() || ch == '.' => {
+                let start = i;
+
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
                 }
 
-                let res = match char_iter.next() {
-                    Some(ch) => match ch {
-                        ';' => {
-                            let res = match char_iter.next() {
-                                    Some(ch) => match ch {
-                                        ';' => Self::ProfileName(s.replace(";;;", "")),
-                                        _ => Self::NodeName(s.replace(";;", "")),
-                                    },
-                                    None => error_out!("Wrong token '{}' at line {}, semicolon marker cannot be left empty", ch, line_number),
-                                };
-
-                            res
+                // An exponent only counts as one when digits follow.
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let digits_at = match chars.get(i + 1) {
+                        Some('+') | Some('-') => i + 2,
+                        _ => i + 1,
+                    };
+
+                    if chars.get(digits_at).is_some_and(|x| x.is_ascii_digit()) {
+                        i = digits_at;
+
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
                         }
-                        _ => Self::NetlistName(s.replace(";", "")),
-                    },
-                    None => Self::EndMarker,
-                };
-                res
+                    }
+                }
+
+                while i < chars.len() && chars[i].is_alphabetic() {
+                    i += 1;
+                }
+
+                let literal = chars[start..i].iter().collect::<String>();
+                let quantity = Quantity::from_literal(&literal).map_err(|message| format!("{} in expression", message))?;
+
+                tokens.push(ExpressionToken::Number(quantity));
             }
-            '-' => Self::Arg(Argument::from(s, line_number)),
-            '$' => match s.to_lowercase().contains("probe") {
-                true => Self::Pobe,
-                false => error_out!("Lexeme '{}' in line {}: it must be $PROBE", s, line_number),
-            },
-            '/' => Self::Comment,
-            _ => error_out!(
-                "Problem with lexeme '{}' in line {}: it's not a valid lexeme for Scheesim Netlist",
-                s,
-                line_number
-            ),
+            _ if ch.is_alphabetic() || ch == '_' => {
+                let start = i;
+
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+
+                tokens.push(ExpressionToken::Name(chars[start..i].iter().collect()));
+            }
+            _ => return Err(format!("Unexpected character '{}' in expression", ch)),
         }
     }
 
-    pub fn is_arg(&self) -> bool {
-        match self {
-            Self::Arg(_) => true,
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens of an expression, from the loosest binding operator to the
+/// tightest: `+ -`, `* /`, unary `-`, then `^` which groups to the right.
+struct ExpressionParser {
+    tokens: Vec<ExpressionToken>,
+    position: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&ExpressionToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<ExpressionToken> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        match self.peek() {
+            Some(ExpressionToken::Symbol(x)) if *x == symbol => {
+                self.position += 1;
+                true
+            }
             _ => false,
         }
     }
 
-    pub fn get_arg(&self) -> Option<Argument> {
-        match self {
-            Self::Arg(arg) => Some(arg.clone()),
-            _ => None,
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut expression = self.product()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(ExpressionToken::Symbol(x)) if *x == '+' || *x == '-' => *x,
+                _ => return Ok(expression),
+            };
+
+            self.position += 1;
+            expression = Expression::Binary(op, Box::new(expression), Box::new(self.product()?));
         }
     }
 
-    pub fn is_propub_fname(&self) -> bool {
-        match self {
-            Self::ProfileName(_) => true,
-            _ => false,
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(ExpressionToken::Symbol(x)) if *x == '*' || *x == '/' => *x,
+                _ => return Ok(expression),
+            };
+
+            self.position += 1;
+            expression = Expression::Binary(op, Box::new(expression), Box::new(self.unary()?));
         }
     }
 
-    pub fn get_propub_fname(&self) -> Option<String> {
-        match self {
-            Self::ProfileName(s) => Some(s.clone()),
-            _ => None,
+    fn unary(&mut self) -> Result<Expression, String> {
+        match () {
+            _ if self.eat('-') => Ok(Expression::Negate(Box::new(self.unary()?))),
+            _ if self.eat('+') => self.unary(),
+            _ => self.power(),
         }
     }
 
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.atom()?;
 
-    pub fn is_element(&self) -> bool {
-        match self {
-            Self::Element(_) => true,
-            _ => false,
+        match self.eat('^') {
+            true => Ok(Expression::Binary('^', Box::new(base), Box::new(self.unary()?))),
+            false => Ok(base),
         }
     }
 
-    pub fn get_element(&self) -> Option<ElementMarker> {
-        match self {
-            Self::Element(e) => Some(e.clone()),
-            _ => None,
+    fn atom(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(ExpressionToken::Number(x)) => Ok(Expression::Number(x)),
+            Some(ExpressionToken::Name(name)) => match self.eat('(') {
+                true => {
+                    if !Expression::FUNCTIONS.contains(&name.as_str()) {
+                        return Err(match suggest(&name, &Expression::FUNCTIONS) {
+                            Some(hint) => format!("Unknown function '{}', {}", name, hint),
+                            None => format!("Unknown function '{}'", name),
+                        });
+                    }
+
+                    let argument = self.sum()?;
+
+                    match self.eat(')') {
+                        true => Ok(Expression::Call(name, Box::new(argument))),
+                        false => Err(format!("Call to '{}' is never closed with )", name)),
+                    }
+                }
+                false => Ok(Expression::Name(name)),
+            },
+            Some(ExpressionToken::Symbol('(')) => {
+                let expression = self.sum()?;
+
+                match self.eat(')') {
+                    true => Ok(expression),
+                    false => Err("Parenthesis is never closed with )".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected '{}' in expression", token)),
+            None => Err("Expression ends too early".to_string()),
         }
     }
+}
 
+impl Expression {
+    pub const FUNCTIONS: [&'static str; 5] = ["sqrt", "exp", "log", "log10", "abs"];
 
-    pub fn is_nlname(&self) -> bool {
-        match self {
-            Self::NetlistName(_) => true,
-            _ => false,
+    pub fn from(s: &str) -> Result<Self, String> {
+        let mut parser = ExpressionParser {
+            tokens: tokenize_expression(s)?,
+            position: 0,
+        };
+
+        let expression = parser.sum()?;
+
+        match parser.peek() {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected '{}' in expression", token)),
         }
     }
 
-    pub fn get_nlname(&self) -> Option<String> {
-        match self {
-            Self::NetlistName(nl) => Some(nl.clone()),
-            _ => None,
-        }
+    /// Works out the value with `params` in scope. A parameter may itself be an expression over
+    /// other parameters, as long as it does not end up referring to itself.
+    pub fn evaluate(&self, params: &[(String, String)]) -> Result<Quantity, String> {
+        self.evaluate_with(params, &mut vec![])
     }
 
-    pub fn is_ndname(&self) -> bool {
+    fn evaluate_with(&self, params: &[(String, String)], stack: &mut Vec<String>) -> Result<Quantity, String> {
         match self {
-            Self::NodeName(_) => true,
-            _ => false,
+            Self::Number(x) => Ok(*x),
+            Self::Name(name) => match (find_param(params, name), name.as_str()) {
+                (Some(value), _) => {
+                    if stack.contains(name) {
+                        return Err(format!("Parameter '{}' is defined in terms of itself", name));
+                    }
+
+                    let value = value
+                        .strip_prefix('{')
+                        .and_then(|x| x.strip_suffix('}'))
+                        .unwrap_or(value);
+
+                    stack.push(name.clone());
+                    let evaluated = Self::from(value)
+                        .map_err(|message| format!("{} of parameter '{}'", message, name))
+                        .and_then(|x| x.evaluate_with(params, stack));
+                    stack.pop();
+
+                    evaluated
+                }
+                (None, "pi") => Ok(Quantity::from(std::f64::consts::PI, Dimension::NONE)),
+                (None, _) => Err(format!("Unknown parameter '{}'", name)),
+            },
+            Self::Negate(x) => {
+                let x = x.evaluate_with(params, stack)?;
+                Ok(Quantity::from(-x.get_value(), x.get_dimension()))
+            }
+            Self::Binary(op, a, b) => {
+                let (a, b) = (a.evaluate_with(params, stack)?, b.evaluate_with(params, stack)?);
+
+                match op {
+                    '+' => a.add(&b).map_err(|message| format!("{} in expression", message)),
+                    '-' => a.subtract(&b).map_err(|message| format!("{} in expression", message)),
+                    '*' => a.multiply(&b).map_err(|message| format!("{} in expression", message)),
+                    '/' if b.get_value() == 0.0 => Err("Division by zero in expression".to_string()),
+                    '/' => a.divide(&b).map_err(|message| format!("{} in expression", message)),
+                    _ => a.power(&b).map_err(|message| format!("{} in expression", message)),
+                }
+            }
+            Self::Call(function, x) => {
+                let x = x.evaluate_with(params, stack)?;
+                let (value, dimension) = (x.get_value(), x.get_dimension());
+
+                // Only roots and magnitudes make sense of a value with a unit.
+                if !dimension.is_none() && matches!(function.as_str(), "exp" | "log" | "log10") {
+                    return Err(format!("'{}' takes a plain number, not one in {}", function, dimension));
+                }
+
+                match function.as_str() {
+                    "sqrt" if value < 0.0 => Err("Square root of a negative number in expression".to_string()),
+                    "sqrt" => match dimension.root(2) {
+                        Some(root) => Ok(Quantity::from(value.sqrt(), root)),
+                        None => Err(format!("Square root of {} in expression", dimension)),
+                    },
+                    "exp" => Ok(Quantity::from(value.exp(), dimension)),
+                    "log" | "log10" if value <= 0.0 => Err(format!("Logarithm of {} in expression", value)),
+                    "log" => Ok(Quantity::from(value.ln(), dimension)),
+                    "log10" => Ok(Quantity::from(value.log10(), dimension)),
+                    _ => Ok(Quantity::from(value.abs(), dimension)),
+                }
+            }
         }
     }
+}
 
-    pub fn get_ndname(&self) -> Option<String> {
-        match self {
-            Self::NodeName(nd) => Some(nd.clone()),
-            _ => None,
+#[derive(Clone)]
+pub enum JunctionChannel {
+    NPN,
+    PNP,
+    NP,
+    PN,
+    N,
+    P,
+}
+
+impl JunctionChannel {
+    pub fn from(s: &str, line_number: usize) -> Result<Self, Diagnostic> {
+        match s.to_lowercase().as_str() {
+            "npn" => Ok(Self::NPN),
+            "pnp" => Ok(Self::PNP),
+            "np" => Ok(Self::NP),
+            "pn" => Ok(Self::PN),
+            "n" => Ok(Self::N),
+            "p" => Ok(Self::P),
+            _ => Err(Diagnostic::at_lexeme(s, line_number, "Wrong junction or channel".to_string())),
         }
     }
+}
 
-    pub fn is_probe(&self) -> bool {
-        match self {
-            Self::Pobe => true,
-            _ => false,
+#[derive(Clone)]
+pub enum Connection {
+    Serial(String),
+    Parallel(String),
+    Ground,
+    Next,
+    Prev,
+    Probe,
+}
+
+impl Connection {
+    pub fn from(s: &str, serial: bool) -> Self {
+        match s.to_lowercase().as_str() {
+            "prev" | "previous" => Self::Prev,
+            "next" => Self::Next,
+            "grnd" | "ground" => Self::Ground,
+            "$probe" => Self::Probe,
+            _ => match serial {
+                true => Self::Serial(s.to_string()),
+                false => Self::Parallel(s.to_string()),
+            }
         }
     }
+}
 
-    pub fn is_endmarker(&self) -> bool {
+impl fmt::Display for JunctionChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::EndMarker => true,
-            _ => false,
+            Self::NPN => write!(f, "npn"),
+            Self::PNP => write!(f, "pnp"),
+            Self::NP => write!(f, "np"),
+            Self::PN => write!(f, "pn"),
+            Self::N => write!(f, "n"),
+            Self::P => write!(f, "p"),
         }
     }
+}
 
-    pub fn is_comment(&self) -> bool {
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Comment => true,
-            _ => false,
+            Self::Serial(name) | Self::Parallel(name) => write!(f, "{}", name),
+            Self::Ground => write!(f, "ground"),
+            Self::Next => write!(f, "next"),
+            Self::Prev => write!(f, "prev"),
+            Self::Probe => write!(f, "$PROBE"),
         }
     }
 }
 
-struct LexemeLine {
-    lexemes: Vec<Lexeme>,
-    line_number: usize,
+#[derive(Clone)]
+pub enum Currentage {
+    Solo(Unit),
+    Dom(Unit),
+    Sub(Unit),
 }
 
-impl LexemeLine {
-    pub fn from(s: &str, line_number: usize) -> Self {
-        let lexemes = s.split_whitespace()
-        .map(|s| Lexeme::from(s, line_number))
-        .collect::<Vec<Lexeme>>();
+#[derive(Clone)]
+pub enum Argument {
+    Author(String),
+    Date(String),
+    Profile(String),
+    In(Connection),
+    Out(Connection),
+    Base(Connection),
+    Voltage(Currentage),
+    MaxVoltage(Unit),
+    Power(Unit),
+    Current(Currentage),
+    Inductance(Unit),
+    Capacitance(Unit),
+    Resistance(Unit),
+    Frequency(Unit),
+    SaturationCurrent(Unit),
+    Emission(Unit),
+    BreakdownVoltage(Unit),
+    TransitTime(Unit),
+    Beta(Unit),
+    BetaReverse(Unit),
+    EarlyVoltage(Unit),
+    EarlyVoltageReverse(Unit),
+    KneeCurrent(Unit),
+    LeakageCurrent(Unit),
+    EmitterCapacitance(Unit),
+    CollectorCapacitance(Unit),
+    JunctionChannel(JunctionChannel),
+    Dynamic,
+    Nonlinear,
+    Subcircuit,
+    Port(String),
+    Param(String, String),
+    Pin(String, Connection),
+    Instance(String),
+    Control(String),
+    Deferred(String, String),
+}
+
+impl Argument {
+    pub const FLAG_NAMES: [&'static str; 3] = ["-dynamic", "-nonlinear", "-subcircuit"];
+
+    pub const VALUED_NAMES: [&'static str; 41] = [
+        "-author",
+        "-date",
+        "-profile",
+        "-port",
+        "-param",
+        "-pin",
+        "-instance",
+        "-control",
+        "-in",
+        "-base",
+        "-out",
+        "-in*",
+        "-out*",
+        "-parallel",
+        "-junction",
+        "-channel",
+        "-voltage",
+        "-current",
+        "-voltage*",
+        "-current*",
+        "-voltage^",
+        "-current^",
+        "-max_voltage",
+        "-power",
+        "-inductance",
+        "-capacitance",
+        "-resistance",
+        "-frequency",
+        "-freq",
+        "-saturation_current",
+        "-emission",
+        "-breakdown_voltage",
+        "-transit_time",
+        "-beta",
+        "-beta_reverse",
+        "-early_voltage",
+        "-early_voltage_reverse",
+        "-knee_current",
+        "-leakage_current",
+        "-emitter_capacitance",
+        "-collector_capacitance",
+    ];
+
+    pub fn from(s: &str, line_number: usize) -> Result<Self, Diagnostic> {
+        let mut split_on_equal = s.splitn(2, '=');
+
+        let name = split_on_equal.next().unwrap().trim();
 
-        Self { lexemes, line_number }
+        match name.to_lowercase().as_str() {
+            "-dynamic" => Ok(Self::Dynamic),
+            "-nonlinear" => Ok(Self::Nonlinear),
+            "-subcircuit" => Ok(Self::Subcircuit),
+            _ => match split_on_equal.next() {
+                Some(v) => {
+                    if name == "-junction" || name == "-channel" {
+                        let junction_channel = JunctionChannel::from(v, line_number)
+                            .map_err(|_| Diagnostic::at_lexeme(s, line_number, "Wrong junction or channel".to_string()))?;
+                        return Ok(Self::JunctionChannel(junction_channel));
+                    }
+
+                    let value = v.trim().to_string();
+
+                    match name.to_lowercase().as_str() {
+                        "-author" => Ok(Self::Author(value)),
+                        "-date" => Ok(Self::Date(value)),
+                        "-profile" => Ok(Self::Profile(value)),
+                        "-in" => Ok(Self::In(Connection::from(&value, true))),
+                        "-base" => Ok(Self::Base(Connection::from(&value, true))),
+                        "-out" => Ok(Self::Out(Connection::from(&value, true))),
+                        "-in*" => Ok(Self::In(Connection::from(&value, false))),
+                        "-out*" | "-parallel" => Ok(Self::Out(Connection::from(&value, false))),
+                        "-port" => Ok(Self::Port(value)),
+                        "-instance" => Ok(Self::Instance(value)),
+                        "-control" => Ok(Self::Control(value)),
+                        "-param" => match value.split_once('=') {
+                            Some((param, param_value)) if !param.is_empty() && !param_value.is_empty() => {
+                                if let Some(expression) = param_value.strip_prefix('{').and_then(|x| x.strip_suffix('}')) {
+                                    Expression::from(expression)
+                                        .map_err(|message| Diagnostic::at_lexeme(s, line_number, message))?;
+                                }
+
+                                Ok(Self::Param(param.to_string(), param_value.to_string()))
+                            }
+                            _ => Err(Diagnostic::at_lexeme(
+                                s,
+                                line_number,
+                                "Parameters are given as -param=name=value".to_string(),
+                            )),
+                        },
+                        "-pin" => match value.split_once('=') {
+                            Some((port, node)) if !port.is_empty() && !node.is_empty() => {
+                                Ok(Self::Pin(port.to_string(), Connection::from(node, true)))
+                            }
+                            _ => Err(Diagnostic::at_lexeme(
+                                s,
+                                line_number,
+                                "Pins are given as -pin=port=node".to_string(),
+                            )),
+                        },
+                        _ if value.starts_with('{') => match value.ends_with('}') {
+                            true => {
+                                let expression = value[1..value.len() - 1].trim().to_string();
+                                Expression::from(&expression)
+                                    .map_err(|message| Diagnostic::at_lexeme(s, line_number, message))?;
+
+                                Ok(Self::Deferred(name.to_lowercase(), expression))
+                            }
+                            false => Err(Diagnostic::at_lexeme(
+                                s,
+                                line_number,
+                                "Expression is never closed with }".to_string(),
+                            )),
+                        },
+
+                        _ => {
+                            let value_unit = Unit::with_units(&value, line_number, Self::physical_units(name))
+                                .map_err(|e| Diagnostic::at_lexeme(s, line_number, e.get_message().to_string()))?;
+
+                            match name.to_lowercase().as_str() {
+                                "-voltage" => Ok(Self::Voltage(Currentage::Solo(value_unit))),
+                                "-current" => Ok(Self::Current(Currentage::Solo(value_unit))),
+                                "-voltage*" => Ok(Self::Voltage(Currentage::Dom(value_unit))),
+                                "-current*" => Ok(Self::Current(Currentage::Dom(value_unit))),
+                                "-voltage^" => Ok(Self::Voltage(Currentage::Sub(value_unit))),
+                                "-current^" => Ok(Self::Current(Currentage::Sub(value_unit))),
+                                "-max_voltage" => Ok(Self::MaxVoltage(value_unit)),
+                                "-power" => Ok(Self::Power(value_unit)),
+                                "-inductance" => Ok(Self::Inductance(value_unit)),
+                                "-capacitance" => Ok(Self::Capacitance(value_unit)),
+                                "-resistance" => Ok(Self::Resistance(value_unit)),
+                                "-frequency" | "-freq" => Ok(Self::Frequency(value_unit)),
+                                "-saturation_current" => Ok(Self::SaturationCurrent(value_unit)),
+                                "-emission" => Ok(Self::Emission(value_unit)),
+                                "-breakdown_voltage" => Ok(Self::BreakdownVoltage(value_unit)),
+                                "-transit_time" => Ok(Self::TransitTime(value_unit)),
+                                "-beta" => Ok(Self::Beta(value_unit)),
+                                "-beta_reverse" => Ok(Self::BetaReverse(value_unit)),
+                                "-early_voltage" => Ok(Self::EarlyVoltage(value_unit)),
+                                "-early_voltage_reverse" => Ok(Self::EarlyVoltageReverse(value_unit)),
+                                "-knee_current" => Ok(Self::KneeCurrent(value_unit)),
+                                "-leakage_current" => Ok(Self::LeakageCurrent(value_unit)),
+                                "-emitter_capacitance" => Ok(Self::EmitterCapacitance(value_unit)),
+                                "-collector_capacitance" => Ok(Self::CollectorCapacitance(value_unit)),
+                                _ => Err(Diagnostic::at_lexeme(s, line_number, "Wrong type of argument".to_string())
+                                    .with_hint(suggest(name, &Self::VALUED_NAMES))),
+                            }
+                        }
+                    }
+                }
+                None => Err(Diagnostic::at_lexeme(
+                    s,
+                    line_number,
+                    "Wrong argument given, must have value sperated by =".to_string(),
+                )
+                .with_hint(suggest(name, &Self::FLAG_NAMES))),
+            },
+        }
     }
 
-    pub fn count_arguments(&self) -> usize {
-        self.lexemes.iter().filter(|x| x.is_arg()).count()
+    /// The dimension of the values argument `name` takes, e.g. farads for `-capacitance`.
+    pub fn dimension(name: &str) -> Option<Dimension> {
+        match name.to_lowercase().trim_end_matches(['*', '^']) {
+            "-voltage" | "-max_voltage" | "-breakdown_voltage" | "-early_voltage" | "-early_voltage_reverse" => {
+                Some(Dimension::VOLT)
+            }
+            "-current" | "-saturation_current" | "-knee_current" | "-leakage_current" => Some(Dimension::AMPERE),
+            "-power" => Some(Dimension::WATT),
+            "-inductance" => Some(Dimension::HENRY),
+            "-capacitance" | "-emitter_capacitance" | "-collector_capacitance" => Some(Dimension::FARAD),
+            "-resistance" => Some(Dimension::OHM),
+            "-frequency" | "-freq" => Some(Dimension::HERTZ),
+            "-transit_time" => Some(Dimension::SECOND),
+            _ => None,
+        }
     }
 
-    pub fn get_args(&self) -> Vec<Argument> {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
-                |x| x.is_arg())
-                            .map(|x| x.clone().get_arg().unwrap()
-            )
-            .collect()
+    /// The physical units a value given to argument `name` may be written with, e.g. `F` for
+    /// `-capacitance`.
+    pub fn physical_units(name: &str) -> &'static [&'static str] {
+        Self::dimension(name).map_or(&[], |x| x.get_symbols())
     }
 
-    pub fn get_elements(&self) -> Vec<ElementMarker> {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
-                |x| x.is_element())
-                            .map(|x| x.clone().get_element().unwrap()
-            )
-            .collect()
+    /// The value of the argument with the dimension it is given in, `None` if it has no value.
+    pub fn get_quantity(&self) -> Option<Quantity> {
+        let (unit, dimension) = match self {
+            Self::Voltage(Currentage::Solo(unit) | Currentage::Dom(unit) | Currentage::Sub(unit)) => (unit, Dimension::VOLT),
+            Self::Current(Currentage::Solo(unit) | Currentage::Dom(unit) | Currentage::Sub(unit)) => (unit, Dimension::AMPERE),
+            Self::MaxVoltage(unit) => (unit, Dimension::VOLT),
+            Self::Power(unit) => (unit, Dimension::WATT),
+            Self::Inductance(unit) => (unit, Dimension::HENRY),
+            Self::Capacitance(unit) => (unit, Dimension::FARAD),
+            Self::Resistance(unit) => (unit, Dimension::OHM),
+            Self::Frequency(unit) => (unit, Dimension::HERTZ),
+            Self::SaturationCurrent(unit) => (unit, Dimension::AMPERE),
+            Self::Emission(unit) => (unit, Dimension::NONE),
+            Self::BreakdownVoltage(unit) => (unit, Dimension::VOLT),
+            Self::TransitTime(unit) => (unit, Dimension::SECOND),
+            Self::Beta(unit) => (unit, Dimension::NONE),
+            Self::BetaReverse(unit) => (unit, Dimension::NONE),
+            Self::EarlyVoltage(unit) => (unit, Dimension::VOLT),
+            Self::EarlyVoltageReverse(unit) => (unit, Dimension::VOLT),
+            Self::KneeCurrent(unit) => (unit, Dimension::AMPERE),
+            Self::LeakageCurrent(unit) => (unit, Dimension::AMPERE),
+            Self::EmitterCapacitance(unit) => (unit, Dimension::FARAD),
+            Self::CollectorCapacitance(unit) => (unit, Dimension::FARAD),
+            _ => return None,
+        };
+
+        Some(Quantity::from(unit.get_corresponding_value(), dimension))
     }
 
+    pub fn is_key(&self, key: &'static str) -> bool {
+        matches!(
+            (key, self),
+            ("author", Self::Author(_))
+                | ("date", Self::Date(_))
+                | ("profile", Self::Profile(_))
+                | ("in", Self::In(_))
+                | ("out", Self::Out(_))
+                | ("base", Self::Base(_))
+                | ("voltage", Self::Voltage(_))
+                | ("max_voltage", Self::MaxVoltage(_))
+                | ("power", Self::Power(_))
+                | ("current", Self::Current(_))
+                | ("resistance", Self::Resistance(_))
+                | ("capacitance", Self::Capacitance(_))
+                | ("inductance", Self::Inductance(_))
+                | ("frequency", Self::Frequency(_))
+                | ("saturation_current", Self::SaturationCurrent(_))
+                | ("emission", Self::Emission(_))
+                | ("breakdown_voltage", Self::BreakdownVoltage(_))
+                | ("transit_time", Self::TransitTime(_))
+                | ("beta", Self::Beta(_))
+                | ("beta_reverse", Self::BetaReverse(_))
+                | ("early_voltage", Self::EarlyVoltage(_))
+                | ("early_voltage_reverse", Self::EarlyVoltageReverse(_))
+                | ("knee_current", Self::KneeCurrent(_))
+                | ("leakage_current", Self::LeakageCurrent(_))
+                | ("emitter_capacitance", Self::EmitterCapacitance(_))
+                | ("collector_capacitance", Self::CollectorCapacitance(_))
+                | ("junction_channel", Self::JunctionChannel(_))
+                | ("dynamic", Self::Dynamic)
+                | ("nonlinear", Self::Nonlinear)
+                | ("subcircuit", Self::Subcircuit)
+                | ("port", Self::Port(_))
+                | ("param", Self::Param(_, _))
+                | ("pin", Self::Pin(_, _))
+                | ("instance", Self::Instance(_))
+                | ("control", Self::Control(_))
+                | ("deferred", Self::Deferred(_, _))
+        )
+    }
 
-    pub fn get_nlname(&self) -> Vec<String> {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
-                |x| x.is_nlname())
-                            .map(|x| x.clone().get_nlname().unwrap()
-            )
-            .collect()
+    pub fn is_identity(&self) -> bool {
+        self.is_key("author")
+            || self.is_key("date")
+            || self.is_key("profile")
+            || self.is_key("subcircuit")
+            || self.is_key("port")
+            || self.is_key("param")
+            || self.is_key("pin")
+            || self.is_key("instance")
     }
 
-    pub fn get_ndname(&self) -> Vec<String> {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
+    pub fn is_inout(&self) -> bool {
+        self.is_key("in") || self.is_key("out") || self.is_key("base")
+    }
+
+    pub fn is_flag(&self) -> bool {
+        self.is_key("dynamic") || self.is_key("nonlinear")
+    }
+}
+
+impl fmt::Display for Argument {
+    /// Writes the argument the way it is written in a netlist, e.g. `-resistance=4.7k`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Author(author) => write!(f, "-author={}", author),
+            Self::Date(date) => write!(f, "-date={}", date),
+            Self::Profile(profile) => write!(f, "-profile={}", profile),
+            Self::In(connection @ Connection::Parallel(_)) => write!(f, "-in*={}", connection),
+            Self::In(connection) => write!(f, "-in={}", connection),
+            Self::Out(connection @ Connection::Parallel(_)) => write!(f, "-out*={}", connection),
+            Self::Out(connection) => write!(f, "-out={}", connection),
+            Self::Base(connection) => write!(f, "-base={}", connection),
+            Self::Voltage(Currentage::Solo(unit)) => write!(f, "-voltage={}", unit),
+            Self::Voltage(Currentage::Dom(unit)) => write!(f, "-voltage*={}", unit),
+            Self::Voltage(Currentage::Sub(unit)) => write!(f, "-voltage^={}", unit),
+            Self::Current(Currentage::Solo(unit)) => write!(f, "-current={}", unit),
+            Self::Current(Currentage::Dom(unit)) => write!(f, "-current*={}", unit),
+            Self::Current(Currentage::Sub(unit)) => write!(f, "-current^={}", unit),
+            Self::MaxVoltage(unit) => write!(f, "-max_voltage={}", unit),
+            Self::Power(unit) => write!(f, "-power={}", unit),
+            Self::Inductance(unit) => write!(f, "-inductance={}", unit),
+            Self::Capacitance(unit) => write!(f, "-capacitance={}", unit),
+            Self::Resistance(unit) => write!(f, "-resistance={}", unit),
+            Self::Frequency(unit) => write!(f, "-frequency={}", unit),
+            Self::SaturationCurrent(unit) => write!(f, "-saturation_current={}", unit),
+            Self::Emission(unit) => write!(f, "-emission={}", unit),
+            Self::BreakdownVoltage(unit) => write!(f, "-breakdown_voltage={}", unit),
+            Self::TransitTime(unit) => write!(f, "-transit_time={}", unit),
+            Self::Beta(unit) => write!(f, "-beta={}", unit),
+            Self::BetaReverse(unit) => write!(f, "-beta_reverse={}", unit),
+            Self::EarlyVoltage(unit) => write!(f, "-early_voltage={}", unit),
+            Self::EarlyVoltageReverse(unit) => write!(f, "-early_voltage_reverse={}", unit),
+            Self::KneeCurrent(unit) => write!(f, "-knee_current={}", unit),
+            Self::LeakageCurrent(unit) => write!(f, "-leakage_current={}", unit),
+            Self::EmitterCapacitance(unit) => write!(f, "-emitter_capacitance={}", unit),
+            Self::CollectorCapacitance(unit) => write!(f, "-collector_capacitance={}", unit),
+            Self::JunctionChannel(channel @ (JunctionChannel::N | JunctionChannel::P)) => {
+                write!(f, "-channel={}", channel)
+            }
+            Self::JunctionChannel(junction) => write!(f, "-junction={}", junction),
+            Self::Dynamic => write!(f, "-dynamic"),
+            Self::Nonlinear => write!(f, "-nonlinear"),
+            Self::Subcircuit => write!(f, "-subcircuit"),
+            Self::Port(port) => write!(f, "-port={}", port),
+            Self::Param(name, value) => write!(f, "-param={}={}", name, value),
+            Self::Pin(port, connection) => write!(f, "-pin={}={}", port, connection),
+            Self::Instance(instance) => write!(f, "-instance={}", instance),
+            Self::Control(control) => write!(f, "-control={}", control),
+            Self::Deferred(name, expression) => write!(f, "{}={{{}}}", name, expression),
+        }
+    }
+}
+
+pub trait FilterArgList {
+    fn filter(&self, key: &'static str) -> Vec<Argument>;
+}
+
+impl FilterArgList for Vec<Argument> {
+    fn filter(&self, key: &'static str) -> Vec<Argument> {
+        self
+            .clone()
+            .into_iter()
+            .filter(|x| {
+                x.is_key(key)
+            })
+            .collect()
+    }
+}
+
+
+#[derive(Clone)]
+pub enum Lexeme {
+    NetlistName(String),
+    Element(ElementMarker),
+    NodeName(String),
+    ProfileName(String),
+    Arg(Argument),
+    Pobe,
+    EndMarker,
+    Comment,
+}
+
+impl Lexeme {
+    pub fn from(s: &str, line_number: usize) -> Result<Self, Diagnostic> {
+        let mut char_iter = s.chars();
+
+        match char_iter.next().unwrap() {
+            ';' => {
+                if s.chars().filter(|ch| *ch == ';').count() > 3 {
+                    return Err(Diagnostic::at_lexeme(
+                        s,
+                        line_number,
+                        "Wrong lexeme, cannot contain more than 3 semicolons".to_string(),
+                    ));
+                }
+
+                let res = match char_iter.next() {
+                    Some(ch) => match ch {
+                        ';' => match char_iter.next() {
+                            Some(ch) => match ch {
+                                ';' => Self::ProfileName(s.replace(";;;", "")),
+                                _ => Self::NodeName(s.replace(";;", "")),
+                            },
+                            None => return Err(Diagnostic::at_lexeme(
+                                s,
+                                line_number,
+                                "Semicolon marker cannot be left empty".to_string(),
+                            )),
+                        },
+                        _ => Self::NetlistName(s.replace(";", "")),
+                    },
+                    None => Self::EndMarker,
+                };
+                Ok(res)
+            }
+            '-' => Ok(Self::Arg(Argument::from(s, line_number)?)),
+            '.' => Ok(Self::Element(ElementMarker::from(s, line_number)?)),
+            '$' => match s.to_lowercase().contains("probe") {
+                true => Ok(Self::Pobe),
+                false => Err(Diagnostic::at_lexeme(s, line_number, "It must be $PROBE".to_string())),
+            },
+            '/' => Err(Diagnostic::at_lexeme(
+                s,
+                line_number,
+                "Not a valid lexeme, comments are written as // or /* */".to_string(),
+            )),
+            _ => Err(Diagnostic::at_lexeme(
+                s,
+                line_number,
+                "Not a valid lexeme for Scheesim Netlist".to_string(),
+            )),
+        }
+    }
+
+    pub fn is_arg(&self) -> bool {
+        matches!(self, Self::Arg(_))
+    }
+
+    pub fn get_arg(&self) -> Option<Argument> {
+        match self {
+            Self::Arg(arg) => Some(arg.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_propub_fname(&self) -> bool {
+        matches!(self, Self::ProfileName(_))
+    }
+
+    pub fn get_propub_fname(&self) -> Option<String> {
+        match self {
+            Self::ProfileName(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+
+    pub fn is_element(&self) -> bool {
+        matches!(self, Self::Element(_))
+    }
+
+    pub fn get_element(&self) -> Option<ElementMarker> {
+        match self {
+            Self::Element(e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+
+
+    pub fn is_nlname(&self) -> bool {
+        matches!(self, Self::NetlistName(_))
+    }
+
+    pub fn get_nlname(&self) -> Option<String> {
+        match self {
+            Self::NetlistName(nl) => Some(nl.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_ndname(&self) -> bool {
+        matches!(self, Self::NodeName(_))
+    }
+
+    pub fn get_ndname(&self) -> Option<String> {
+        match self {
+            Self::NodeName(nd) => Some(nd.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_probe(&self) -> bool {
+        matches!(self, Self::Pobe)
+    }
+
+    pub fn is_endmarker(&self) -> bool {
+        matches!(self, Self::EndMarker)
+    }
+
+    pub fn is_comment(&self) -> bool {
+        matches!(self, Self::Comment)
+    }
+}
+
+pub struct LexemeLine {
+    lexemes: Vec<Lexeme>,
+    sources: Vec<(Range<usize>, String)>,
+    line_number: usize,
+}
+
+impl LexemeLine {
+    /// Lexes a line, keeping every lexeme that could be lexed and returning the diagnostics
+    /// for the ones that couldn't.
+    pub fn from(s: &str, line_number: usize) -> (Self, Vec<Diagnostic>) {
+        let mut lexemes = vec![];
+        let mut sources = vec![];
+        let mut diagnostics = vec![];
+
+        for (column, word) in split_whitespace_columns(s) {
+            let word = word.trim_end_matches(',');
+
+            if word.is_empty() {
+                continue;
+            }
+
+            match Lexeme::from(word, line_number) {
+                Ok(lexeme) => {
+                    lexemes.push(lexeme);
+                    sources.push((column..column + word.chars().count(), word.to_string()));
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic.offset(column)),
+            }
+        }
+
+        (Self { lexemes, sources, line_number }, diagnostics)
+    }
+
+    pub fn empty(line_number: usize) -> Self {
+        Self { lexemes: vec![], sources: vec![], line_number }
+    }
+
+    /// Pushes a lexeme along with the columns and text it was lexed from.
+    pub fn push(&mut self, lexeme: Lexeme, source: (Range<usize>, String)) {
+        self.lexemes.push(lexeme);
+        self.sources.push(source);
+    }
+
+    pub fn replace(&mut self, n: usize, lexeme: Lexeme) {
+        self.lexemes[n] = lexeme;
+    }
+
+    pub fn get_source(&self, n: usize) -> Option<&(Range<usize>, String)> {
+        self.sources.get(n)
+    }
+
+    /// Makes a diagnostic pointing at the nth lexeme of this line.
+    pub fn diagnose(&self, n: usize, message: String) -> Diagnostic {
+        match self.sources.get(n) {
+            Some((span, word)) => Diagnostic::at_span(self.line_number, span.clone(), word, message),
+            None => Diagnostic::new(self.line_number, message),
+        }
+    }
+
+    pub fn get_lexemes(&self) -> &Vec<Lexeme> {
+        &self.lexemes
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+
+    pub fn first(&self) -> Option<&Lexeme> {
+        self.lexemes.first()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.lexemes.iter().all(|x| x.is_comment())
+    }
+
+    pub fn count_arguments(&self) -> usize {
+        self.lexemes.iter().filter(|x| x.is_arg()).count()
+    }
+
+    pub fn get_args(&self) -> Vec<Argument> {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
+                |x| x.is_arg())
+                            .map(|x| x.clone().get_arg().unwrap()
+            )
+            .collect()
+    }
+
+    pub fn get_elements(&self) -> Vec<ElementMarker> {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
+                |x| x.is_element())
+                            .map(|x| x.clone().get_element().unwrap()
+            )
+            .collect()
+    }
+
+
+    pub fn get_nlname(&self) -> Vec<String> {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
+                |x| x.is_nlname())
+                            .map(|x| x.clone().get_nlname().unwrap()
+            )
+            .collect()
+    }
+
+    pub fn get_ndname(&self) -> Vec<String> {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
                 |x| x.is_ndname())
                             .map(|x| x.clone().get_ndname().unwrap()
             )
@@ -664,425 +1711,3691 @@ impl LexemeLine {
         self.get_elements().len() == 1
     }
 
-    pub fn has_valid_arument(&self, num: usize) -> bool {
-        self.get_args().len() >= num
+    pub fn has_valid_arument(&self, num: usize) -> bool {
+        self.get_args().len() >= num
+    }
+
+    pub fn has_valid_nlnames(&self) -> bool {
+        self.get_nlname().len() == 1
+    }
+
+    pub fn has_valid_ndnames(&self) -> bool {
+        self.get_ndname().len() == 1
+    }
+
+    pub fn has_valid_propub_fnames(&self) -> bool {
+        self.get_propub_fnames().len() == 1
+    }
+
+    pub fn has_probe(&self) -> bool {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
+                |x| x.is_probe()
+            )
+            .count() > 0
+    }
+
+    pub fn has_endmarker(&self) -> bool {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
+                |x| x.is_endmarker()
+            )
+            .count() > 0
+    }
+
+    pub fn has_comment(&self) -> bool {
+        self.lexemes
+            .clone()
+            .into_iter()
+            .filter(
+                |x| x.is_comment()
+            )
+            .count() > 0
+    }
+}
+
+/// Splits a line on whitespace like `str::split_whitespace`, but also yields the column
+/// (in characters) each word starts at. Whitespace inside `{...}` does not split, so expressions
+/// can be spaced out.
+fn split_whitespace_columns(s: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start: Option<(usize, usize)> = None;
+    let mut depth = 0;
+
+    for (column, (i, ch)) in s.char_indices().enumerate() {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ => (),
+        }
+
+        match (ch.is_whitespace() && depth == 0, start) {
+            (true, Some((byte, col))) => {
+                words.push((col, &s[byte..i]));
+                start = None;
+            }
+            (false, None) => start = Some((i, column)),
+            _ => (),
+        }
+    }
+
+    if let Some((byte, col)) = start {
+        words.push((col, &s[byte..]));
+    }
+
+    words
+}
+
+/// Blanks out `//` line comments and `/* */` block comments, which may span several lines. A `//`
+/// only starts a comment where it starts a word, so one inside a value, e.g. a URL, is kept.
+/// Comments are replaced with spaces and their newlines are kept, so line numbers and columns
+/// still point at the right place in the original netlist.
+pub fn strip_comments(netlist: &str) -> (String, Vec<Diagnostic>) {
+    let mut lines = vec![];
+    let mut diagnostics = vec![];
+
+    let mut in_block = false;
+    let mut block_start: Option<(usize, usize)> = None;
+
+    for (i, line) in netlist.split('\n').enumerate() {
+        let (stripped, ends_in_block, opened) = strip_line_comments(line, in_block);
+
+        if let Some(column) = opened {
+            block_start = Some((i + 1, column));
+        }
+
+        lines.push(stripped);
+        in_block = ends_in_block;
+    }
+
+    if let (true, Some((line_number, column))) = (in_block, block_start) {
+        diagnostics.push(unclosed_block_comment(line_number, column));
+    }
+
+    (lines.join("\n"), diagnostics)
+}
+
+/// Blanks out the comments of a single line, `in_block` telling whether a block comment is still
+/// open where it starts. Returns the stripped line, whether a block comment is open where it ends
+/// and the column of the `/*` that opened it, if that is on this line.
+fn strip_line_comments(line: &str, in_block: bool) -> (String, bool, Option<usize>) {
+    let mut stripped = String::with_capacity(line.len());
+
+    let mut chars = line.chars().peekable();
+    let mut column = 0;
+    let mut block_start: Option<usize> = None;
+    let mut in_block = in_block;
+    let mut in_line_comment = false;
+
+    while let Some(ch) = chars.next() {
+        let next = chars.peek().cloned();
+
+        match (in_block, in_line_comment, ch, next) {
+            (true, _, '*', Some('/')) => {
+                chars.next();
+                stripped.push_str("  ");
+                in_block = false;
+                block_start = None;
+                column += 2;
+                continue;
+            }
+            (false, false, '/', Some('*')) => {
+                chars.next();
+                stripped.push_str("  ");
+                in_block = true;
+                block_start = Some(column);
+                column += 2;
+                continue;
+            }
+            (false, false, '/', Some('/')) if stripped.chars().last().is_none_or(char::is_whitespace) => {
+                in_line_comment = true;
+                stripped.push(' ');
+            }
+            (true, _, _, _) | (_, true, _, _) => stripped.push(match ch {
+                '\r' | '\t' => ch,
+                _ => ' ',
+            }),
+            _ => stripped.push(ch),
+        }
+
+        column += 1;
+    }
+
+    (stripped, in_block, block_start)
+}
+
+fn unclosed_block_comment(line_number: usize, column: usize) -> Diagnostic {
+    Diagnostic::at_span(line_number, column..column + 2, "/*", "Block comment is never closed with */".to_string())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TokenKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    Comma,
+    CircuitName,
+    NodeName,
+    ProfileName,
+    EndMarker,
+    Element,
+    Argument,
+    Probe,
+    Include,
+    Path,
+    Error,
+}
+
+/// A piece of a netlist exactly as it was written, whitespace and comments included, with where
+/// it starts. Lines are counted from 1 and columns in characters from 0, as in diagnostics.
+#[derive(Clone, Debug)]
+pub struct Token {
+    kind: TokenKind,
+    text: String,
+    span: Range<usize>,
+    line_number: usize,
+    column: usize,
+}
+
+impl Token {
+    pub fn get_kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// The byte offsets of the token in the netlist.
+    pub fn get_span(&self) -> &Range<usize> {
+        &self.span
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+
+    /// Whether the token only matters to the layout, i.e. whitespace, newlines, comments and the
+    /// commas the lexer skips.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Whitespace | TokenKind::Newline | TokenKind::LineComment | TokenKind::BlockComment | TokenKind::Comma
+        )
+    }
+
+    /// Lexes the token the way the parser sees it, `None` for trivia and `.include` directives.
+    pub fn to_lexeme(&self) -> Option<Result<Lexeme, Diagnostic>> {
+        match self.is_trivia() || matches!(self.kind, TokenKind::Include | TokenKind::Path) {
+            true => None,
+            false => Some(
+                Lexeme::from(&self.text, self.line_number).map_err(|x| x.offset(self.column)),
+            ),
+        }
+    }
+}
+
+/// Splits a netlist into tokens that add up to it byte for byte. Words are cut where
+/// `LexemeLine` cuts them, comments where `strip_comments` finds them.
+fn tokenize_lossless(netlist: &str) -> Vec<Token> {
+    let chars = netlist.char_indices().collect::<Vec<(usize, char)>>();
+    let byte_at = |i: usize| chars.get(i).map_or(netlist.len(), |(byte, _)| *byte);
+    let mut tokens: Vec<Token> = vec![];
+    let (mut line_number, mut column) = (1, 0);
+    let mut first_word = true;
+    let mut in_include = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i].1;
+        let next = chars.get(i + 1).map(|(_, x)| *x);
+        let start = i;
+
+        let kind = match (ch, next) {
+            ('\n', _) => {
+                i += 1;
+                TokenKind::Newline
+            }
+            ('\r', Some('\n')) => {
+                i += 2;
+                TokenKind::Newline
+            }
+            ('/', Some('/')) => {
+                while i < chars.len() && chars[i].1 != '\n' && !(chars[i].1 == '\r' && chars.get(i + 1).map(|x| x.1) == Some('\n')) {
+                    i += 1;
+                }
+
+                TokenKind::LineComment
+            }
+            ('/', Some('*')) => {
+                i += 2;
+
+                while i < chars.len() && !(chars[i].1 == '*' && chars.get(i + 1).map(|x| x.1) == Some('/')) {
+                    i += 1;
+                }
+
+                i = (i + 2).min(chars.len());
+                TokenKind::BlockComment
+            }
+            _ if ch.is_whitespace() => {
+                while i < chars.len() && chars[i].1.is_whitespace() && chars[i].1 != '\n'
+                    && !(chars[i].1 == '\r' && chars.get(i + 1).map(|x| x.1) == Some('\n'))
+                {
+                    i += 1;
+                }
+
+                TokenKind::Whitespace
+            }
+            _ => {
+                let mut depth = 0;
+
+                while i < chars.len() {
+                    let (ch, next) = (chars[i].1, chars.get(i + 1).map(|x| x.1));
+
+                    match ch {
+                        '\n' => break,
+                        '/' if next == Some('*') => break,
+                        _ if ch.is_whitespace() && depth == 0 => break,
+                        '{' => depth += 1,
+                        '}' if depth > 0 => depth -= 1,
+                        _ => (),
+                    }
+
+                    i += 1;
+                }
+
+                // Trailing commas are skipped by the lexer, so they get a token of their own.
+                let mut end = i;
+
+                while end > start && chars[end - 1].1 == ',' {
+                    end -= 1;
+                }
+
+                match end {
+                    _ if end == start => TokenKind::Comma,
+                    _ => {
+                        let word = &netlist[byte_at(start)..byte_at(end)];
+
+                        let kind = match (first_word, in_include) {
+                            (true, _) if word == ".include" => {
+                                in_include = true;
+                                TokenKind::Include
+                            }
+                            (_, true) => TokenKind::Path,
+                            _ => match Lexeme::from(word, line_number) {
+                                Ok(Lexeme::NetlistName(_)) => TokenKind::CircuitName,
+                                Ok(Lexeme::NodeName(_)) => TokenKind::NodeName,
+                                Ok(Lexeme::ProfileName(_)) => TokenKind::ProfileName,
+                                Ok(Lexeme::EndMarker) => TokenKind::EndMarker,
+                                Ok(Lexeme::Element(_)) => TokenKind::Element,
+                                Ok(Lexeme::Arg(_)) => TokenKind::Argument,
+                                Ok(Lexeme::Pobe) => TokenKind::Probe,
+                                Ok(Lexeme::Comment) | Err(_) => TokenKind::Error,
+                            },
+                        };
+
+                        first_word = false;
+
+                        // The commas go back to be read as a token of their own.
+                        i = end;
+                        kind
+                    }
+                }
+            }
+        };
+
+        let text = netlist[byte_at(start)..byte_at(i)].to_string();
+        tokens.push(Token { kind, text: text.clone(), span: byte_at(start)..byte_at(i), line_number, column });
+
+        for ch in text.chars() {
+            match ch {
+                '\n' => {
+                    line_number += 1;
+                    column = 0;
+                }
+                _ => column += 1,
+            }
+        }
+
+        if kind == TokenKind::Newline {
+            first_word = true;
+            in_include = false;
+        }
+    }
+
+    tokens
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyntaxKind {
+    Netlist,
+    Circuit,
+    Node,
+    Profile,
+    Include,
+}
+
+#[derive(Clone, Debug)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+/// A lossless concrete syntax tree of a netlist. Circuits hold their nodes, nodes their
+/// profiles, and every token of the netlist sits somewhere in it, so writing the tree back out
+/// gives the netlist byte for byte. Lines that belong nowhere in particular, like blank ones,
+/// comments and includes, go to whatever is open at the time.
+///
+/// ```text
+/// Netlist
+///   Circuit    ;lowPassFilter -profile=prof_ac ⏎
+///     Node     ;;cap1 -in=prev -out=next ⏎
+///       Profile  ;;;default .capacitor -capacitance=33u ⏎
+///     ;⏎
+/// ```
+#[derive(Clone, Debug)]
+pub struct SyntaxNode {
+    kind: SyntaxKind,
+    children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    /// Builds the tree of `netlist`, which never fails: whatever doesn't lex is kept as an
+    /// `Error` token and the parser reports it.
+    pub fn from(netlist: &str) -> Self {
+        let mut root = Self { kind: SyntaxKind::Netlist, children: vec![] };
+        // The open nodes below the root, from the outermost in.
+        let mut open: Vec<Self> = vec![];
+        let mut line: Vec<Token> = vec![];
+
+        let mut tokens = tokenize_lossless(netlist).into_iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            let ends_line = token.kind == TokenKind::Newline || tokens.peek().is_none();
+            line.push(token);
+
+            if !ends_line {
+                continue;
+            }
+
+            let first = line.iter().find(|x| !x.is_trivia()).map(|x| x.kind);
+            let elements = line.drain(..).map(SyntaxElement::Token).collect::<Vec<SyntaxElement>>();
+
+            // How many open nodes the line closes, and what it opens.
+            let (keep, opens) = match first {
+                Some(TokenKind::CircuitName) => (0, Some(SyntaxKind::Circuit)),
+                Some(TokenKind::NodeName) => (1, Some(SyntaxKind::Node)),
+                Some(TokenKind::ProfileName) => (2, Some(SyntaxKind::Profile)),
+                Some(TokenKind::Include) => (open.len(), Some(SyntaxKind::Include)),
+                Some(TokenKind::EndMarker) => (1, None),
+                _ => (open.len(), None),
+            };
+
+            while open.len() > keep.min(open.len()) {
+                let closed = open.pop().unwrap();
+                Self::attach(&mut root, &mut open, SyntaxElement::Node(closed));
+            }
+
+            match opens {
+                Some(SyntaxKind::Include) => {
+                    let include = Self { kind: SyntaxKind::Include, children: elements };
+                    Self::attach(&mut root, &mut open, SyntaxElement::Node(include));
+                }
+                Some(kind) => open.push(Self { kind, children: elements }),
+                None => {
+                    for element in elements {
+                        Self::attach(&mut root, &mut open, element);
+                    }
+
+                    // The end marker line belongs to the circuit it ends.
+                    if first == Some(TokenKind::EndMarker) {
+                        while let Some(closed) = open.pop() {
+                            Self::attach(&mut root, &mut open, SyntaxElement::Node(closed));
+                        }
+                    }
+                }
+            }
+        }
+
+        while let Some(closed) = open.pop() {
+            Self::attach(&mut root, &mut open, SyntaxElement::Node(closed));
+        }
+
+        root
+    }
+
+    /// Adds an element to the innermost open node, or the root if none is open.
+    fn attach(root: &mut Self, open: &mut [Self], element: SyntaxElement) {
+        match open.last_mut() {
+            Some(node) => node.children.push(element),
+            None => root.children.push(element),
+        }
+    }
+
+    pub fn get_kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn get_children(&self) -> &Vec<SyntaxElement> {
+        &self.children
+    }
+
+    /// Every token under the node, in order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![];
+
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+
+        tokens
+    }
+
+    /// The nodes right under this one, e.g. the circuits of a netlist.
+    pub fn nodes(&self) -> Vec<&SyntaxNode> {
+        self.children
+            .iter()
+            .filter_map(|x| match x {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// The name the node is declared with, e.g. `cap1` for `;;cap1 -in=prev`.
+    pub fn get_name(&self) -> Option<String> {
+        let token = self.children.iter().find_map(|x| match x {
+            SyntaxElement::Token(token) if !token.is_trivia() => Some(token),
+            _ => None,
+        })?;
+
+        match token.kind {
+            TokenKind::CircuitName | TokenKind::NodeName | TokenKind::ProfileName => {
+                Some(token.text.trim_start_matches(';').to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// The byte offsets the node covers in the netlist.
+    pub fn get_span(&self) -> Range<usize> {
+        let tokens = self.tokens();
+
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span.start..last.span.end,
+            _ => 0..0,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    /// Writes the tokens back out, which gives the netlist the tree was built from.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            write!(f, "{}", token.text)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_netlist(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Reading file '{}' to string: {}", path.display(), e))
+}
+
+/// A netlist with every `.include` directive replaced by the file it names, remembering which
+/// file and line each line of the spliced text came from so diagnostics can point back there.
+///
+/// ```text
+/// .include lib/sources.schnl
+/// ```
+///
+/// Paths are resolved relative to the including file. A file is only spliced in the first time
+/// it is included, and a file that ends up including itself is reported.
+pub struct SourceMap {
+    text: String,
+    files: Vec<(String, PathBuf, String)>,
+    origins: Vec<(usize, usize)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl SourceMap {
+    pub fn from_file(fp: &str) -> Result<Self, Diagnostic> {
+        let source = read_netlist(Path::new(fp)).map_err(|message| Diagnostic::new(0, message))?;
+
+        Ok(Self::from(&source, fp))
+    }
+
+    /// Splices the includes of a netlist that is already in memory, `fp` being where it would be
+    /// read from.
+    pub fn from(netlist: &str, fp: &str) -> Self {
+        let mut source_map = Self { text: String::new(), files: vec![], origins: vec![], diagnostics: vec![] };
+        source_map.splice(Path::new(fp), netlist.to_string(), &mut vec![]);
+
+        source_map
+    }
+
+    fn splice(&mut self, path: &Path, source: String, stack: &mut Vec<PathBuf>) {
+        let name = path.display().to_string();
+        let canonical = path.canonicalize().unwrap_or(path.to_path_buf());
+        let file = self.files.len();
+
+        // Comments go first, so an include that is commented out stays out.
+        let (stripped, diagnostics) = strip_comments(&source);
+        self.diagnostics.extend(diagnostics.into_iter().map(|x| x.in_file(&name)));
+        self.files.push((name.clone(), canonical.clone(), source));
+        stack.push(canonical);
+
+        for (i, line) in stripped.lines().enumerate() {
+            let words = split_whitespace_columns(line);
+
+            match words.first() {
+                Some((column, directive)) if *directive == ".include" => match words.get(1) {
+                    Some((column, target)) => {
+                        let target_path = path.parent().unwrap_or(Path::new("")).join(target.trim_matches('"'));
+                        let target_canonical = target_path.canonicalize().unwrap_or(target_path.clone());
+                        let span = *column..(column + target.chars().count());
+
+                        if words.len() > 2 {
+                            self.diagnostics.push(
+                                Diagnostic::at_span(i + 1, span.clone(), target, "An include takes a single path".to_string())
+                                    .in_file(&name),
+                            );
+                        }
+
+                        if let Some(k) = stack.iter().position(|x| *x == target_canonical) {
+                            let chain = self.files
+                                .iter()
+                                .filter(|(_, canonical, _)| stack[k..].contains(canonical))
+                                .map(|(name, _, _)| name.clone())
+                                .collect::<Vec<String>>();
+
+                            self.diagnostics.push(
+                                Diagnostic::at_span(
+                                    i + 1,
+                                    span,
+                                    target,
+                                    format!("Netlist '{}' includes itself through {}", target.trim_matches('"'), chain.join(" -> ")),
+                                )
+                                .in_file(&name),
+                            );
+                        } else if !self.files.iter().any(|(_, canonical, _)| *canonical == target_canonical) {
+                            match read_netlist(&target_path) {
+                                Ok(source) => self.splice(&target_path, source, stack),
+                                Err(message) => self.diagnostics.push(
+                                    Diagnostic::at_span(i + 1, span, target, message).in_file(&name),
+                                ),
+                            }
+                        }
+                    }
+                    None => self.diagnostics.push(
+                        Diagnostic::at_span(
+                            i + 1,
+                            *column..(column + directive.len()),
+                            directive,
+                            "An include needs the path of a netlist".to_string(),
+                        )
+                        .in_file(&name),
+                    ),
+                },
+                _ => {
+                    self.text.push_str(line);
+                    self.text.push('\n');
+                    self.origins.push((file, i + 1));
+                }
+            }
+        }
+
+        stack.pop();
+    }
+
+    /// The netlist with its includes spliced in, ready for `parse`.
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Problems with the includes themselves, such as missing files or cycles.
+    pub fn get_diagnostics(&self) -> &Vec<Diagnostic> {
+        &self.diagnostics
+    }
+
+    /// Points a diagnostic about the spliced text back at the file and line it came from.
+    pub fn locate(&self, diagnostic: &Diagnostic) -> Diagnostic {
+        let mut located = diagnostic.clone();
+
+        if located.data.file.is_none() {
+            let (file, line_number) = match located.data.line_number {
+                0 => (0, 0),
+                n => self.origins.get(n - 1).cloned().unwrap_or((0, 0)),
+            };
+
+            located.data.line_number = line_number;
+            located.data.file = self.files.get(file).map(|(name, _, _)| name.clone());
+        }
+
+        located
+    }
+
+    /// Renders every diagnostic of `error` with the snippet of the file it points into.
+    pub fn render(&self, error: &ParseError) -> String {
+        error
+            .get_diagnostics()
+            .iter()
+            .map(|x| {
+                let located = self.locate(x);
+                let source = self
+                    .files
+                    .iter()
+                    .find(|(name, _, _)| Some(name) == located.get_file())
+                    .map_or("", |(_, _, source)| source.as_str());
+
+                located.render(source)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl IntoIterator for LexemeLine {
+    type Item = Lexeme;
+
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.lexemes.into_iter()
+    }
+}
+
+impl Clone for LexemeLine {
+    fn clone(&self) -> Self {
+        Self {
+            lexemes: self.lexemes.clone(),
+            sources: self.sources.clone(),
+            line_number: self.line_number,
+        }
+    }
+}
+
+
+pub struct Netlist {
+    lines: Vec<LexemeLine>,
+    current_line: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Netlist {
+    pub fn from(netlist: &str) -> Self {
+        let mut lines = vec![];
+        let (netlist, mut diagnostics) = strip_comments(netlist);
+
+        for (i, s) in netlist.lines().enumerate() {
+            let (lexeme_line, line_diagnostics) = LexemeLine::from(s, i + 1);
+
+            lines.push(lexeme_line);
+            diagnostics.extend(line_diagnostics);
+        }
+
+        Self { lines, current_line: 0, diagnostics }
+    }
+
+    /// Reads a netlist with its `.include` directives spliced in. The lines are numbered as in
+    /// the spliced text, but the diagnostics point back at the files they came from.
+    pub fn from_file(fp: &str) -> Result<Self, Diagnostic> {
+        let source_map = SourceMap::from_file(fp)?;
+        let mut netlist = Self::from(source_map.get_text());
+
+        netlist.diagnostics = source_map
+            .get_diagnostics()
+            .iter()
+            .cloned()
+            .chain(netlist.diagnostics.iter().map(|x| source_map.locate(x)))
+            .collect();
+
+        Ok(netlist)
+    }
+
+    /// The diagnostics gathered while lexing the lines of this netlist.
+    pub fn get_diagnostics(&self) -> &Vec<Diagnostic> {
+        &self.diagnostics
+    }
+
+    pub fn advance(&mut self) {
+        self.current_line += 1;
+    }
+
+    pub fn get_at(&self, index: usize) -> Option<LexemeLine> {
+        self.lines.get(index).cloned()
+    }
+
+    pub fn get_curr(&self) -> Option<LexemeLine> {
+        self.get_at(self.current_line)
+    }
+
+    pub fn get_curr_and_advance(&mut self) -> Option<LexemeLine> {
+        let curr = self.get_curr();
+        self.advance();
+
+        curr
+    }
+
+    /// Returns the next line that holds something other than comments, skipping blank ones.
+    pub fn get_next_significant(&mut self) -> Option<LexemeLine> {
+        loop {
+            match self.get_curr_and_advance() {
+                Some(lexeme_line) => match lexeme_line.is_blank() {
+                    true => continue,
+                    false => return Some(lexeme_line),
+                },
+                None => return None,
+            }
+        }
+    }
+}
+
+
+impl Clone for Netlist {
+    fn clone(&self) -> Self {
+        Self {
+            lines: self.lines.clone(),
+            current_line: self.current_line,
+            diagnostics: self.diagnostics.clone(),
+        }
+    }
+}
+
+/// A component of a node profile, holding the argument lexemes that were given to the element.
+/// The actual models are built from these in scheesim-mna.
+#[derive(Clone)]
+pub enum EelectroCircuitComponent {
+    Resistor(LexemeLine),
+    Capacitor(LexemeLine),
+    Inductor(LexemeLine),
+    Transistor(LexemeLine),
+    Diode(LexemeLine),
+    ACSweep(LexemeLine),
+    DCSource(LexemeLine),
+    Init,
+}
+
+impl EelectroCircuitComponent {
+    pub fn from(element: ElementMarker, line_number: usize) -> Self {
+        let arguments = LexemeLine::empty(line_number);
+
+        match element {
+            ElementMarker::ACSweep => Self::ACSweep(arguments),
+            ElementMarker::DCSource => Self::DCSource(arguments),
+            ElementMarker::Resistor => Self::Resistor(arguments),
+            ElementMarker::Capacitor => Self::Capacitor(arguments),
+            ElementMarker::Inductor => Self::Inductor(arguments),
+            ElementMarker::Transistor => Self::Transistor(arguments),
+            ElementMarker::Diode => Self::Diode(arguments),
+        }
+    }
+
+    pub fn get_arguments(&self) -> Option<&LexemeLine> {
+        match self {
+            Self::Resistor(ll) => Some(ll),
+            Self::Capacitor(ll) => Some(ll),
+            Self::Inductor(ll) => Some(ll),
+            Self::Transistor(ll) => Some(ll),
+            Self::Diode(ll) => Some(ll),
+            Self::ACSweep(ll) => Some(ll),
+            Self::DCSource(ll) => Some(ll),
+            Self::Init => None,
+        }
+    }
+
+    pub fn push_argument(&mut self, argument: Argument, source: (Range<usize>, String)) {
+        match self {
+            Self::Resistor(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::Capacitor(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::Inductor(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::Transistor(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::Diode(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::ACSweep(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::DCSource(ll) => ll.push(Lexeme::Arg(argument), source),
+            Self::Init => (),
+        }
+    }
+
+    pub fn get_arguments_mut(&mut self) -> Option<&mut LexemeLine> {
+        match self {
+            Self::Resistor(ll) => Some(ll),
+            Self::Capacitor(ll) => Some(ll),
+            Self::Inductor(ll) => Some(ll),
+            Self::Transistor(ll) => Some(ll),
+            Self::Diode(ll) => Some(ll),
+            Self::ACSweep(ll) => Some(ll),
+            Self::DCSource(ll) => Some(ll),
+            Self::Init => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum ConnectionType {
+    Named(String),
+    Probe,
+    Ground,
+    Next,
+    Previous,
+    Init,
+}
+
+impl ConnectionType {
+    pub fn from(connection: Connection) -> Self {
+        match connection {
+            Connection::Serial(name) | Connection::Parallel(name) => Self::Named(name),
+            Connection::Ground => Self::Ground,
+            Connection::Next => Self::Next,
+            Connection::Prev => Self::Previous,
+            Connection::Probe => Self::Probe,
+        }
+    }
+}
+
+/// The terminals of a node that a connection can be made from.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Terminal {
+    In,
+    Out,
+    Base,
+}
+
+#[derive(Clone)]
+pub struct ElectoCircuitConnection {
+    serial_in: ConnectionType,
+    serial_out: ConnectionType,
+    serial_base: Option<ConnectionType>,
+    parallel_in: Vec<ConnectionType>,
+    parallel_out: Vec<ConnectionType>,
+}
+
+impl ElectoCircuitConnection {
+    pub fn init() -> Self {
+        Self {
+            serial_in: ConnectionType::Init,
+            serial_out: ConnectionType::Init,
+            serial_base: None,
+            parallel_in: vec![],
+            parallel_out: vec![],
+        }
+    }
+
+    pub fn modify_serial_in(&mut self, serial: ConnectionType) {
+        self.serial_in = serial;
+    }
+
+    pub fn modify_serial_out(&mut self, serial: ConnectionType) {
+        self.serial_out = serial;
+    }
+
+    pub fn modify_serial_base(&mut self, serial: ConnectionType) {
+        self.serial_base = Some(serial);
+    }
+
+    pub fn add_parallel_in(&mut self, parallel: ConnectionType) {
+        self.parallel_in.push(parallel);
+    }
+
+    pub fn add_parallel_out(&mut self, parallel: ConnectionType) {
+        self.parallel_out.push(parallel);
+    }
+
+    pub fn get_serial_in(&self) -> &ConnectionType {
+        &self.serial_in
+    }
+
+    pub fn get_serial_out(&self) -> &ConnectionType {
+        &self.serial_out
+    }
+
+    pub fn get_serial_base(&self) -> Option<&ConnectionType> {
+        self.serial_base.as_ref()
+    }
+
+    pub fn get_parallel_in(&self) -> &Vec<ConnectionType> {
+        &self.parallel_in
+    }
+
+    pub fn get_parallel_out(&self) -> &Vec<ConnectionType> {
+        &self.parallel_out
+    }
+
+    /// Calls `f` on every connection along with the terminal it was made from.
+    pub fn for_each<F: FnMut(Terminal, &ConnectionType)>(&self, mut f: F) {
+        f(Terminal::In, &self.serial_in);
+        f(Terminal::Out, &self.serial_out);
+
+        if let Some(base) = self.serial_base.as_ref() {
+            f(Terminal::Base, base);
+        }
+
+        self.parallel_in.iter().for_each(|x| f(Terminal::In, x));
+        self.parallel_out.iter().for_each(|x| f(Terminal::Out, x));
+    }
+
+    pub fn for_each_mut<F: FnMut(Terminal, &mut ConnectionType)>(&mut self, mut f: F) {
+        f(Terminal::In, &mut self.serial_in);
+        f(Terminal::Out, &mut self.serial_out);
+
+        if let Some(base) = self.serial_base.as_mut() {
+            f(Terminal::Base, base);
+        }
+
+        self.parallel_in.iter_mut().for_each(|x| f(Terminal::In, x));
+        self.parallel_out.iter_mut().for_each(|x| f(Terminal::Out, x));
+    }
+}
+
+#[derive(Clone)]
+pub struct ElectroCircuitNodeProfile {
+    name: String,
+    components: Vec<EelectroCircuitComponent>,
+    line_number: usize,
+}
+
+impl ElectroCircuitNodeProfile {
+    pub fn init() -> Self {
+        Self { name: String::new(), components: vec![], line_number: 0 }
+    }
+
+    pub fn modify_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn modify_line_number(&mut self, line_number: usize) {
+        self.line_number = line_number;
+    }
+
+    pub fn init_components(&mut self, num: usize) {
+        self.components = make_vec!(EelectroCircuitComponent $ EelectroCircuitComponent::Init $ num);
+    }
+
+    pub fn set_nth_components(&mut self, component: EelectroCircuitComponent, n: usize) {
+        self.components[n] = component;
+    }
+
+    pub fn get_nth_component_mut(&mut self, n: usize) -> Option<&mut EelectroCircuitComponent> {
+        self.components.get_mut(n)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_components(&self) -> &Vec<EelectroCircuitComponent> {
+        &self.components
+    }
+
+    pub fn get_components_mut(&mut self) -> &mut Vec<EelectroCircuitComponent> {
+        &mut self.components
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+}
+
+#[derive(Clone)]
+pub struct ElectroCircuitNode {
+    name: String,
+    profiles: Vec<ElectroCircuitNodeProfile>,
+    connections: ElectoCircuitConnection,
+    instance: Option<String>,
+    params: Vec<(String, String)>,
+    pins: Vec<(String, ConnectionType)>,
+    line_number: usize,
+}
+
+impl ElectroCircuitNode {
+    pub fn init() -> Self {
+        Self {
+            name: String::new(),
+            profiles: vec![],
+            connections: ElectoCircuitConnection::init(),
+            instance: None,
+            params: vec![],
+            pins: vec![],
+            line_number: 0,
+         }
+    }
+
+    pub fn modify_instance(&mut self, instance: String) {
+        self.instance = Some(instance);
+    }
+
+    pub fn add_param(&mut self, name: String, value: String) {
+        self.params.push((name, value));
+    }
+
+    pub fn add_pin(&mut self, port: String, connection: ConnectionType) {
+        self.pins.push((port, connection));
+    }
+
+    pub fn modify_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn modify_line_number(&mut self, line_number: usize) {
+        self.line_number = line_number;
+    }
+
+    pub fn add_profile(&mut self, profile: ElectroCircuitNodeProfile) {
+        self.profiles.push(profile);
+    }
+
+    pub fn get_connections_mut(&mut self) -> &mut ElectoCircuitConnection {
+        &mut self.connections
+    }
+
+    pub fn get_last_profile_mut(&mut self) -> Option<&mut ElectroCircuitNodeProfile> {
+        self.profiles.last_mut()
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_profiles(&self) -> &Vec<ElectroCircuitNodeProfile> {
+        &self.profiles
+    }
+
+    /// The node profile named `profile`, or the `default` one when the node doesn't have it.
+    pub fn select_profile(&self, profile: &str) -> Option<&ElectroCircuitNodeProfile> {
+        self.profiles
+            .iter()
+            .find(|x| x.name == profile)
+            .or(self.profiles.iter().find(|x| x.name == "default"))
+    }
+
+    pub fn get_connections(&self) -> &ElectoCircuitConnection {
+        &self.connections
+    }
+
+    /// The subcircuit this node is an instance of, if it is one.
+    pub fn get_instance(&self) -> Option<&String> {
+        self.instance.as_ref()
+    }
+
+    pub fn get_params(&self) -> &Vec<(String, String)> {
+        &self.params
+    }
+
+    /// The ports of its subcircuit an instance connects by name, with `-pin=port=node`.
+    pub fn get_pins(&self) -> &Vec<(String, ConnectionType)> {
+        &self.pins
+    }
+
+    pub fn get_pins_mut(&mut self) -> &mut Vec<(String, ConnectionType)> {
+        &mut self.pins
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+}
+
+#[derive(Clone)]
+pub struct ElectroCircuit {
+    name: String,
+    author: Option<String>,
+    date: Option<String>,
+    profiles: Vec<String>,
+    subcircuit: bool,
+    ports: Vec<String>,
+    params: Vec<(String, String)>,
+    nodes: Vec<ElectroCircuitNode>,
+    line_number: usize,
+}
+
+impl Default for ElectroCircuit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ElectroCircuit {
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            author: None,
+            date: None,
+            profiles: vec![],
+            subcircuit: false,
+            ports: vec![],
+            params: vec![],
+            nodes: vec![],
+            line_number: 0,
+        }
+    }
+
+    pub fn modify_subcircuit(&mut self, subcircuit: bool) {
+        self.subcircuit = subcircuit;
+    }
+
+    pub fn add_port(&mut self, port: String) {
+        self.ports.push(port);
+    }
+
+    pub fn add_param(&mut self, name: String, value: String) {
+        self.params.push((name, value));
+    }
+
+    pub fn modify_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn modify_author(&mut self, author: String) {
+        self.author = Some(author);
+    }
+
+    pub fn modify_date(&mut self, date: String) {
+        self.date = Some(date)
+    }
+
+    pub fn modify_line_number(&mut self, line_number: usize) {
+        self.line_number = line_number;
+    }
+
+    pub fn add_profile(&mut self, profile: String) {
+        self.profiles.push(profile);
+    }
+
+    pub fn add_node(&mut self, node: ElectroCircuitNode) {
+        self.nodes.push(node);
+    }
+
+    pub fn get_last_node_mut(&mut self) -> Option<&mut ElectroCircuitNode> {
+        self.nodes.last_mut()
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_author(&self) -> Option<&String> {
+        self.author.as_ref()
+    }
+
+    pub fn get_date(&self) -> Option<&String> {
+        self.date.as_ref()
+    }
+
+    pub fn get_profiles(&self) -> &Vec<String> {
+        &self.profiles
+    }
+
+    pub fn get_nodes(&self) -> &Vec<ElectroCircuitNode> {
+        &self.nodes
+    }
+
+    /// Whether this is a subcircuit definition, which is only simulated through its instances.
+    pub fn is_subcircuit(&self) -> bool {
+        self.subcircuit
+    }
+
+    pub fn get_ports(&self) -> &Vec<String> {
+        &self.ports
+    }
+
+    pub fn get_params(&self) -> &Vec<(String, String)> {
+        &self.params
+    }
+
+    /// Node names are scoped to their circuit, so this only looks among the nodes of this one.
+    pub fn find_node(&self, name: &str) -> Option<&ElectroCircuitNode> {
+        self.nodes.iter().find(|x| x.name == name)
+    }
+
+    /// Checks the `-profile` declarations of the circuit against the node profiles. A declared
+    /// profile that no node defines is an error, a node profile other than `default` that the
+    /// circuit doesn't declare is handed back as a warning, along with the errors if there are any.
+    pub fn check_profiles(&self) -> Result<Vec<Diagnostic>, ParseError> {
+        let mut diagnostics = vec![];
+        let mut warnings = vec![];
+
+        for declared in self.profiles.iter() {
+            let defined = self.nodes.iter().any(|x| x.profiles.iter().any(|y| &y.name == declared));
+
+            if !defined {
+                diagnostics.push(Diagnostic::new(
+                    self.line_number,
+                    format!("Profile '{}' is declared on circuit '{}' but no node defines it", declared, self.name),
+                ));
+            }
+        }
+
+        let declared = self.profiles.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+
+        for node in self.nodes.iter() {
+            for profile in node.profiles.iter() {
+                if profile.name == "default" || declared.contains(&profile.name.as_str()) {
+                    continue;
+                }
+
+                warnings.push(
+                    Diagnostic::new(
+                        profile.line_number,
+                        format!(
+                            "Profile '{}' of node '{}' is not declared on circuit '{}'",
+                            profile.name, node.name, self.name
+                        ),
+                    )
+                    .with_hint(suggest(&profile.name, &declared))
+                    .as_warning(),
+                );
+            }
+        }
+
+        match diagnostics.is_empty() {
+            true => Ok(warnings),
+            false => {
+                diagnostics.extend(warnings);
+                Err(ParseError::new(diagnostics))
+            }
+        }
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+}
+
+/// Looking up circuits by name in what `parse` returned, since a netlist file may hold several.
+pub trait FindCircuit {
+    fn find_circuit(&self, name: &str) -> Option<&ElectroCircuit>;
+    fn circuit_names(&self) -> Vec<&str>;
+}
+
+impl FindCircuit for Vec<ElectroCircuit> {
+    fn find_circuit(&self, name: &str) -> Option<&ElectroCircuit> {
+        self.iter().find(|x| x.get_name() == name)
+    }
+
+    fn circuit_names(&self) -> Vec<&str> {
+        self.iter().map(|x| x.get_name()).collect()
+    }
+}
+
+/// The order arguments are written in by the formatter, anything else keeps its place at the end.
+const ARGUMENT_ORDER: [&str; 16] = [
+    "-dynamic",
+    "-nonlinear",
+    "-junction",
+    "-channel",
+    "-voltage",
+    "-voltage*",
+    "-voltage^",
+    "-current",
+    "-current*",
+    "-current^",
+    "-max_voltage",
+    "-power",
+    "-resistance",
+    "-capacitance",
+    "-inductance",
+    "-frequency",
+];
+
+impl fmt::Display for ConnectionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Named(name) => write!(f, "{}", name),
+            Self::Probe => write!(f, "$PROBE"),
+            Self::Ground => write!(f, "ground"),
+            Self::Next => write!(f, "next"),
+            Self::Previous => write!(f, "prev"),
+            Self::Init => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for EelectroCircuitComponent {
+    /// Writes the element marker followed by its arguments in `ARGUMENT_ORDER`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let marker = match self {
+            Self::ACSweep(_) => ".acsweep",
+            Self::DCSource(_) => ".dcsource",
+            Self::Resistor(_) => ".resistor",
+            Self::Capacitor(_) => ".capacitor",
+            Self::Inductor(_) => ".inductor",
+            Self::Transistor(_) => ".transistor",
+            Self::Diode(_) => ".diode",
+            Self::Init => return Ok(()),
+        };
+
+        let mut arguments = self
+            .get_arguments()
+            .map(|x| x.get_args().iter().map(|x| x.to_string()).collect::<Vec<String>>())
+            .unwrap_or_default();
+
+        arguments.sort_by_key(|x| {
+            let name = x.split('=').next().unwrap_or("");
+            ARGUMENT_ORDER.iter().position(|x| *x == name).unwrap_or(ARGUMENT_ORDER.len())
+        });
+
+        write!(f, "{}", marker)?;
+
+        for argument in arguments {
+            write!(f, " {}", argument)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ElectroCircuit {
+    /// Writes the circuit back out as canonical netlist text, nodes and node profiles indented
+    /// by level:
+    ///
+    /// ```text
+    /// ;lowPassFilter -author=ChubakBidpaa -profile=prof_dc
+    ///     ;;mySource -in=ground -out=next
+    ///         ;;;default .dcsource -voltage=5
+    /// ;
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ";{}", self.name)?;
+
+        if let Some(author) = &self.author {
+            write!(f, " -author={}", author)?;
+        }
+
+        if let Some(date) = &self.date {
+            write!(f, " -date={}", date)?;
+        }
+
+        for profile in self.profiles.iter() {
+            write!(f, " -profile={}", profile)?;
+        }
+
+        if self.subcircuit {
+            write!(f, " -subcircuit")?;
+        }
+
+        for port in self.ports.iter() {
+            write!(f, " -port={}", port)?;
+        }
+
+        for (name, value) in self.params.iter() {
+            write!(f, " -param={}={}", name, value)?;
+        }
+
+        writeln!(f)?;
+
+        for node in self.nodes.iter() {
+            let connections = &node.connections;
+            write!(f, "    ;;{}", node.name)?;
+
+            for (argument, connection) in [("-in", &connections.serial_in), ("-out", &connections.serial_out)] {
+                if !matches!(connection, ConnectionType::Init) {
+                    write!(f, " {}={}", argument, connection)?;
+                }
+            }
+
+            if let Some(base) = &connections.serial_base {
+                write!(f, " -base={}", base)?;
+            }
+
+            for connection in connections.parallel_in.iter() {
+                write!(f, " -in*={}", connection)?;
+            }
+
+            // A bare `$PROBE` is the only way a probe ends up among the parallel connections.
+            for connection in connections.parallel_out.iter() {
+                match connection {
+                    ConnectionType::Probe => write!(f, " $PROBE")?,
+                    _ => write!(f, " -out*={}", connection)?,
+                }
+            }
+
+            if let Some(instance) = &node.instance {
+                write!(f, " -instance={}", instance)?;
+            }
+
+            for (name, value) in node.params.iter() {
+                write!(f, " -param={}={}", name, value)?;
+            }
+
+            for (port, connection) in node.pins.iter() {
+                write!(f, " -pin={}={}", port, connection)?;
+            }
+
+            writeln!(f)?;
+
+            for profile in node.profiles.iter() {
+                write!(f, "        ;;;{}", profile.name)?;
+
+                for component in profile.components.iter().filter(|x| !matches!(x, EelectroCircuitComponent::Init)) {
+                    write!(f, " {}", component)?;
+                }
+
+                writeln!(f)?;
+            }
+        }
+
+        writeln!(f, ";")
+    }
+}
+
+/// Formats parsed circuits as a canonical netlist, one blank line between circuits. Parsing the
+/// result gives the same circuits back, but comments and `.include` directives are not kept,
+/// `format_source` keeps them.
+pub fn format_netlist(circuits: &[ElectroCircuit]) -> String {
+    circuits
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Formats a netlist the way `format_netlist` does, keeping its comments and `.include`
+/// directives. It works from the lossless syntax tree: every declaration line is swapped for its
+/// canonical form with the comments that were on it after it, and lines holding nothing but
+/// comments are kept as they are. Blank lines inside circuits go, those between them are
+/// collapsed into one.
+pub fn format_source(netlist: &str) -> Result<String, ParseError> {
+    let tree = SyntaxNode::from(netlist);
+    let tokens = tree.tokens();
+
+    // Includes are spliced in by `SourceMap`, to the parser they are blank.
+    let parsed = tokens
+        .iter()
+        .map(|x| match x.kind {
+            TokenKind::Include | TokenKind::Path => " ".repeat(x.text.chars().count()),
+            _ => x.text.clone(),
+        })
+        .collect::<String>();
+
+    let canonical = format_netlist(&parse(&parsed)?);
+    let mut canonical = canonical.lines().filter(|x| !x.is_empty());
+
+    let mut lines: Vec<String> = vec![];
+    let (mut in_circuit, mut blank) = (false, false);
+
+    for line in tokens.split(|x| x.kind == TokenKind::Newline) {
+        let first = line.iter().find(|x| !x.is_trivia()).map(|x| x.kind);
+        let comments = line
+            .iter()
+            .filter(|x| matches!(x.kind, TokenKind::LineComment | TokenKind::BlockComment))
+            .map(|x| x.text.as_str())
+            .collect::<Vec<&str>>();
+
+        let formatted = match first {
+            Some(TokenKind::CircuitName | TokenKind::NodeName | TokenKind::ProfileName | TokenKind::EndMarker) => {
+                canonical.next().unwrap_or_default().to_string()
+            }
+            Some(_) => line.iter().filter(|x| !x.is_trivia()).map(|x| x.text.as_str()).collect::<Vec<&str>>().join(" "),
+            None if comments.is_empty() => {
+                blank = blank || !in_circuit;
+                continue;
+            }
+            None => {
+                let text = line.iter().map(|x| x.text.as_str()).collect::<String>();
+                text.trim_end().to_string()
+            }
+        };
+
+        if blank && !lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        blank = false;
+
+        match (first.is_some(), comments.is_empty()) {
+            (true, false) => lines.push(format!("{} {}", formatted, comments.join(" "))),
+            _ => lines.push(formatted),
+        }
+
+        match first {
+            Some(TokenKind::CircuitName) => in_circuit = true,
+            Some(TokenKind::EndMarker) => {
+                in_circuit = false;
+                blank = true;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Returned by `parse` when the netlist has problems, holding every diagnostic found in it.
+#[derive(Debug)]
+pub struct ParseError {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseError {
+    pub fn new(diagnostics: Vec<Diagnostic>) -> Self {
+        Self { diagnostics }
+    }
+
+    pub fn get_diagnostics(&self) -> &Vec<Diagnostic> {
+        &self.diagnostics
+    }
+}
+
+impl ParseError {
+    /// Renders every diagnostic with its snippet of `source`, see `Diagnostic::render`.
+    pub fn render(&self, source: &str) -> String {
+        self.diagnostics
+            .iter()
+            .map(|x| x.render(source))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self.diagnostics.iter() {
+            match diagnostic.is_warning() {
+                true => writeln!(f, "Warning in {}", diagnostic)?,
+                false => writeln!(f, "Error in {}", diagnostic)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+
+#[derive(Clone)]
+pub enum LexerState {
+    CircuitName(LexemeLine, Netlist),
+    CircuitNode(LexemeLine, Netlist),
+    NodeProfile(LexemeLine, Netlist),
+    InOutArgs(LexemeLine, Netlist),
+    UnitArgs(LexemeLine, Netlist),
+    FlagArgs(LexemeLine, Netlist),
+    IdentityArgs(LexemeLine, Netlist),
+    EndCircuit(LexemeLine, Netlist),
+    Begin(Netlist),
+}
+
+impl LexerState {
+    /// Runs the current state over its line and returns the state that follows it, or `None` once
+    /// the netlist has been exhausted. Problems are pushed to `diagnostics` and parsing carries on
+    /// from the next line that makes sense.
+    pub fn advance(self, circuits: &mut Vec<ElectroCircuit>, diagnostics: &mut Vec<Diagnostic>) -> Option<Self> {
+        match self {
+            Self::Begin(netlist) => Self::parse_begin(netlist, diagnostics),
+            Self::CircuitName(ll, nl) => Self::parse_circuit_name(ll, nl, circuits, diagnostics),
+            Self::IdentityArgs(ll, nl) => Self::parse_identity_args(ll, nl, circuits, diagnostics),
+            Self::CircuitNode(ll, nl) => Self::parse_circuit_node(ll, nl, circuits, diagnostics),
+            Self::InOutArgs(ll, nl) => Self::parse_inout_args(ll, nl, circuits, diagnostics),
+            Self::NodeProfile(ll, nl) => Self::parse_node_profile(ll, nl, circuits, diagnostics),
+            Self::UnitArgs(ll, nl) => Self::parse_unit_args(ll, nl, circuits, diagnostics),
+            Self::FlagArgs(ll, nl) => Self::parse_flag_args(ll, nl, circuits, diagnostics),
+            Self::EndCircuit(ll, nl) => Self::parse_end_circuit(ll, nl, diagnostics),
+        }
+    }
+
+    fn parse_begin(netlist: Netlist, diagnostics: &mut Vec<Diagnostic>) -> Option<Self> {
+        if netlist.lines.iter().all(|x| x.is_blank()) && netlist.diagnostics.is_empty() {
+            diagnostics.push(Diagnostic::new(1, "Probably empty file".to_string()));
+            return None;
+        }
+
+        Self::outside_circuit(netlist, diagnostics)
+    }
+
+    /// Picks the state for the next line outside of a circuit, which can only be a circuit name.
+    /// Anything else is reported once and skipped up to the next circuit.
+    fn outside_circuit(mut netlist: Netlist, diagnostics: &mut Vec<Diagnostic>) -> Option<Self> {
+        let mut reported = false;
+
+        loop {
+            match netlist.get_next_significant() {
+                Some(lexeme_line) => match lexeme_line.first() {
+                    Some(Lexeme::NetlistName(_)) => return Some(Self::CircuitName(lexeme_line, netlist)),
+                    Some(Lexeme::EndMarker) => reported = false,
+                    _ => if !reported {
+                        diagnostics.push(lexeme_line.diagnose(0, "Expected a circuit name (;name)".to_string()));
+                        reported = true;
+                    },
+                },
+                None => return None,
+            }
+        }
+    }
+
+    /// Picks the state for the next line inside a circuit, i.e. a node, a profile or the end marker.
+    fn inside_circuit(mut netlist: Netlist, diagnostics: &mut Vec<Diagnostic>) -> Option<Self> {
+        loop {
+            match netlist.get_next_significant() {
+                Some(lexeme_line) => match lexeme_line.first() {
+                    Some(Lexeme::NodeName(_)) => return Some(Self::CircuitNode(lexeme_line, netlist)),
+                    Some(Lexeme::ProfileName(_)) => return Some(Self::NodeProfile(lexeme_line, netlist)),
+                    Some(Lexeme::EndMarker) => return Some(Self::EndCircuit(lexeme_line, netlist)),
+                    Some(Lexeme::NetlistName(_)) => {
+                        diagnostics.push(lexeme_line.diagnose(
+                            0,
+                            "The circuit before this one is not closed with an end marker (;)".to_string(),
+                        ));
+                        return Some(Self::CircuitName(lexeme_line, netlist));
+                    }
+                    _ => diagnostics.push(lexeme_line.diagnose(
+                        0,
+                        "Expected a node (;;), a node profile (;;;) or the end marker (;)".to_string(),
+                    )),
+                },
+                None => {
+                    diagnostics.push(Diagnostic::new(
+                        netlist.lines.last().map_or(0, |x| x.get_line_number()),
+                        "Circuit is not closed with an end marker (;) at the end of file".to_string(),
+                    ));
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn parse_circuit_name(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut Vec<ElectroCircuit>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let line_number = lexeme_line.get_line_number();
+
+        match (lexeme_line.first(), lexeme_line.has_valid_nlnames()) {
+            (Some(Lexeme::NetlistName(name)), true) => {
+                if name.is_empty() {
+                    diagnostics.push(lexeme_line.diagnose(0, "Circuit name cannot be left empty".to_string()));
+                }
+
+                if let Some(declared) = circuits.find_circuit(name) {
+                    diagnostics.push(lexeme_line.diagnose(
+                        0,
+                        format!("Circuit '{}' is already declared in line {}", name, declared.get_line_number()),
+                    ));
+                }
+
+                let mut circuit = ElectroCircuit::new();
+                circuit.modify_name(name.clone());
+                circuit.modify_line_number(line_number);
+                circuits.push(circuit);
+
+                Some(Self::IdentityArgs(lexeme_line, netlist))
+            }
+            _ => {
+                diagnostics.push(lexeme_line.diagnose(
+                    0,
+                    "Expected exactly one circuit name (;name) at the start of the line".to_string(),
+                ));
+
+                Self::outside_circuit(netlist, diagnostics)
+            }
+        }
+    }
+
+    fn parse_identity_args(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut [ElectroCircuit],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let circuit = circuits.last_mut().unwrap();
+
+        for (n, lexeme) in lexeme_line.get_lexemes().iter().enumerate().skip(1) {
+            match lexeme.clone() {
+                Lexeme::Arg(Argument::Author(author)) => circuit.modify_author(author),
+                Lexeme::Arg(Argument::Date(date)) => circuit.modify_date(date),
+                Lexeme::Arg(Argument::Profile(profile)) => circuit.add_profile(profile),
+                Lexeme::Arg(Argument::Subcircuit) => circuit.modify_subcircuit(true),
+                Lexeme::Arg(Argument::Port(port)) => match circuit.get_ports().contains(&port) {
+                    true => diagnostics.push(lexeme_line.diagnose(n, format!("Port '{}' is already declared", port))),
+                    false => circuit.add_port(port),
+                },
+                Lexeme::Arg(Argument::Param(name, value)) => circuit.add_param(name, value),
+                Lexeme::Comment => break,
+                _ => diagnostics.push(lexeme_line.diagnose(
+                    n,
+                    "A circuit can only be given -author, -date, -profile, -subcircuit, -port and -param".to_string(),
+                )),
+            }
+        }
+
+        Self::inside_circuit(netlist, diagnostics)
+    }
+
+    fn parse_circuit_node(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut [ElectroCircuit],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let line_number = lexeme_line.get_line_number();
+
+        match (lexeme_line.first(), lexeme_line.has_valid_ndnames()) {
+            (Some(Lexeme::NodeName(name)), true) => {
+                if name.is_empty() {
+                    diagnostics.push(lexeme_line.diagnose(0, "Node name cannot be left empty".to_string()));
+                }
+
+                let circuit = circuits.last_mut().unwrap();
+
+                if let Some(declared) = circuit.find_node(name) {
+                    diagnostics.push(lexeme_line.diagnose(
+                        0,
+                        format!(
+                            "Node '{}' is already declared in circuit '{}' in line {}",
+                            name,
+                            circuit.get_name(),
+                            declared.get_line_number()
+                        ),
+                    ));
+                }
+
+                let mut node = ElectroCircuitNode::init();
+                node.modify_name(name.clone());
+                node.modify_line_number(line_number);
+                circuit.add_node(node);
+
+                Some(Self::InOutArgs(lexeme_line, netlist))
+            }
+            _ => {
+                diagnostics.push(lexeme_line.diagnose(
+                    0,
+                    "Expected exactly one node name (;;name) at the start of the line".to_string(),
+                ));
+
+                Self::inside_circuit(netlist, diagnostics)
+            }
+        }
+    }
+
+    fn parse_inout_args(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut [ElectroCircuit],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let node = circuits.last_mut().unwrap().get_last_node_mut().unwrap();
+
+        for (n, lexeme) in lexeme_line.get_lexemes().iter().enumerate().skip(1) {
+            match lexeme.clone() {
+                Lexeme::Arg(Argument::In(connection)) => match connection {
+                    Connection::Parallel(_) => node.get_connections_mut().add_parallel_in(ConnectionType::from(connection)),
+                    _ => node.get_connections_mut().modify_serial_in(ConnectionType::from(connection)),
+                },
+                Lexeme::Arg(Argument::Out(connection)) => match connection {
+                    Connection::Parallel(_) => node.get_connections_mut().add_parallel_out(ConnectionType::from(connection)),
+                    _ => node.get_connections_mut().modify_serial_out(ConnectionType::from(connection)),
+                },
+                Lexeme::Arg(Argument::Base(connection)) => {
+                    node.get_connections_mut().modify_serial_base(ConnectionType::from(connection))
+                }
+                Lexeme::Pobe => node.get_connections_mut().add_parallel_out(ConnectionType::Probe),
+                Lexeme::Arg(Argument::Instance(instance)) => node.modify_instance(instance),
+                Lexeme::Arg(Argument::Param(name, value)) => node.add_param(name, value),
+                Lexeme::Arg(Argument::Pin(port, connection)) => node.add_pin(port, ConnectionType::from(connection)),
+                Lexeme::Comment => break,
+                _ => diagnostics.push(lexeme_line.diagnose(
+                    n,
+                    "A node can only be given -in, -out, -base, $PROBE, -instance, -param and -pin".to_string(),
+                )),
+            }
+        }
+
+        if !node.get_params().is_empty() && node.get_instance().is_none() {
+            diagnostics.push(lexeme_line.diagnose(0, "Only instances (-instance) can be given -param".to_string()));
+        }
+
+        if !node.get_pins().is_empty() && node.get_instance().is_none() {
+            diagnostics.push(lexeme_line.diagnose(0, "Only instances (-instance) can be given -pin".to_string()));
+        }
+
+        Self::inside_circuit(netlist, diagnostics)
+    }
+
+    fn parse_node_profile(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut [ElectroCircuit],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let node = match circuits.last_mut().unwrap().get_last_node_mut() {
+            Some(node) => node,
+            None => {
+                diagnostics.push(lexeme_line.diagnose(
+                    0,
+                    "A node profile (;;;) must come after a node (;;)".to_string(),
+                ));
+
+                return Self::inside_circuit(netlist, diagnostics);
+            }
+        };
+
+        match (lexeme_line.first(), lexeme_line.has_valid_propub_fnames()) {
+            (Some(Lexeme::ProfileName(name)), true) => {
+                if name.is_empty() {
+                    diagnostics.push(lexeme_line.diagnose(0, "Profile name cannot be left empty".to_string()));
+                }
+
+                if lexeme_line.get_elements().is_empty() {
+                    diagnostics.push(lexeme_line.diagnose(
+                        0,
+                        format!("Profile '{}' needs at least one element, e.g. .resistor", name),
+                    ));
+
+                    return Self::inside_circuit(netlist, diagnostics);
+                }
+
+                if let Some(declared) = node.get_profiles().iter().find(|x| x.get_name() == name) {
+                    diagnostics.push(lexeme_line.diagnose(
+                        0,
+                        format!(
+                            "Profile '{}' is already given to node '{}' in line {}",
+                            name,
+                            node.get_name(),
+                            declared.get_line_number()
+                        ),
+                    ));
+                }
+
+                let mut profile = ElectroCircuitNodeProfile::init();
+                profile.modify_name(name.clone());
+                profile.modify_line_number(lexeme_line.get_line_number());
+                profile.init_components(lexeme_line.get_elements().len());
+                node.add_profile(profile);
+
+                Some(Self::UnitArgs(lexeme_line, netlist))
+            }
+            _ => {
+                diagnostics.push(lexeme_line.diagnose(
+                    0,
+                    "Expected exactly one profile name (;;;name) at the start of the line".to_string(),
+                ));
+
+                Self::inside_circuit(netlist, diagnostics)
+            }
+        }
+    }
+
+    /// Sets up the components of the profile and hands them every valued argument that follows
+    /// their element marker.
+    fn parse_unit_args(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut [ElectroCircuit],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let line_number = lexeme_line.get_line_number();
+        let node = circuits.last_mut().unwrap().get_last_node_mut().unwrap();
+        let profile = node.get_last_profile_mut().unwrap();
+
+        let mut nth: Option<usize> = None;
+
+        for (n, lexeme) in lexeme_line.get_lexemes().iter().enumerate().skip(1) {
+            match lexeme.clone() {
+                Lexeme::Element(element) => {
+                    let next = nth.map_or(0, |nth| nth + 1);
+                    profile.set_nth_components(EelectroCircuitComponent::from(element, line_number), next);
+                    nth = Some(next);
+                }
+                Lexeme::Arg(arg) if !arg.is_flag() => {
+                    if arg.is_identity() || arg.is_inout() {
+                        diagnostics.push(lexeme_line.diagnose(
+                            n,
+                            "Connections and circuit or instance arguments cannot be given to elements".to_string(),
+                        ));
+                        continue;
+                    }
+
+                    match nth {
+                        Some(nth) => profile
+                            .get_nth_component_mut(nth)
+                            .unwrap()
+                            .push_argument(arg, lexeme_line.get_source(n).unwrap().clone()),
+                        None => diagnostics.push(lexeme_line.diagnose(
+                            n,
+                            "Arguments must come after an element marker, e.g. .resistor".to_string(),
+                        )),
+                    }
+                }
+                Lexeme::Arg(_) => (),
+                Lexeme::Comment => break,
+                _ => diagnostics.push(lexeme_line.diagnose(
+                    n,
+                    "A node profile can only hold elements and their arguments".to_string(),
+                )),
+            }
+        }
+
+        Some(Self::FlagArgs(lexeme_line, netlist))
+    }
+
+    /// Hands the flags (-dynamic, -nonlinear) over to the component they follow.
+    fn parse_flag_args(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        circuits: &mut [ElectroCircuit],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let node = circuits.last_mut().unwrap().get_last_node_mut().unwrap();
+        let profile = node.get_last_profile_mut().unwrap();
+
+        let mut nth: Option<usize> = None;
+
+        for (n, lexeme) in lexeme_line.get_lexemes().iter().enumerate().skip(1) {
+            match lexeme.clone() {
+                Lexeme::Element(_) => nth = Some(nth.map_or(0, |nth| nth + 1)),
+                Lexeme::Arg(arg) if arg.is_flag() => match nth {
+                    Some(nth) => profile
+                        .get_nth_component_mut(nth)
+                        .unwrap()
+                        .push_argument(arg, lexeme_line.get_source(n).unwrap().clone()),
+                    None => diagnostics.push(lexeme_line.diagnose(
+                        n,
+                        "Flags must come after an element marker, e.g. .capacitor".to_string(),
+                    )),
+                },
+                Lexeme::Comment => break,
+                _ => (),
+            }
+        }
+
+        Self::inside_circuit(netlist, diagnostics)
+    }
+
+    fn parse_end_circuit(
+        lexeme_line: LexemeLine,
+        netlist: Netlist,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        for (n, lexeme) in lexeme_line.get_lexemes().iter().enumerate().skip(1) {
+            if !lexeme.is_comment() {
+                diagnostics.push(lexeme_line.diagnose(n, "The end marker (;) must be alone on its line".to_string()));
+                break;
+            }
+        }
+
+        Self::outside_circuit(netlist, diagnostics)
+    }
+}
+
+/// Parses a netlist into the circuits it describes, in the order they are declared. Parsing goes on
+/// past problems, so the error holds every diagnostic for the netlist, sorted by line.
+pub fn parse(netlist: &str) -> Result<Vec<ElectroCircuit>, ParseError> {
+    let netlist = Netlist::from(netlist);
+
+    let mut circuits = vec![];
+    let mut diagnostics = netlist.get_diagnostics().clone();
+    let mut state = Some(LexerState::Begin(netlist));
+
+    while let Some(current) = state {
+        state = current.advance(&mut circuits, &mut diagnostics);
+    }
+
+    match diagnostics.is_empty() {
+        true => Ok(circuits),
+        false => {
+            diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+            Err(ParseError::new(diagnostics))
+        }
+    }
+}
+
+/// A line of an `IncrementalNetlist`, lexed along with what it takes to tell whether it has to be
+/// lexed again when the lines before it change.
+struct LexedLine {
+    lexeme_line: LexemeLine,
+    diagnostics: Vec<Diagnostic>,
+    in_block: bool,
+    ends_in_block: bool,
+    opened: Option<usize>,
+    include: bool,
+}
+
+impl LexedLine {
+    fn from(line: &str, line_number: usize, in_block: bool) -> Self {
+        let (stripped, ends_in_block, opened) = strip_line_comments(line, in_block);
+        let (lexeme_line, diagnostics) = LexemeLine::from(&stripped, line_number);
+        let include = split_whitespace_columns(&stripped).first().is_some_and(|(_, x)| *x == ".include");
+
+        Self { lexeme_line, diagnostics, in_block, ends_in_block, opened, include }
+    }
+
+    /// Whether the line opens a circuit, which is where the parser picks up again after anything.
+    fn is_circuit_name(&self) -> bool {
+        matches!(self.lexeme_line.first(), Some(Lexeme::NetlistName(_)))
+    }
+
+    fn shift_lines(&mut self, delta: isize) {
+        self.lexeme_line.line_number = self.lexeme_line.line_number.saturating_add_signed(delta);
+
+        for diagnostic in self.diagnostics.iter_mut() {
+            diagnostic.data.line_number = diagnostic.data.line_number.saturating_add_signed(delta);
+        }
+    }
+}
+
+/// The lines from a circuit name up to the next one, or the lines before the first circuit, and
+/// what parsing them gave.
+struct ParsedSection {
+    start: usize,
+    end: usize,
+    circuits: Vec<ElectroCircuit>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A netlist that is kept lexed and parsed while it is being edited, for the editor. An edit only
+/// lexes the lines it touches again, along with the lines after them that a block comment it
+/// opened or closed reaches into, and only parses the circuits those lines are in again. The
+/// rest is reused, which is what keeps large netlists responsive.
+///
+/// `get_circuits` gives the same result as `parse` on the whole text. Includes are not spliced
+/// in, netlists that have any (see `has_includes`) go through `SourceMap` instead.
+pub struct IncrementalNetlist {
+    text: String,
+    lines: Vec<LexedLine>,
+    sections: Vec<ParsedSection>,
+}
+
+impl IncrementalNetlist {
+    pub fn from(netlist: &str) -> Self {
+        let count = netlist.matches('\n').count() + 1;
+        let mut lines: Vec<LexedLine> = vec![];
+
+        for (i, line) in netlist.split('\n').enumerate() {
+            let in_block = lines.last().is_some_and(|x| x.ends_in_block);
+            lines.push(LexedLine::from(trim_carriage_return(line, i + 1 < count), i + 1, in_block));
+        }
+
+        let mut incremental = Self { text: netlist.to_string(), lines, sections: vec![] };
+
+        incremental.sections = incremental
+            .section_bounds()
+            .into_iter()
+            .map(|(start, end)| incremental.parse_section(start, end))
+            .collect();
+
+        incremental
+    }
+
+    /// Replaces the bytes in `range` with `text`, then lexes and parses again what the change
+    /// reaches. The range has to lie on character boundaries, as with `String::replace_range`.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) {
+        let first = self.text[..range.start].matches('\n').count();
+        let removed = self.text[range.clone()].matches('\n').count();
+        let inserted = text.matches('\n').count();
+        let delta = inserted as isize - removed as isize;
+        let line_start = self.text[..range.start].rfind('\n').map_or(0, |x| x + 1);
+
+        self.text.replace_range(range, text);
+
+        let count = self.lines.len().saturating_add_signed(delta);
+        let mut sources = self.text[line_start..].split('\n');
+        let mut relexed: Vec<LexedLine> = vec![];
+
+        for i in first..=first + inserted {
+            let in_block = match relexed.last() {
+                Some(line) => line.ends_in_block,
+                None => first > 0 && self.lines[first - 1].ends_in_block,
+            };
+
+            let source = trim_carriage_return(sources.next().unwrap_or(""), i + 1 < count);
+            relexed.push(LexedLine::from(source, i + 1, in_block));
+        }
+
+        self.lines.splice(first..=first + removed, relexed);
+
+        // The lines after the edit only have to be lexed again while a block comment that the
+        // edit opened or closed reaches into them.
+        let mut end = first + inserted + 1;
+
+        while end < self.lines.len() && self.lines[end].in_block != self.lines[end - 1].ends_in_block {
+            let source = trim_carriage_return(sources.next().unwrap_or(""), end + 1 < count);
+            self.lines[end] = LexedLine::from(source, end + 1, self.lines[end - 1].ends_in_block);
+            end += 1;
+        }
+
+        if delta != 0 {
+            for line in self.lines[end..].iter_mut() {
+                line.shift_lines(delta);
+            }
+        }
+
+        self.reparse(first, end, delta);
+    }
+
+    /// Parses the sections that have lines in `first..end` again, along with the one whose next
+    /// circuit name is in there, and moves the ones after it by `delta` lines.
+    fn reparse(&mut self, first: usize, end: usize, delta: isize) {
+        let starts = self.sections.iter().map(|x| x.start).collect::<Vec<usize>>();
+        let mut previous = std::mem::take(&mut self.sections)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<Option<ParsedSection>>>();
+        let mut sections = vec![];
+
+        for (start, stop) in self.section_bounds() {
+            let old_start = match start < first {
+                true => Some(start),
+                false => start.checked_add_signed(-delta).filter(|_| start >= end),
+            };
+
+            let reused = match (first <= stop && start < end, old_start) {
+                (false, Some(old_start)) => starts
+                    .binary_search(&old_start)
+                    .ok()
+                    .and_then(|i| previous[i].take())
+                    .filter(|x| x.end.checked_add_signed(if start < first { 0 } else { delta }) == Some(stop)),
+                _ => None,
+            };
+
+            // Diagnostics can name the lines of others in their message, so a section that has
+            // any is parsed again rather than moved.
+            let section = match reused {
+                Some(section) if start < first || delta == 0 => section,
+                Some(mut section) if section.diagnostics.is_empty() => {
+                    section.start = start;
+                    section.end = stop;
+
+                    for circuit in section.circuits.iter_mut() {
+                        circuit.shift_lines(delta);
+                    }
+
+                    section
+                }
+                _ => self.parse_section(start, stop),
+            };
+
+            sections.push(section);
+        }
+
+        self.sections = sections;
+    }
+
+    /// The number of lines `str::lines` would give, as a trailing newline does not start a line.
+    fn count_lines(&self) -> usize {
+        match self.text.is_empty() || self.text.ends_with('\n') {
+            true => self.lines.len() - 1,
+            false => self.lines.len(),
+        }
+    }
+
+    /// Where every section starts and ends, the end being the line of the next circuit name.
+    fn section_bounds(&self) -> Vec<(usize, usize)> {
+        let count = self.count_lines();
+
+        let mut starts = (0..count).filter(|&i| self.lines[i].is_circuit_name()).collect::<Vec<usize>>();
+
+        if count > 0 && starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+
+        starts.iter().enumerate().map(|(i, &start)| (start, starts.get(i + 1).cloned().unwrap_or(count))).collect()
+    }
+
+    /// Runs the parser over the lines of a section, stopping once it gets to the next circuit name.
+    /// That line is handed over as well, as the parser has to see it to tell a circuit that is
+    /// not closed.
+    fn parse_section(&self, start: usize, end: usize) -> ParsedSection {
+        let lines = self.lines[start..(end + 1).min(self.count_lines())]
+            .iter()
+            .map(|x| x.lexeme_line.clone())
+            .collect::<Vec<LexemeLine>>();
+
+        let netlist = Netlist { lines, current_line: 0, diagnostics: vec![] };
+
+        let mut circuits = vec![];
+        let mut diagnostics = vec![];
+        let mut state = LexerState::outside_circuit(netlist, &mut diagnostics);
+
+        while let Some(current) = state {
+            if let LexerState::CircuitName(lexeme_line, _) = &current {
+                if lexeme_line.get_line_number() == end + 1 {
+                    break;
+                }
+            }
+
+            state = current.advance(&mut circuits, &mut diagnostics);
+        }
+
+        ParsedSection { start, end, circuits, diagnostics }
+    }
+
+    /// The circuits of the netlist, or every diagnostic for it sorted by line, as `parse` gives.
+    pub fn get_circuits(&self) -> Result<Vec<ElectroCircuit>, ParseError> {
+        let count = self.count_lines();
+        let mut diagnostics = vec![];
+
+        // The block comment left open at the end is the last one to be opened.
+        if self.lines[..count].last().is_some_and(|x| x.ends_in_block) {
+            let opened = self.lines[..count].iter().enumerate().rev().find_map(|(i, x)| x.opened.map(|y| (i, y)));
+
+            if let Some((i, column)) = opened {
+                diagnostics.push(unclosed_block_comment(i + 1, column));
+            }
+        }
+
+        for line in self.lines[..count].iter() {
+            diagnostics.extend(line.diagnostics.iter().cloned());
+        }
+
+        if self.lines[..count].iter().all(|x| x.lexeme_line.is_blank()) && diagnostics.is_empty() {
+            return Err(ParseError::new(vec![Diagnostic::new(1, "Probably empty file".to_string())]));
+        }
+
+        let mut circuits: Vec<ElectroCircuit> = vec![];
+
+        for section in self.sections.iter() {
+            let at = diagnostics.len();
+            diagnostics.extend(section.diagnostics.iter().cloned());
+
+            // Sections are parsed on their own, so circuits declared twice are only found here.
+            // The parser reports them right after an empty name, before anything else.
+            for circuit in section.circuits.iter() {
+                if let Some(declared) = circuits.find_circuit(circuit.get_name()) {
+                    let diagnostic = self.lines[section.start].lexeme_line.diagnose(
+                        0,
+                        format!(
+                            "Circuit '{}' is already declared in line {}",
+                            circuit.get_name(),
+                            declared.get_line_number()
+                        ),
+                    );
+
+                    diagnostics.insert(at + usize::from(circuit.get_name().is_empty()), diagnostic);
+                }
+
+                circuits.push(circuit.clone());
+            }
+        }
+
+        match diagnostics.is_empty() {
+            true => Ok(circuits),
+            false => {
+                diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+                Err(ParseError::new(diagnostics))
+            }
+        }
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether any line is an `.include`, which only `SourceMap` knows what to do with.
+    pub fn has_includes(&self) -> bool {
+        self.lines.iter().any(|x| x.include)
+    }
+}
+
+/// Cuts the carriage return off a line that was split off before a newline, as `str::lines` does.
+fn trim_carriage_return(line: &str, before_newline: bool) -> &str {
+    match before_newline {
+        true => line.strip_suffix('\r').unwrap_or(line),
+        false => line,
+    }
+}
+
+/// Looks a parameter up among `params`, where the ones declared last win.
+fn find_param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a String> {
+    params.iter().rev().find(|(x, _)| x == name).map(|(_, value)| value)
+}
+
+/// Works out the value of a `{...}` expression from the parameters in scope, written back as a
+/// number with its unit so it can go through `Argument::from` like any other value.
+fn evaluate_deferred(expression: &str, params: &[(String, String)]) -> Result<String, String> {
+    let quantity = Expression::from(expression)?.evaluate(params)?;
+    let dimension = quantity.get_dimension();
+
+    if !quantity.get_value().is_finite() {
+        return Err(format!("Expression '{}' does not evaluate to a finite number", expression));
+    }
+
+    match dimension.get_symbols().first() {
+        Some(symbol) => Ok(format!("{}{}", quantity.get_value(), symbol)),
+        None if dimension.is_none() => Ok(format!("{}", quantity.get_value())),
+        None => Err(format!("Expression '{}' works out to {}, which is not a unit of anything", expression, dimension)),
+    }
+}
+
+impl ElectroCircuit {
+    /// Replaces `prev` and `next` with the names of the nodes they point at, so connections keep
+    /// their meaning once elaboration moves nodes around.
+    fn name_relative_connections(&mut self, diagnostics: &mut Vec<Diagnostic>) {
+        let names = self.nodes.iter().map(|x| x.name.clone()).collect::<Vec<String>>();
+
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            let line_number = node.line_number;
+
+            let mut name_relative = |connection: &mut ConnectionType| {
+                let (target, relation) = match connection {
+                    ConnectionType::Previous => (i.checked_sub(1).and_then(|i| names.get(i)), "previous"),
+                    ConnectionType::Next => (names.get(i + 1), "next"),
+                    _ => return,
+                };
+
+                match target {
+                    Some(name) => *connection = ConnectionType::Named(name.clone()),
+                    None => diagnostics.push(Diagnostic::new(
+                        line_number,
+                        format!("There is no {} node to connect to", relation),
+                    )),
+                }
+            };
+
+            node.connections.for_each_mut(|_, connection| name_relative(connection));
+
+            for (_, connection) in node.pins.iter_mut() {
+                name_relative(connection);
+            }
+        }
+    }
+
+    /// Moves the circuit down by `delta` lines, or up if it is negative.
+    fn shift_lines(&mut self, delta: isize) {
+        self.line_number = self.line_number.saturating_add_signed(delta);
+
+        for node in self.nodes.iter_mut() {
+            node.line_number = node.line_number.saturating_add_signed(delta);
+
+            for profile in node.profiles.iter_mut() {
+                profile.line_number = profile.line_number.saturating_add_signed(delta);
+
+                for component in profile.components.iter_mut() {
+                    if let Some(arguments) = component.get_arguments_mut() {
+                        arguments.line_number = arguments.line_number.saturating_add_signed(delta);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fills in every `{...}` argument and instance parameter from the parameters in scope.
+    fn resolve_deferred(&mut self, params: &[(String, String)], diagnostics: &mut Vec<Diagnostic>) {
+        for node in self.nodes.iter_mut() {
+            for (_, value) in node.params.iter_mut() {
+                if value.starts_with('{') && value.ends_with('}') {
+                    match evaluate_deferred(value[1..value.len() - 1].trim(), params) {
+                        Ok(resolved) => *value = resolved,
+                        Err(message) => diagnostics.push(Diagnostic::new(node.line_number, message)),
+                    }
+                }
+            }
+
+            for profile in node.profiles.iter_mut() {
+                for component in profile.components.iter_mut() {
+                    let arguments = match component.get_arguments_mut() {
+                        Some(arguments) => arguments,
+                        None => continue,
+                    };
+
+                    for n in 0..arguments.lexemes.len() {
+                        let (name, expression) = match &arguments.lexemes[n] {
+                            Lexeme::Arg(Argument::Deferred(name, expression)) => (name.clone(), expression.clone()),
+                            _ => continue,
+                        };
+
+                        let resolved = evaluate_deferred(&expression, params).and_then(|value| {
+                            let dimension = Quantity::from_literal(&value).map(|x| x.get_dimension());
+
+                            match (dimension, Argument::dimension(&name)) {
+                                (Ok(dimension), Some(expected)) if !dimension.is_none() && dimension != expected => {
+                                    return Err(format!("'{}' works out to {} but {} is given in {}", expression, dimension, name, expected));
+                                }
+                                _ => (),
+                            }
+
+                            match Argument::from(&format!("{}={}", name, value), arguments.line_number) {
+                                Ok(Argument::Deferred(_, _)) => Err(format!("Parameter '{}' must have a plain value", expression)),
+                                Ok(argument) => Ok(argument),
+                                Err(diagnostic) => Err(format!("{} once '{}' is filled in", diagnostic.get_message(), value)),
+                            }
+                        });
+
+                        match resolved {
+                            Ok(argument) => arguments.replace(n, Lexeme::Arg(argument)),
+                            Err(message) => diagnostics.push(arguments.diagnose(n, message)),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn elaborate_with(
+        &self,
+        library: &Vec<ElectroCircuit>,
+        params: &[(String, String)],
+        stack: &mut Vec<String>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Self {
+        let mut circuit = self.clone();
+        circuit.name_relative_connections(diagnostics);
+        circuit.resolve_deferred(params, diagnostics);
+
+        let mut nodes = vec![];
+        let mut instances: Vec<(String, Vec<String>)> = vec![];
+
+        for node in circuit.nodes.drain(..) {
+            let definition = match &node.instance {
+                Some(definition) => definition.clone(),
+                None => {
+                    nodes.push(node);
+                    continue;
+                }
+            };
+
+            if !node.profiles.is_empty() {
+                diagnostics.push(Diagnostic::new(
+                    node.line_number,
+                    format!("Instance '{}' cannot be given profiles, they come from its subcircuit", node.name),
+                ));
+            }
+
+            let subcircuit = match library.find_circuit(&definition) {
+                Some(subcircuit) if subcircuit.is_subcircuit() => subcircuit,
+                _ => {
+                    diagnostics.push(Diagnostic::new(
+                        node.line_number,
+                        format!("There is no subcircuit named '{}'", definition),
+                    ));
+                    continue;
+                }
+            };
+
+            if stack.contains(&definition) {
+                diagnostics.push(Diagnostic::new(
+                    node.line_number,
+                    format!("Subcircuit '{}' instantiates itself through {}", definition, stack.join(" -> ")),
+                ));
+                continue;
+            }
+
+            let mut instance_params = subcircuit.params.clone();
+
+            for (name, value) in node.params.iter() {
+                match find_param(&subcircuit.params, name) {
+                    Some(_) => instance_params.push((name.clone(), value.clone())),
+                    None => diagnostics.push(Diagnostic::new(
+                        node.line_number,
+                        format!("Subcircuit '{}' has no parameter named '{}'", definition, name),
+                    )),
+                }
+            }
+
+            stack.push(definition);
+            let inner = subcircuit.elaborate_with(library, &instance_params, stack, diagnostics);
+            stack.pop();
+
+            instances.push((node.name.clone(), inner.ports.clone()));
+            nodes.extend(instantiate(node, inner, diagnostics));
+        }
+
+        // Other nodes reach an instance through its ports: `-out=x1` goes to the terminal `-in`
+        // of x1 is wired to, which is its first port, anything else to the second one.
+        for node in nodes.iter_mut() {
+            let line_number = node.line_number;
+
+            node.connections.for_each_mut(|terminal, connection| {
+                let name = match connection {
+                    ConnectionType::Named(name) => name.clone(),
+                    _ => return,
+                };
+
+                if let Some((_, ports)) = instances.iter().find(|(x, _)| *x == name) {
+                    let port = match terminal {
+                        Terminal::Out => ports.first(),
+                        _ => ports.get(1),
+                    };
+
+                    match port {
+                        Some(port) => *connection = ConnectionType::Named(format!("{}.{}", name, port)),
+                        None => diagnostics.push(Diagnostic::new(
+                            line_number,
+                            format!("Instance '{}' has no port to connect to from here", name),
+                        )),
+                    }
+                }
+            });
+        }
+
+        circuit.nodes = nodes;
+        circuit
+    }
+}
+
+/// Splices an elaborated subcircuit in place of the node instantiating it. Its nodes are prefixed
+/// with the instance name (`x1.r1`) and each port becomes a node without profiles, i.e. a wire,
+/// carrying the connection the instance made through it: `-in` for the first port, `-out` for the
+/// second, `-base` for the third and `-pin=port=node` for any port by name.
+fn instantiate(
+    instance: ElectroCircuitNode,
+    inner: ElectroCircuit,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<ElectroCircuitNode> {
+    let prefix = instance.name.clone();
+    let connections = instance.connections;
+    let mut nodes = vec![];
+
+    let uses_in = !matches!(connections.serial_in, ConnectionType::Init) || !connections.parallel_in.is_empty();
+    let uses_out = !matches!(connections.serial_out, ConnectionType::Init) || !connections.parallel_out.is_empty();
+    let uses_base = connections.serial_base.is_some();
+
+    if (inner.ports.len() < 2 && uses_out) || (inner.ports.len() < 3 && uses_base) {
+        diagnostics.push(Diagnostic::new(
+            instance.line_number,
+            format!(
+                "Instance '{}' makes more connections than subcircuit '{}' has ports",
+                prefix,
+                inner.name
+            ),
+        ));
+    }
+
+    for (n, (port, _)) in instance.pins.iter().enumerate() {
+        let position = inner.ports.iter().position(|x| x == port);
+        let twice = instance.pins[..n].iter().any(|(x, _)| x == port)
+            || matches!(position, Some(0) if uses_in)
+            || matches!(position, Some(1) if uses_out)
+            || matches!(position, Some(2) if uses_base);
+
+        match (position, twice) {
+            (None, _) => diagnostics.push(
+                Diagnostic::new(
+                    instance.line_number,
+                    format!("Subcircuit '{}' has no port named '{}'", inner.name, port),
+                )
+                .with_hint(suggest(port, &inner.ports.iter().map(|x| x.as_str()).collect::<Vec<&str>>())),
+            ),
+            (Some(_), true) => diagnostics.push(Diagnostic::new(
+                instance.line_number,
+                format!("Port '{}' of instance '{}' is connected more than once", port, prefix),
+            )),
+            _ => (),
+        }
+    }
+
+    for (k, port) in inner.ports.iter().enumerate() {
+        let mut wire = ElectroCircuitNode::init();
+        wire.modify_name(format!("{}.{}", prefix, port));
+        wire.modify_line_number(instance.line_number);
+
+        match k {
+            0 => {
+                wire.connections.serial_in = connections.serial_in.clone();
+                wire.connections.parallel_in = connections.parallel_in.clone();
+            }
+            1 => {
+                wire.connections.serial_out = connections.serial_out.clone();
+                wire.connections.parallel_out = connections.parallel_out.clone();
+            }
+            2 => if let Some(base) = &connections.serial_base {
+                wire.connections.serial_in = base.clone();
+            },
+            _ => (),
+        }
+
+        if let Some((_, connection)) = instance.pins.iter().find(|(x, _)| x == port) {
+            wire.connections.serial_in = connection.clone();
+        }
+
+        nodes.push(wire);
+    }
+
+    for mut node in inner.nodes {
+        node.name = format!("{}.{}", prefix, node.name);
+        node.connections.for_each_mut(|_, connection| {
+            if let ConnectionType::Named(name) = connection {
+                *name = format!("{}.{}", prefix, name);
+            }
+        });
+
+        for profile in node.profiles.iter_mut() {
+            for arguments in profile.components.iter_mut().filter_map(|x| x.get_arguments_mut()) {
+                for n in 0..arguments.lexemes.len() {
+                    if let Lexeme::Arg(Argument::Control(name)) = &arguments.lexemes[n] {
+                        let control = Argument::Control(format!("{}.{}", prefix, name));
+                        arguments.replace(n, Lexeme::Arg(control));
+                    }
+                }
+            }
+        }
+
+        nodes.push(node);
     }
 
-    pub fn has_valid_nlnames(&self) -> bool {
-        self.get_nlname().len() == 1
-    }
+    nodes
+}
 
-    pub fn has_valid_ndnames(&self) -> bool {
-        self.get_ndname().len() == 1
+/// Flattens every subcircuit instance and fills in the `{...}` parameters, giving back the circuits
+/// that can be simulated, i.e. all but the subcircuit definitions.
+pub fn elaborate(circuits: &Vec<ElectroCircuit>) -> Result<Vec<ElectroCircuit>, ParseError> {
+    let mut diagnostics = vec![];
+
+    for subcircuit in circuits.iter().filter(|x| x.is_subcircuit()) {
+        for port in subcircuit.ports.iter().filter(|x| subcircuit.find_node(x).is_some()) {
+            diagnostics.push(Diagnostic::new(
+                subcircuit.line_number,
+                format!("Port '{}' has the same name as one of the nodes of the subcircuit", port),
+            ));
+        }
     }
 
-    pub fn has_valid_propub_fnames(&self) -> bool {
-        self.get_ndname().len() == 1
+    let elaborated = circuits
+        .iter()
+        .filter(|x| !x.is_subcircuit())
+        .map(|x| x.elaborate_with(circuits, &x.params, &mut vec![x.name.clone()], &mut diagnostics))
+        .collect::<Vec<ElectroCircuit>>();
+
+    match diagnostics.is_empty() {
+        true => Ok(elaborated),
+        false => {
+            diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+            Err(ParseError::new(diagnostics))
+        }
     }
+}
 
-    pub fn has_probe(&self) -> bool {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
-                |x| x.is_probe()
-            )
-            .count() > 0
-    }
+/// Joins terminals into nets, a disjoint-set forest where index 0 is ground.
+struct DisjointSet {
+    parent: Vec<usize>,
+}
 
-    pub fn has_endmarker(&self) -> bool {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
-                |x| x.is_endmarker()
-            )
-            .count() > 0
+impl DisjointSet {
+    fn new(count: usize) -> Self {
+        Self { parent: (0..count).collect() }
     }
 
-    pub fn has_comment(&self) -> bool {
-        self.lexemes
-            .clone()
-            .into_iter()
-            .filter(
-                |x| x.is_comment()
-            )
-            .count() > 0
+    fn add(&mut self) -> usize {
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
     }
-}
-
-impl IntoIterator for LexemeLine {
-    type Item = Lexeme;
 
-    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.lexemes.into_iter()
+        x
     }
-}
 
-impl Clone for LexemeLine {
-    fn clone(&self) -> Self {
-        Self { lexemes: self.lexemes.clone(), line_number: self.line_number.clone() }
+    /// Joins two sets, keeping ground as the root so it stays net 0.
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+
+        match (a, b) {
+            (0, _) => self.parent[b] = 0,
+            _ => self.parent[a] = b,
+        }
     }
 }
 
-
-struct Netlist {
-    lines: Vec<LexemeLine>,
-    current_line: usize,
+/// A component placed between nets: `from` is the side of `-in`, `to` the side of `-out` and
+/// `base` the net of `-base` for transistors. `control` is the node given with `-control` to a
+/// controlled source.
+#[derive(Clone)]
+pub struct NetBranch {
+    name: String,
+    node: usize,
+    component: EelectroCircuitComponent,
+    from: usize,
+    to: usize,
+    base: Option<usize>,
+    control: Option<usize>,
+    line_number: usize,
 }
 
-impl Netlist {
-    pub fn from(netlist: &str) -> Self {
-        Self {
-            lines: netlist
-                .lines()
-                .enumerate()
-                .map(|(i, s)| LexemeLine::from(s, i + 1))
-                .collect(),
-            current_line: 0,
-        }
+impl NetBranch {
+    /// The name of the node the component belongs to, suffixed with `_1`, `_2`... when the
+    /// node profile holds several components.
+    pub fn get_name(&self) -> &str {
+        &self.name
     }
 
-    pub fn from_file(fp: &str) -> Self {
-        match File::open(fp) {
-            Ok(desc) => match read_to_string(desc) {
-                Ok(netlist) => Self::from(&netlist),
-                Err(e) => error_out!("Reading file '{}' to string: {}", fp, e),
-            },
-            Err(e) => error_out!("Opening file '{}' for reading: {}", fp, e),
-        }
+    /// Index of the node among the nodes of the circuit.
+    pub fn get_node(&self) -> usize {
+        self.node
     }
 
-    pub fn advance(&mut self) {
-        self.current_line += 1;
+    pub fn get_component(&self) -> &EelectroCircuitComponent {
+        &self.component
     }
 
-    pub fn get_at(&self, index: usize) -> Option<RefCell<LexemeLine>> {
-        match self.lines.get(index) {
-            Some(lexeme_line) => Some(RefCell::new(lexeme_line.clone())),
-            None => None,
-        }
+    pub fn get_from(&self) -> usize {
+        self.from
     }
 
-    pub fn get_at_refcell(this: RefCell<Self>, index: usize) -> Option<RefCell<LexemeLine>> {
-        let this_ref = this.borrow();
-
-        this_ref.get_at(index)
+    pub fn get_to(&self) -> usize {
+        self.to
     }
 
-    pub fn get_curr(&self) -> Option<RefCell<LexemeLine>> {
-        match self.lines.get(self.current_line) {
-            Some(lexeme_line) => Some(RefCell::new(lexeme_line.clone())),
-            None => None,
-        }
+    pub fn get_base(&self) -> Option<usize> {
+        self.base
     }
 
-    pub fn get_curr_and_advance(netlist: &RefCell<Self>) -> Option<RefCell<LexemeLine>> {
-        let mut ref_mut_this = netlist.get_mut();
-        let curr = ref_mut_this.get_curr();
-        ref_mut_this.advance();
+    /// Index of the node whose voltage or current controls the component.
+    pub fn get_control(&self) -> Option<usize> {
+        self.control
+    }
 
-        curr
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
     }
 }
 
+/// The electrical nets of a circuit, with `prev`, `next`, named and ground connections
+/// resolved. Each node has three terminals (`-in`, `-out` and `-base`); a connection joins a
+/// terminal with a terminal of another node, `-in` and `-base` with the `-out` of the node
+/// named, `-out` with its `-in`, and every joined set of terminals is a net. Ground is net 0.
+///
+/// The components of the chosen node profile sit between `-in` and `-out` in series, and a
+/// node without profiles is a wire that shorts the two.
+pub struct NetGraph {
+    net_count: usize,
+    branches: Vec<NetBranch>,
+    terminals: Vec<[Option<usize>; 3]>,
+    probes: Vec<(String, usize)>,
+}
 
-impl Clone for Netlist {
-    fn clone(&self) -> Self {
-        Self { lines: self.lines.clone(), current_line: self.current_line.clone() }
+fn terminal_index(node: usize, terminal: Terminal) -> usize {
+    1 + node * 3 + match terminal {
+        Terminal::In => 0,
+        Terminal::Out => 1,
+        Terminal::Base => 2,
     }
 }
 
-struct Resistor {
-    nonlinear: bool,
-    resistance: f64,
-}
+impl NetGraph {
+    /// Resolves the nets of `circuit` with node profile `profile` in place, falling back to
+    /// `default` on nodes that don't have it. Any profile but `default` has to be declared on
+    /// the circuit with `-profile`.
+    pub fn from(circuit: &ElectroCircuit, profile: &str) -> Result<Self, ParseError> {
+        let nodes = circuit.get_nodes();
+        let names = nodes.iter().map(|x| x.get_name()).collect::<Vec<&str>>();
+        let mut sets = DisjointSet::new(1 + nodes.len() * 3);
+        let mut diagnostics = vec![];
+        let mut probes = vec![];
+
+        if profile != "default" && !circuit.get_profiles().iter().any(|x| x == profile) {
+            let declared = circuit.get_profiles().iter().map(|x| x.as_str()).collect::<Vec<&str>>();
+
+            diagnostics.push(
+                Diagnostic::new(
+                    circuit.get_line_number(),
+                    format!("Circuit '{}' does not declare profile '{}'", circuit.get_name(), profile),
+                )
+                .with_hint(suggest(profile, &declared)),
+            );
+        }
 
-struct Capacitor {
-    nonlinear: bool,
-    dynamic: bool,
-    capacitance: f64,
-}
+        for (i, node) in nodes.iter().enumerate() {
+            if node.get_instance().is_some() {
+                diagnostics.push(Diagnostic::new(
+                    node.get_line_number(),
+                    format!("Instance '{}' has to be elaborated first", node.get_name()),
+                ));
+            }
 
-struct Inductor {
-    nonlinear: bool,
-    dynamic: bool,
-    inductance: f64,
-}
+            node.get_connections().for_each(|terminal, connection| {
+                let from = terminal_index(i, terminal);
+                let to = |j: usize| match terminal {
+                    Terminal::Out => terminal_index(j, Terminal::In),
+                    _ => terminal_index(j, Terminal::Out),
+                };
 
-enum TransistorType {
-    BJT,
-    MOSFET,
-}
+                match connection {
+                    ConnectionType::Ground => sets.join(from, 0),
+                    ConnectionType::Named(name) => match names.iter().position(|x| x == name) {
+                        Some(j) => sets.join(from, to(j)),
+                        None => diagnostics.push(
+                            Diagnostic::new(node.get_line_number(), format!("There is no node named '{}'", name))
+                                .with_hint(suggest(name, &names)),
+                        ),
+                    },
+                    ConnectionType::Next => match i + 1 < nodes.len() {
+                        true => sets.join(from, to(i + 1)),
+                        false => diagnostics.push(Diagnostic::new(
+                            node.get_line_number(),
+                            "There is no next node to connect to".to_string(),
+                        )),
+                    },
+                    ConnectionType::Previous => match i.checked_sub(1) {
+                        Some(j) => sets.join(from, to(j)),
+                        None => diagnostics.push(Diagnostic::new(
+                            node.get_line_number(),
+                            "There is no previous node to connect to".to_string(),
+                        )),
+                    },
+                    ConnectionType::Probe => probes.push((node.get_name().to_string(), from)),
+                    ConnectionType::Init => (),
+                }
+            });
+        }
 
-struct Transistor {
-    power: f64,
-    voltage: f64,
-    junction_channel: JunctionChannel,
-    trantype: TransistorType,
-}
+        // Branches hold set indices until every connection is in, then they become net numbers.
+        let mut branches = vec![];
+
+        for (i, node) in nodes.iter().enumerate() {
+            let components = match (node.select_profile(profile), node.get_profiles().is_empty()) {
+                (Some(selected), _) => selected
+                    .get_components()
+                    .iter()
+                    .filter(|x| !matches!(x, EelectroCircuitComponent::Init))
+                    .collect::<Vec<&EelectroCircuitComponent>>(),
+                (None, true) => {
+                    sets.join(terminal_index(i, Terminal::In), terminal_index(i, Terminal::Out));
+                    continue;
+                }
+                (None, false) => {
+                    diagnostics.push(Diagnostic::new(
+                        node.get_line_number(),
+                        format!("Node '{}' has neither profile '{}' nor a default one", node.get_name(), profile),
+                    ));
+                    continue;
+                }
+            };
 
-struct Diode {
-    power: f64,
-    voltage: f64,
-    junction: JunctionChannel,
-}
+            let mut from = terminal_index(i, Terminal::In);
 
-struct ACSweep {
-    freq: f64,
-    max_voltage: f64,
-}
+            for (k, component) in components.iter().enumerate() {
+                let to = match k + 1 == components.len() {
+                    true => terminal_index(i, Terminal::Out),
+                    false => sets.add(),
+                };
 
-enum VoltAmps {
-    ParentAmps(f64),
-    ChildAmps(f64),
-    IndependentAmps(f64),
-    ParentVolts(f64),
-    ChildVolts(f64),
-    IndependentVolts(f64),
-}
+                let base = match component {
+                    EelectroCircuitComponent::Transistor(_) => Some(terminal_index(i, Terminal::Base)),
+                    _ => None,
+                };
 
+                let control = component.get_arguments().and_then(|arguments| {
+                    let name = arguments.get_args().into_iter().find_map(|x| match x {
+                        Argument::Control(name) => Some(name),
+                        _ => None,
+                    })?;
 
-enum DCSource {
-    Voltage(VoltAmps),
-    Current(VoltAmps),
-    CurrentByCurrent(VoltAmps, VoltAmps),
-    CurrentByVoltage(VoltAmps, VoltAmps),
-    VoltageByCurrent(VoltAmps, VoltAmps),
-    VoltageByVoltage(VoltAmps, VoltAmps),
-}
+                    let position = names.iter().position(|x| *x == name);
 
-enum EelectroCircuitComponent {
-    Resistor(Resistor),
-    Capacitor(Capacitor),
-    Inductor(Inductor),
-    Transistor(Transistor),
-    Diode(Diode),
-    ACSweep(ACSweep),
-    DCSource(DCSource),    
-    Init,
-}
+                    if position.is_none() {
+                        diagnostics.push(
+                            Diagnostic::new(arguments.get_line_number(), format!("There is no node named '{}'", name))
+                                .with_hint(suggest(&name, &names)),
+                        );
+                    }
 
-struct ElectroCircuitSigniture {
-    author: String,
-    date: String,
-}
+                    position
+                });
 
-enum ConnectionType {
-    Named(String),
-    Probe,
-    Ground,
-    Next,
-    Previous,
-    Init,
-}
+                branches.push(NetBranch {
+                    name: match components.len() {
+                        1 => node.get_name().to_string(),
+                        _ => format!("{}_{}", node.get_name(), k + 1),
+                    },
+                    node: i,
+                    component: (*component).clone(),
+                    from,
+                    to,
+                    base,
+                    control,
+                    line_number: component.get_arguments().map_or(node.get_line_number(), |x| x.get_line_number()),
+                });
+
+                from = to;
+            }
+        }
 
-struct ElectoCircuitConnection {
-    serial_in: ConnectionType,
-    serial_out: ConnectionType,
-    serial_base: Option<ConnectionType>,
-    parallel: Vec<ConnectionType>,
-}
+        if !diagnostics.is_empty() {
+            diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+            return Err(ParseError::new(diagnostics));
+        }
 
-impl ElectoCircuitConnection {
-    pub fn init() -> Self {
-        Self { 
-            serial_in: ConnectionType::Init, 
-            serial_out: ConnectionType::Init,
-            serial_base: None, 
-            parallel: vec![],
+        // Nets are numbered in the order they are first used, ground stays 0.
+        let mut numbers: Vec<(usize, usize)> = vec![(0, 0)];
+        let mut number = |sets: &mut DisjointSet, index: usize| {
+            let root = sets.find(index);
+
+            match numbers.iter().find(|(x, _)| *x == root) {
+                Some((_, n)) => *n,
+                None => {
+                    numbers.push((root, numbers.len()));
+                    numbers.len() - 1
+                }
+            }
+        };
+
+        for branch in branches.iter_mut() {
+            branch.from = number(&mut sets, branch.from);
+            branch.to = number(&mut sets, branch.to);
+            branch.base = branch.base.map(|x| number(&mut sets, x));
         }
-    }
 
-    pub fn modify_serial_in(&mut self, serial: ConnectionType) {
-        self.serial_in = serial;
+        let probes = probes.into_iter().map(|(name, x)| (name, number(&mut sets, x))).collect();
+
+        // Terminals no component touches only get a net when they share it with one that does.
+        let used = numbers.clone();
+        let terminals = (0..nodes.len())
+            .map(|i| {
+                [Terminal::In, Terminal::Out, Terminal::Base].map(|terminal| {
+                    let root = sets.find(terminal_index(i, terminal));
+                    used.iter().find(|(x, _)| *x == root).map(|(_, n)| *n)
+                })
+            })
+            .collect();
+
+        Ok(Self { net_count: numbers.len(), branches, terminals, probes })
     }
 
-    pub fn modify_serial_out(&mut self, serial: ConnectionType) {
-        self.serial_out = serial;
+    /// Number of nets, ground included.
+    pub fn get_net_count(&self) -> usize {
+        self.net_count
     }
 
-    pub fn modify_serial_base(&mut self, serial: ConnectionType) {
-        self.serial_base = Some(serial);
+    pub fn get_branches(&self) -> &Vec<NetBranch> {
+        &self.branches
     }
 
-    pub fn add_parallel(&mut self, parallel: ConnectionType) {
-        self.parallel.push(parallel);
+    /// Nodes with a `$PROBE` on them, with the net each one probes.
+    pub fn get_probes(&self) -> &Vec<(String, usize)> {
+        &self.probes
     }
-}
 
-struct ElectroCircuitNodeProfile {
-    name: String,
-    components: Vec<EelectroCircuitComponent>,
+    /// The net a terminal of the `node`th node is on, if any component is on it too.
+    pub fn get_terminal_net(&self, node: usize, terminal: Terminal) -> Option<usize> {
+        let terminals = self.terminals.get(node)?;
+
+        match terminal {
+            Terminal::In => terminals[0],
+            Terminal::Out => terminals[1],
+            Terminal::Base => terminals[2],
+        }
+    }
 }
 
-impl ElectroCircuitNodeProfile {
-    pub fn init() -> Self {
-        Self { name: String::new(), components: vec![] }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syntax_tree_gives_back_its_source() {
+        let sources = [
+            include_str!("../../examples/filters.schnl"),
+            ".include \"lib/parts.schnl\"  // shared parts\n\n;amp -author=me\t-profile=ac   \r\n\
+             ;;src -in=ground -out=next, /* block\n  comment */\n\
+             ;;;default .dcsource -voltage=5V // trailing\n\
+             ;;;ac .acsweep -freq=1k -max_voltage=1   // 1µF, 4.7kΩ\n\
+             ;;r1 -in=prev -out=$PROBE  ,\n\t;;;default .resistor -resistance={2*1kOhm}\n;\n\n\n",
+            ";broken -author\n;;@@ -in==,\n;;;default .nothing -what=?\n/* unclosed",
+            "",
+            "\n\n  \t\n",
+        ];
+
+        for source in sources {
+            assert_eq!(SyntaxNode::from(source).to_string(), source);
+        }
     }
 
-    pub fn modify_name(&mut self, name: String) {
-        self.name = name;
-    } 
+    /// What a netlist parses into, written out so two results can be compared.
+    fn summarize(result: Result<Vec<ElectroCircuit>, ParseError>) -> String {
+        match result {
+            Ok(circuits) => {
+                let lines = circuits
+                    .iter()
+                    .map(|x| format!("{}: {:?}", x.get_line_number(), x.get_nodes().iter().map(|y| y.get_line_number()).collect::<Vec<usize>>()))
+                    .collect::<Vec<String>>();
 
-    pub fn init_components(&mut self, num: usize) {
-        self.components = make_vec!(EelectroCircuitComponent $ EelectroCircuitComponent::Init $ num);
+                format!("{}{}", format_netlist(&circuits), lines.join("\n"))
+            }
+            Err(e) => format!("{:?}", e.get_diagnostics()),
+        }
     }
 
-    pub fn set_nth_components(&mut self, component: EelectroCircuitComponent, n: usize) {
-        self.components[n] = component;
+    #[test]
+    fn incremental_edits_parse_like_the_whole_text() {
+        let fragments = [
+            "\n",
+            ";",
+            ";;",
+            ";;;",
+            ",",
+            " ",
+            "=",
+            "x",
+            "next",
+            ";;r -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n",
+            ";;src -in=ground -out=next,\n;;;default .dcsource -voltage=5,\n",
+            ";;;default .capacitor -capacitance=1u\n",
+            ";c2 -author=me\n;;a -in=ground -out=ground,\n;;;default .resistor -resistance=2\n;\n",
+            "/* note */",
+            "// note\n",
+            "-resistance=",
+            "{2*1k}",
+        ];
+
+        let base = ";c1 -author=me\n;;src -in=ground -out=next,\n;;;default .dcsource -voltage=5,\n\
+                    ;;r -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n;\n";
+        let mut text = base.to_string();
+        let mut netlist = IncrementalNetlist::from(&text);
+
+        // xorshift64, seeded so a failure can be replayed.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        for step in 0..2000 {
+            // Most edits add a node before another one or the end of the circuit, so the text
+            // stays a netlist often enough for the circuits to be compared and not just the errors.
+            let node = format!(";;n{} -in=prev -out=ground,\n;;;default .resistor -resistance={}\n", step, step + 1);
+
+            let (start, end, inserted) = match random(4) {
+                0 => {
+                    let boundaries = (0..=text.len()).filter(|x| text.is_char_boundary(*x)).collect::<Vec<usize>>();
+                    let start = boundaries[random(boundaries.len())];
+                    let end = match random(3) {
+                        0 => start,
+                        _ => (start + random(24)).min(text.len()),
+                    };
+                    let end = (end..=text.len()).find(|x| text.is_char_boundary(*x)).unwrap();
+                    let inserted = match random(4) {
+                        0 => "",
+                        _ => fragments[random(fragments.len())],
+                    };
+
+                    (start, end, inserted)
+                }
+                _ => {
+                    let starts = (1..text.len())
+                        .filter(|x| text.is_char_boundary(*x) && text[..*x].ends_with('\n') && (text[*x..].starts_with(";\n") || !text[*x..].starts_with(";;;")))
+                        .collect::<Vec<usize>>();
+
+                    match starts.is_empty() {
+                        true => (0, 0, ""),
+                        false => {
+                            let start = starts[random(starts.len())];
+                            (start, start, node.as_str())
+                        }
+                    }
+                }
+            };
+
+            text.replace_range(start..end, inserted);
+            netlist.edit(start..end, inserted);
+
+            assert_eq!(netlist.get_text(), text, "text differs after edit {}", step);
+            assert_eq!(
+                summarize(netlist.get_circuits()),
+                summarize(parse(&text)),
+                "parse differs after edit {} of {:?} at {:?} in\n{}",
+                step,
+                inserted,
+                start..end,
+                text
+            );
+
+            // Starts over now and then, before the edits have mangled everything.
+            if step % 20 == 19 || text.len() > 600 {
+                text = base.to_string();
+                netlist = IncrementalNetlist::from(&text);
+            }
+        }
     }
-}
 
-struct ElectroCircuitNode {
-    name: String,
-    profiles: Vec<ElectroCircuitNodeProfile>,
-    connections: ElectoCircuitConnection,
-}
+    #[test]
+    fn parses_circuits_nodes_and_profiles() {
+        let circuits = match parse(
+            ";divider -author=me -date=1Jan2024 -profile=fast\n\
+             ;;src -in=ground -out=next,\n\
+             ;;;default .dcsource -voltage=10,\n\
+             ;;r1 -in=prev -out=next,\n\
+             ;;;default .resistor -resistance=1k,\n\
+             ;;;fast .resistor -resistance=2k,\n\
+             ;;r2 -in=prev -out=ground -out*=r1,\n\
+             ;;;default .capacitor -dynamic -capacitance=1u\n\
+             ;\n",
+        ) {
+            Ok(circuits) => circuits,
+            Err(e) => panic!("{}", e),
+        };
 
-impl ElectroCircuitNode {
-    pub fn init() -> Self {
-        Self { 
-            name: String::new(), 
-            profiles: vec![], 
-            connections: ElectoCircuitConnection::init()
-         }
+        assert_eq!(circuits.len(), 1);
+
+        let circuit = &circuits[0];
+        assert_eq!(circuit.get_name(), "divider");
+        assert_eq!(circuit.get_author().map(|x| x.as_str()), Some("me"));
+        assert_eq!(circuit.get_date().map(|x| x.as_str()), Some("1Jan2024"));
+        assert_eq!(circuit.get_profiles(), &vec!["fast".to_string()]);
+        assert_eq!(circuit.get_nodes().iter().map(|x| x.get_name()).collect::<Vec<&str>>(), ["src", "r1", "r2"]);
+        assert_eq!(circuit.get_nodes().iter().map(|x| x.get_line_number()).collect::<Vec<usize>>(), [2, 4, 7]);
+
+        let r1 = circuit.find_node("r1").unwrap();
+        assert_eq!(r1.get_profiles().iter().map(|x| x.get_name()).collect::<Vec<&str>>(), ["default", "fast"]);
+        assert!(matches!(r1.get_connections().get_serial_in(), ConnectionType::Previous));
+
+        let fast = r1.select_profile("fast").unwrap();
+        assert!(matches!(fast.get_components()[0], EelectroCircuitComponent::Resistor(_)));
+        assert_eq!(
+            fast.get_components()[0].get_arguments().unwrap().get_args()[0].get_quantity().map(|x| x.get_value()),
+            Some(2000.0)
+        );
+
+        let r2 = circuit.find_node("r2").unwrap();
+        assert_eq!(r2.get_connections().get_parallel_out().len(), 1);
+        assert!(r2.select_profile("default").unwrap().get_components()[0]
+            .get_arguments()
+            .unwrap()
+            .get_args()
+            .iter()
+            .any(|x| x.is_key("dynamic")));
     }
-}
 
-struct ElectroCircuit {
-    name: String,
-    author: Option<String>,
-    date: Option<String>,
-    nodes: Vec<ElectroCircuitNode>,
-}
+    #[test]
+    fn parse_reports_every_problem_with_its_line() {
+        let diagnostics = match parse(
+            ";;stray -in=ground\n\
+             ;first\n\
+             ;;a -in=ground -out=ground -resistance=1,\n\
+             ;;;default -resistance=1\n\
+             ;;a -in=ground -out=ground\n\
+             ;;;default .resistor -resistance=1\n\
+             ;second\n\
+             ;;b -in=ground -out=ground\n\
+             ;;;default .nothing\n",
+        ) {
+            Ok(_) => panic!("expected the netlist to be rejected"),
+            Err(e) => e.get_diagnostics().clone(),
+        };
 
-impl ElectroCircuit {
-    pub fn new() -> RefCell<ElectroCircuit> {
-        RefCell::new(Self { name: String::new(), author: None, date: None, nodes: vec![] })
-    }
+        let found = diagnostics
+            .iter()
+            .map(|x| (x.get_line_number(), x.get_message().to_string()))
+            .collect::<Vec<(usize, String)>>();
+
+        let expected = [
+            (1, "Expected a circuit name (;name)"),
+            (3, "A node can only be given -in, -out, -base, $PROBE, -instance, -param and -pin"),
+            (4, "Profile 'default' needs at least one element, e.g. .resistor"),
+            (5, "Node 'a' is already declared in circuit 'first' in line 3"),
+            (7, "The circuit before this one is not closed with an end marker (;)"),
+            (9, "Unknown element"),
+            (9, "Circuit is not closed with an end marker (;) at the end of file"),
+        ];
+
+        for (line, message) in expected {
+            assert!(
+                found.iter().any(|x| x.0 == line && x.1 == message),
+                "missing {:?} in line {} among {:?}",
+                message,
+                line,
+                found
+            );
+        }
 
-    pub fn modify_name(&mut self, name: String) {
-        self.name = name;
+        assert!(found.windows(2).all(|x| x[0].0 <= x[1].0));
+        assert_eq!(parse("\n\n").err().map(|x| x.get_diagnostics()[0].get_message().to_string()), Some("Probably empty file".to_string()));
     }
 
-    pub fn modify_author(&mut self, author: String) {
-        self.author = Some(author);
+    #[test]
+    fn diagnostics_underline_the_lexeme_and_suggest_a_name() {
+        let source = ";filter\n;;r1 -in=ground -out=ground,\n;;;default .resistr -resistance=1k\n\t;;;ac .resistor -inducance=3m\n;\n";
+
+        let rendered = match parse(source) {
+            Ok(_) => panic!("expected the netlist to be rejected"),
+            Err(e) => e.render(source),
+        };
+
+        assert!(rendered.contains(
+            "error: Unknown element\n --> line 3, column 12\n  |\n3 | ;;;default .resistr -resistance=1k\n  |            ^^^^^^^^\n  = help: did you mean `.resistor`?\n"
+        ), "{}", rendered);
+
+        // The tab before the profile is kept so the carets still line up under the argument.
+        assert!(rendered.contains(
+            "error: Wrong type of argument\n --> line 4, column 18\n  |\n4 | \t;;;ac .resistor -inducance=3m\n  | \t                ^^^^^^^^^^^^^\n  = help: did you mean `-inductance`?\n"
+        ), "{}", rendered);
+
+        let far = Diagnostic::at_lexeme("-zzzzzzzzzz=1", 1, "Wrong type of argument".to_string())
+            .with_hint(suggest("zzzzzzzzzz", &["inductance", "resistance"]));
+        assert_eq!(far.get_hint(), None);
+
+        let warning = Diagnostic::new(9, "Nothing to see".to_string()).as_warning();
+        assert_eq!(warning.render("only one line"), "warning: Nothing to see\n--> line 9\n");
     }
 
-    pub fn modify_date(&mut self, date: String) {
-        self.date = Some(date)
+    #[test]
+    fn comments_keep_lines_columns_and_urls() {
+        let (stripped, diagnostics) = strip_comments("a /* one\ntwo\n three */ b\n-author=file://host/x // note\nc//d");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(stripped, "a       \n   \n          b\n-author=file://host/x        \nc//d");
+
+        let circuits = match parse(
+            ";c1 -author=file://host/x -date=today /* spans\n\
+             ;;not -in=ground -out=ground, a node\n\
+             */\n\
+             ;;r1 -in=ground -out=ground, // trailing\n\
+             ;;;default .resistor -resistance=1k\n\
+             ;\n",
+        ) {
+            Ok(circuits) => circuits,
+            Err(e) => panic!("{}", e),
+        };
+
+        assert_eq!(circuits[0].get_author().map(|x| x.as_str()), Some("file://host/x"));
+        assert_eq!(circuits[0].get_date().map(|x| x.as_str()), Some("today"));
+        assert_eq!(circuits[0].get_nodes().iter().map(|x| (x.get_name(), x.get_line_number())).collect::<Vec<(&str, usize)>>(), [("r1", 4)]);
+
+        let (_, diagnostics) = strip_comments("x\n  y /* open\nz");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].get_line_number(), diagnostics[0].get_span().clone()), (2, 4..6));
     }
 
-    pub fn set_nodes_vec(&mut self, num_nodes: usize) {
+    #[test]
+    fn nodes_are_scoped_to_their_circuit() {
+        let netlist = ";a\n;;r1 -in=ground -out=ground,\n;;;default .resistor -resistance=1\n;\n\
+                       ;b\n;;r1 -in=ground -out=next,\n;;;default .resistor -resistance=2\n\
+                       ;;r2 -in=prev -out=ground -out*=only_in_a,\n;;;default .resistor -resistance=3\n;\n";
+
+        let circuits = match parse(netlist) {
+            Ok(circuits) => circuits,
+            Err(e) => panic!("{}", e),
+        };
+
+        assert_eq!(circuits.circuit_names(), ["a", "b"]);
+        assert_eq!(circuits.find_circuit("b").map(|x| x.get_line_number()), Some(5));
+        assert!(circuits.find_circuit("c").is_none());
+        assert!(NetGraph::from(circuits.find_circuit("a").unwrap(), "default").is_ok());
+
+        let diagnostics = match NetGraph::from(circuits.find_circuit("b").unwrap(), "default") {
+            Ok(_) => panic!("expected a node of another circuit to be out of reach"),
+            Err(e) => e.get_diagnostics().clone(),
+        };
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get_line_number(), 8);
+        assert_eq!(diagnostics[0].get_message(), "There is no node named 'only_in_a'");
+
+        let diagnostics = match parse(";a\n;\n;a\n;\n") {
+            Ok(_) => panic!("expected the second circuit 'a' to be rejected"),
+            Err(e) => e.get_diagnostics().clone(),
+        };
 
+        assert_eq!(diagnostics[0].get_line_number(), 3);
+        assert_eq!(diagnostics[0].get_message(), "Circuit 'a' is already declared in line 1");
     }
-}
 
+    #[test]
+    fn instances_connect_any_number_of_ports() {
+        let netlist = ";stage -subcircuit -port=inp -port=out -port=inn -port=vcc -port=vee\n\
+                       ;;rin -in=inp -out=inn,\n;;;default .resistor -resistance=1k\n\
+                       ;;rout -in=vcc -out=out,\n;;;default .resistor -resistance=2k\n\
+                       ;;rvee -in=vee -out=out,\n;;;default .resistor -resistance=3k\n;\n\
+                       ;top\n\
+                       ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=1\n\
+                       ;;x1 -instance=stage -in=src -out=load -base=ground -pin=vcc=supply -pin=vee=ground\n\
+                       ;;load -in=x1 -out=ground,\n;;;default .resistor -resistance=4k\n\
+                       ;;supply -in=ground -out=next,\n;;;default .dcsource -voltage=5\n\
+                       ;;bleed -in=prev -out=ground,\n;;;default .resistor -resistance=5k\n;\n";
+
+        let elaborated = match parse(netlist).and_then(|x| elaborate(&x)) {
+            Ok(circuits) => circuits,
+            Err(e) => panic!("{}", e),
+        };
 
-#[derive(Clone)]
-enum LexerState {
-    CircuitName(RefCell<LexemeLine>, RefCell<Netlist>),
-    CircuitNode(RefCell<LexemeLine>, RefCell<Netlist>),
-    NodeProfile(RefCell<LexemeLine>, RefCell<Netlist>),
-    InOutArgs(RefCell<LexemeLine>, RefCell<Netlist>),
-    UnitArgs(RefCell<LexemeLine>, RefCell<Netlist>),
-    FlagArgs(RefCell<LexemeLine>, RefCell<Netlist>),
-    IdentityArgs(RefCell<LexemeLine>, RefCell<Netlist>),
-    EndCircuit(RefCell<LexemeLine>, RefCell<Netlist>),
-    Begin(RefCell<Netlist>),
-}
+        let top = elaborated.find_circuit("top").unwrap();
+        let graph = match NetGraph::from(top, "default") {
+            Ok(graph) => graph,
+            Err(e) => panic!("{}", e),
+        };
 
-impl LexerState {
-    pub fn parse_begin(&self) -> Option<Self> {
-        match self.is_this_type("begin") {
-            true => {
-                let Self::Begin(netlist) = self.clone();
-                match Netlist::get_curr_and_advance(&netlist) {
-                    Some(lexeme_line) => Some(Self::CircuitName(lexeme_line, netlist)),
-                    None => error_out!("Error on line 1: probably empty file.",)
-                }
-            },
-            false => None,
-        }
+        let branch = |name: &str| {
+            let branch = graph.get_branches().iter().find(|x| x.get_name() == name).unwrap();
+            (branch.get_from(), branch.get_to())
+        };
+
+        // -in reaches the first port, -out the second, -base the third and -pin the other two.
+        assert_eq!(branch("x1.rin").0, branch("src").1);
+        assert_eq!(branch("x1.rin").1, 0);
+        assert_eq!(branch("x1.rout").1, branch("load").0);
+        assert_eq!(branch("x1.rout").0, branch("supply").1);
+        assert_ne!(branch("supply").1, 0);
+        assert_eq!(branch("x1.rvee").0, 0);
+
+        let written = format_netlist(&parse(netlist).unwrap());
+        assert!(written.contains(" -pin=vcc=supply -pin=vee=ground\n"), "{}", written);
+
+        let diagnostics = match parse(&netlist.replace("-pin=vee=ground", "-pin=vea=ground -pin=vcc=ground -pin=inp=ground"))
+            .and_then(|x| elaborate(&x))
+        {
+            Ok(_) => panic!("expected the pins to be rejected"),
+            Err(e) => e.get_diagnostics().clone(),
+        };
 
+        let messages = diagnostics.iter().map(|x| (x.get_line_number(), x.get_message())).collect::<Vec<(usize, &str)>>();
+        assert_eq!(
+            messages,
+            [
+                (12, "Subcircuit 'stage' has no port named 'vea'"),
+                (12, "Port 'vcc' of instance 'x1' is connected more than once"),
+                (12, "Port 'inp' of instance 'x1' is connected more than once"),
+            ]
+        );
+        assert_eq!(diagnostics[0].get_hint().map(|x| x.as_str()), Some("did you mean `vee`?"));
+
+        let diagnostics = match parse(";c\n;;r -in=ground -out=ground -pin=a=ground,\n;;;default .resistor -resistance=1\n;\n") {
+            Ok(_) => panic!("expected -pin on a node that is no instance to be rejected"),
+            Err(e) => e.get_diagnostics().clone(),
+        };
+        assert_eq!(diagnostics[0].get_message(), "Only instances (-instance) can be given -pin");
     }
 
-    pub fn parse_circuit_name(&self) -> Option<Self> {
-        match self.is_this_type("circuit_name") {
-            true => {
-                let (lexeme_line, netlist) = self.get_values();
+    #[test]
+    fn expressions_follow_precedence_and_units() {
+        let params = [
+            ("r".to_string(), "2k".to_string()),
+            ("gain".to_string(), "{r/1k + 1}".to_string()),
+            ("a".to_string(), "{b}".to_string()),
+            ("b".to_string(), "{a*2}".to_string()),
+        ];
+
+        let evaluate = |s: &str| Expression::from(s).and_then(|x| x.evaluate(&params));
+
+        let cases = [
+            ("1 + 2*3", 7.0, Dimension::NONE),
+            ("(1 + 2)*3", 9.0, Dimension::NONE),
+            ("10 - 4 - 3", 3.0, Dimension::NONE),
+            ("8/4/2", 1.0, Dimension::NONE),
+            ("2^3^2", 512.0, Dimension::NONE),
+            ("-2^2", -4.0, Dimension::NONE),
+            ("2*-3", -6.0, Dimension::NONE),
+            ("r*gain", 6000.0, Dimension::NONE),
+            ("2*1kOhm", 2000.0, Dimension::OHM),
+            ("10V / 2mA", 5000.0, Dimension::OHM),
+            ("1V * 1A", 1.0, Dimension::WATT),
+            ("sqrt(4V^2)", 4.0, Dimension::VOLT),
+            ("abs(-3mA) * 1k", 3.0, Dimension::AMPERE),
+        ];
+
+        for (s, value, dimension) in cases {
+            assert_eq!(evaluate(s), Ok(Quantity::from(value, dimension)), "{}", s);
+        }
+
+        let errors = [
+            ("1V + 1A", "Cannot add V and A in expression"),
+            ("1/0", "Division by zero in expression"),
+            ("a", "Parameter 'a' is defined in terms of itself"),
+            ("c + 1", "Unknown parameter 'c'"),
+            ("sqr(4)", "Unknown function 'sqr', did you mean `sqrt`?"),
+            ("exp(1V)", "'exp' takes a plain number, not one in V"),
+            ("(1 + 2", "Parenthesis is never closed with )"),
+            ("1 +", "Expression ends too early"),
+            ("1 2", "Unexpected '2' in expression"),
+            ("2 # 3", "Unexpected character '#' in expression"),
+        ];
+
+        for (s, message) in errors {
+            assert_eq!(evaluate(s), Err(message.to_string()), "{}", s);
+        }
+
+        let netlist = ";c -param=r=1k\n;;r1 -in=ground -out=ground,\n;;;default .resistor -resistance={2*r + 1}\n;\n";
+        let elaborated = elaborate(&parse(netlist).unwrap()).unwrap();
+        let resistor = &elaborated[0].get_nodes()[0].get_profiles()[0].get_components()[0];
+        assert_eq!(resistor.get_arguments().unwrap().get_args()[0].get_quantity().map(|x| x.get_value()), Some(2001.0));
+
+        let diagnostics = elaborate(&parse(&netlist.replace("{2*r + 1}", "{r*1V}")).unwrap()).err().unwrap();
+        assert_eq!(diagnostics.get_diagnostics()[0].get_message(), "'r*1V' works out to V but -resistance is given in Ω");
+    }
 
-                let ll_ref = lexeme_line.get_mut();
-                let nl_ref = netlist.get_mut();
+    #[test]
+    fn includes_map_back_to_their_file_and_line() {
+        let dir = std::env::temp_dir().join(format!("scheesim-includes-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+
+        std::fs::write(
+            dir.join("lib/parts.schnl"),
+            "// shared parts\n;part -subcircuit -port=a -port=b\n;;r -in=a -out=b,\n;;;default .resistor -resistance=1x\n;\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/loop.schnl"), ".include \"../main.schnl\"\n").unwrap();
+
+        let main = dir.join("main.schnl");
+        std::fs::write(
+            &main,
+            "/* .include \"lib/missing.schnl\" */\n.include \"lib/parts.schnl\"\n.include lib/loop.schnl\n\
+             .include lib/parts.schnl\n;top\n;;x1 -instance=part -in=ground -out=ground\n;\n",
+        )
+        .unwrap();
+
+        let source_map = SourceMap::from_file(main.to_str().unwrap()).unwrap();
+        let text = source_map.get_text().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The commented out include is left alone and the second one of parts.schnl is skipped.
+        assert_eq!(text.matches(";part").count(), 1);
+        assert!(!text.contains(".include"));
+
+        let cycle = &source_map.get_diagnostics()[0];
+        assert!(cycle.get_message().starts_with("Netlist '../main.schnl' includes itself through "), "{}", cycle);
+        assert_eq!(cycle.get_file(), Some(&dir.join("lib/loop.schnl").display().to_string()));
+        assert_eq!(cycle.get_line_number(), 1);
+
+        let error = parse(&text).err().unwrap();
+        let located = source_map.locate(&error.get_diagnostics()[0]);
+        assert_eq!(located.get_file(), Some(&dir.join("lib/parts.schnl").display().to_string()));
+        assert_eq!((located.get_line_number(), located.get_span().clone()), (4, 21..35));
+
+        let top = parse(&text.replace("1x", "1")).unwrap().find_circuit("top").unwrap().get_line_number();
+        let located = source_map.locate(&Diagnostic::new(top, String::new()));
+        assert_eq!(located.get_file(), Some(&main.display().to_string()));
+        assert_eq!(located.get_line_number(), 5);
+
+        assert!(source_map.render(&error).contains("4 | ;;;default .resistor -resistance=1x\n"));
+    }
 
-                let nl_match ll_ref.has_valid_nlnames() {}
+    #[test]
+    fn formatting_is_idempotent_and_keeps_comments() {
+        let sources = [
+            ".include \"lib/parts.schnl\"  // shared parts\n\n\n;amp -profile=ac   -author=me\t// amplifier\r\n\
+             ;;src   -out=next -in=ground, /* block\n  comment */\n\
+             ;;;default .dcsource -voltage=5V // trailing\n\
+             ;;;ac .acsweep -max_voltage=1 -freq=1k   // 1µF, 4.7kΩ\n\n\
+             // between nodes\n\
+             ;;r1 -out=$PROBE -in=prev  ,\n\t;;;default .resistor -resistance={2*1kOhm}\n;\n\n\n\n;b\n;\n",
+            "/* only a comment */\n\n;c\n;\n",
+            ";x -subcircuit -port=a -port=b -param=r=1k\n;;r -in=a -out=b\n;;;default .resistor -resistance={r}\n;\n\
+             ;top\n;;x1 -instance=x -param=r=2k -in=ground -out=ground\n;\n",
+        ];
+
+        // Includes are left to `SourceMap`, so they are taken out before parsing here.
+        let circuits = |s: &str| parse(&s.replace(".include \"lib/parts.schnl\"", "")).map(|x| format_netlist(&x)).ok();
+
+        for source in sources {
+            let once = match format_source(source) {
+                Ok(formatted) => formatted,
+                Err(e) => panic!("{}", e),
+            };
+
+            assert_eq!(format_source(&once).ok(), Some(once.clone()), "formatting twice differs for\n{}", once);
+            assert_eq!(circuits(&once), circuits(source));
+
+            for comment in ["// shared parts", "// amplifier", "/* block\n  comment */", "// trailing", "// 1µF, 4.7kΩ", "// between nodes", "/* only a comment */"] {
+                assert_eq!(source.contains(comment), once.contains(comment), "{:?} in\n{}", comment, once);
             }
-            false => None,
         }
+
+        let formatted = format_source(sources[0]).unwrap();
+        assert!(formatted.starts_with(".include \"lib/parts.schnl\" // shared parts\n\n;amp -author=me -profile=ac // amplifier\n"), "{}", formatted);
+        assert!(formatted.contains("    ;;src -in=ground -out=next /* block\n"), "{}", formatted);
     }
-}
 
-trait LexerStateFilterAndGet {
-    fn is_this_type(&self, key: &'static str) -> bool;
-    fn get_values(&self) -> (&RefCell<LexemeLine>, &RefCell<Netlist>);
-}
+    #[test]
+    fn connections_resolve_to_net_numbers() {
+        let netlist = ";c\n\
+                       ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=1\n\
+                       ;;r1 -in=prev -out*=r2 -out*=r3 -out*=rc,\n;;;default .resistor -resistance=1\n\
+                       ;;r2 -in*=r1 -out=ground,\n;;;default .resistor -resistance=2\n\
+                       ;;r3 -in*=r1 -out=ground,\n;;;default .resistor -resistance=3\n\
+                       ;;rc -in*=r1 -out=$PROBE,\n;;;default .resistor -resistance=4 .capacitor -capacitance=1u\n\
+                       ;;wire -in=rc -out=r1\n\
+                       ;;q -in=src -out=ground -base=wire,\n;;;default .transistor -junction=npn\n;\n";
+
+        let graph = match NetGraph::from(&parse(netlist).unwrap()[0], "default") {
+            Ok(graph) => graph,
+            Err(e) => panic!("{}", e),
+        };
 
-impl LexerStateFilterAndGet for LexerState {
-    fn is_this_type(&self, key: &'static str) -> bool {
-        match key {
-            "circuit_name" => match self {
-                Self::CircuitName(_, _) => true,
-                _ => false,
-            },
-            "circuit_node" => match self {
-                Self::CircuitNode(_, _) => true,
-                _ => false,
-            },
-            "node_profile" => match self {
-                Self::NodeProfile(_, _) => true,
-                _ => false,
-            },            
-            "inout_args" => match self {
-                Self::InOutArgs(_, _) => true,
-                _ => false,
-            },
-            "unit_args" => match self {
-                Self::UnitArgs(_, _) => true,
-                _ => false,
-            },
-            "flag_args" => match self {
-                Self::FlagArgs(_, _) => true,
-                _ => false,
-            },
-            "identity_args" => match self {
-                Self::IdentityArgs(_, _) => true,
-                _ => false,
-            },
-            "end_circuit" => match self {
-                Self::EndCircuit(_, _) => true,
-                _ => false,
-            },
-            "begin" => match self {
-                Self::Begin(_) => true,
-                _ => false,
-            }
+        let nets = graph
+            .get_branches()
+            .iter()
+            .map(|x| (x.get_name(), x.get_from(), x.get_to(), x.get_base()))
+            .collect::<Vec<(&str, usize, usize, Option<usize>)>>();
+
+        // Nets are numbered in the order branches first use them. r2, r3 and rc are in parallel off
+        // the output of r1, rc is split by the net between its components and the wire joins rc's
+        // output to r1's input, i.e. the source's output.
+        assert_eq!(
+            nets,
+            [
+                ("src", 0, 1, None),
+                ("r1", 1, 2, None),
+                ("r2", 2, 0, None),
+                ("r3", 2, 0, None),
+                ("rc_1", 2, 3, None),
+                ("rc_2", 3, 1, None),
+                ("q", 1, 0, Some(1)),
+            ]
+        );
+
+        assert_eq!(graph.get_net_count(), 4);
+        assert_eq!(graph.get_probes(), &vec![("rc".to_string(), 1)]);
+        assert_eq!(graph.get_terminal_net(5, Terminal::In), Some(1));
+        assert_eq!(graph.get_terminal_net(5, Terminal::Base), None);
+
+        let errors = [
+            (";c\n;;r -in=prev -out=ground,\n;;;default .resistor -resistance=1\n;\n", "There is no previous node to connect to"),
+            (";c\n;;r -in=ground -out=next,\n;;;default .resistor -resistance=1\n;\n", "There is no next node to connect to"),
+            (";c\n;;r -in=ground -out=r2,\n;;;default .resistor -resistance=1\n;\n", "There is no node named 'r2'"),
+        ];
+
+        for (netlist, message) in errors {
+            let diagnostics = NetGraph::from(&parse(netlist).unwrap()[0], "default").err().unwrap();
+            assert_eq!(diagnostics.get_diagnostics()[0].get_message(), message);
+            assert_eq!(diagnostics.get_diagnostics()[0].get_line_number(), 2);
         }
     }
 
-    fn get_values(&self) -> (&RefCell<LexemeLine>, &RefCell<Netlist>) {
-        match self {
-            LexerState::CircuitName(ll, nl) => (ll, nl),
-            LexerState::CircuitNode(ll, nl) => (ll, nl),
-            LexerState::NodeProfile(ll, nl) => (ll, nl),
-            LexerState::InOutArgs(ll, nl) => (ll, nl),
-            LexerState::UnitArgs(ll, nl) => (ll, nl),
-            LexerState::FlagArgs(ll, nl) => (ll, nl),
-            LexerState::IdentityArgs(ll, nl) => (ll, nl),
-            LexerState::EndCircuit(ll, nl) => (ll, nl),
-            LexerState::Begin(_) => error_out!("State non-compliant",),
-        }
-    }
-}
\ No newline at end of file
+    #[test]
+    fn profiles_fall_back_to_default_and_must_be_declared() {
+        let netlist = ";c -profile=fast -profile=slow\n\
+                       ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=1\n\
+                       ;;r1 -in=prev -out=ground,\n;;;default .resistor -resistance=1\n;;;fast .resistor -resistance=2\n\
+                       ;;r2 -in=r1 -out=ground,\n;;;fast .resistor -resistance=3\n;;;fsat .resistor -resistance=4\n;\n";
+        let circuit = &parse(netlist).unwrap()[0];
+
+        let resistances = |profile: &str| match NetGraph::from(circuit, profile) {
+            Ok(graph) => graph
+                .get_branches()
+                .iter()
+                .filter_map(|x| x.get_component().get_arguments().unwrap().get_args()[0].get_quantity())
+                .map(|x| x.get_value())
+                .collect::<Vec<f64>>(),
+            Err(e) => panic!("{}", e),
+        };
+
+        // r1 falls back to its default profile when there is no slow one.
+        assert_eq!(resistances("fast"), [1.0, 2.0, 3.0]);
+
+        let messages = |profile: &str| match NetGraph::from(circuit, profile) {
+            Ok(_) => panic!("expected profile '{}' to be rejected", profile),
+            Err(e) => e
+                .get_diagnostics()
+                .iter()
+                .map(|x| (x.get_line_number(), x.get_message().to_string(), x.get_hint().cloned()))
+                .collect::<Vec<(usize, String, Option<String>)>>(),
+        };
+
+        assert_eq!(messages("slow"), [(7, "Node 'r2' has neither profile 'slow' nor a default one".to_string(), None)]);
+        assert_eq!(
+            messages("fastt"),
+            [
+                (1, "Circuit 'c' does not declare profile 'fastt'".to_string(), Some("did you mean `fast`?".to_string())),
+                (7, "Node 'r2' has neither profile 'fastt' nor a default one".to_string(), None),
+            ]
+        );
+
+        let error = circuit.check_profiles().err().unwrap();
+        let found = error
+            .get_diagnostics()
+            .iter()
+            .map(|x| (x.get_line_number(), x.get_message(), x.is_warning()))
+            .collect::<Vec<(usize, &str, bool)>>();
+
+        assert_eq!(
+            found,
+            [
+                (1, "Profile 'slow' is declared on circuit 'c' but no node defines it", false),
+                (9, "Profile 'fsat' of node 'r2' is not declared on circuit 'c'", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_lone_prefix_letter_is_the_prefix_not_the_unit() {
+        let cases = [
+            ("1f", &["F"][..], 1e-15),
+            ("1fF", &["F"], 1e-15),
+            ("1F", &["F"], 1.0),
+            ("5a", &["A"], 5e-18),
+            ("5A", &["A"], 5.0),
+            ("22K", &[], 22e3),
+            ("1meg", &[], 1e6),
+            ("1MEG", &["Ω", "ohm", "ohms"], 1e6),
+            ("33uF", &["F"], 33e-6),
+            ("10kOhm", &["Ω", "ohm", "ohms"], 10e3),
+            ("2m", &["F"], 2e-3),
+        ];
+
+        for (s, units, value) in cases {
+            assert_eq!(Unit::with_units(s, 1, units).ok().map(|x| x.get_corresponding_value()), Some(value), "{}", s);
+        }
+
+        let formatted = format_source(";c\n;;c1 -in=ground -out=ground,\n;;;default .capacitor -capacitance=1f\n;\n").unwrap();
+        assert!(formatted.contains("-capacitance=1f"), "{}", formatted);
+
+        let formatted = format_source(";c\n;;i1 -in=ground -out=ground,\n;;;default .dcsource -current=100a\n;\n").unwrap();
+        assert!(formatted.contains("-current=100a"), "{}", formatted);
+    }
+
+    #[test]
+    fn exponents_out_of_range_are_errors_not_panics() {
+        assert_eq!(Dimension::VOLT.power(127), Err("Exponent out of range".to_string()));
+        assert_eq!(Dimension::AMPERE.power(127).and_then(|x| x.multiply(&Dimension::AMPERE)), Err("Exponent out of range".to_string()));
+        assert_eq!(Dimension::NONE.divide(&Dimension::AMPERE.power(-128).unwrap()), Err("Exponent out of range".to_string()));
+        assert_eq!(Dimension::VOLT.divide(&Dimension::AMPERE), Ok(Dimension::OHM));
+
+        let source = |voltage: &str| format!(";c\n;;src -in=ground -out=ground,\n;;;default .dcsource -voltage={}\n;\n", voltage);
+        let message = |voltage: &str| {
+            elaborate(&parse(&source(voltage)).unwrap()).err().map(|x| x.get_diagnostics()[0].get_message().to_string())
+        };
+
+        assert_eq!(message("{1V^127}"), Some("Exponent out of range in expression".to_string()));
+        assert_eq!(message("{(1V^100)*(1V^100)}"), Some("Exponent out of range in expression".to_string()));
+        assert_eq!(message("{1A*2Ohm*1A}"), Some("'1A*2Ohm*1A' works out to W but -voltage is given in V".to_string()));
+        assert_eq!(message("{1A*2Ohm}"), None);
+    }
+}
@@ -0,0 +1,870 @@
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use scheesim_lexparse::*;
+use serde_json::{json, Value};
+
+/// The elements a node profile can hold, what they are and the arguments each one takes.
+const ELEMENTS: [(&str, &str, &[&str]); 7] = [
+    (".resistor", "Resistor between `-in` and `-out`.", &["-resistance", "-nonlinear"]),
+    (".capacitor", "Capacitor between `-in` and `-out`.", &["-capacitance", "-dynamic", "-nonlinear"]),
+    (".inductor", "Inductor between `-in` and `-out`.", &["-inductance", "-dynamic", "-nonlinear"]),
+    (
+        ".dcsource",
+        "DC source driving its `-out`, independent with `-voltage` or `-current`, or controlled with a `*` \
+         (controlling) and a `^` (controlled) quantity of the node given by `-control`.",
+        &["-voltage", "-current", "-voltage*", "-current*", "-voltage^", "-current^", "-control"],
+    ),
+    (".acsweep", "AC source driving its `-out`, swept over frequency.", &["-freq", "-voltage", "-max_voltage"]),
+    (
+        ".transistor",
+        "Gummel-Poon BJT with `-junction=npn` or `pnp`, MOSFET with `-channel=n` or `p`. The collector or drain \
+         is `-in`, the emitter or source `-out` and the base or gate the `-base` connection of the node.",
+        &[
+            "-junction",
+            "-channel",
+            "-saturation_current",
+            "-emission",
+            "-beta",
+            "-beta_reverse",
+            "-early_voltage",
+            "-early_voltage_reverse",
+            "-knee_current",
+            "-leakage_current",
+            "-emitter_capacitance",
+            "-collector_capacitance",
+            "-transit_time",
+            "-power",
+            "-voltage",
+        ],
+    ),
+    (
+        ".diode",
+        "Shockley diode with `-junction=pn` or `np`, from `-in` to `-out`, optionally with a series resistance, \
+         breakdown and junction capacitance.",
+        &[
+            "-junction",
+            "-saturation_current",
+            "-emission",
+            "-resistance",
+            "-breakdown_voltage",
+            "-capacitance",
+            "-transit_time",
+            "-power",
+            "-voltage",
+        ],
+    ),
+];
+
+/// What every argument means, units aside.
+const ARGUMENTS: [(&str, &str); 43] = [
+    ("-author", "Who drew the circuit."),
+    ("-date", "When the circuit was drawn."),
+    ("-profile", "Declares a profile that nodes may give components for, e.g. `-profile=prof_ac`."),
+    ("-subcircuit", "Makes the circuit a subcircuit, only simulated through its instances."),
+    ("-port", "A node of the subcircuit that instances connect to, `-in`, `-out` and `-base` reaching the first three."),
+    ("-param", "A parameter for `{...}` expressions, given as `-param=name=value`."),
+    ("-instance", "Makes the node an instance of the subcircuit named."),
+    ("-pin", "Joins a port of the instance's subcircuit with the output of the node named, as `-pin=port=node`."),
+    ("-in", "Joins this node's input with the output of the node named, `ground`, `prev` or `next`."),
+    ("-out", "Joins this node's output with the input of the node named, `ground`, `prev`, `next` or `$PROBE`."),
+    ("-base", "Joins the base or gate of a transistor with the output of the node named."),
+    ("-in*", "Like `-in`, for one of several nodes in parallel."),
+    ("-out*", "Like `-out`, for one of several nodes in parallel."),
+    ("-parallel", "Same as `-out*`."),
+    ("-nonlinear", "Marks the component as nonlinear."),
+    ("-dynamic", "Marks the component as dynamic, i.e. it stores energy."),
+    ("-junction", "The junction of a diode (`pn`, `np`) or BJT (`npn`, `pnp`)."),
+    ("-channel", "The channel of a MOSFET, `n` or `p`."),
+    ("-voltage", "Voltage of a source, or the rating of a diode or transistor."),
+    ("-current", "Current of a source."),
+    ("-voltage*", "The controlling voltage of a controlled source."),
+    ("-current*", "The controlling current of a controlled source."),
+    ("-voltage^", "The controlled voltage of a controlled source."),
+    ("-current^", "The controlled current of a controlled source."),
+    (
+        "-control",
+        "The node whose voltage (`-out` over `-in`) or current controls a controlled source. The `^` quantity \
+         over the `*` one is the gain.",
+    ),
+    ("-max_voltage", "Peak voltage of an AC sweep."),
+    ("-power", "Power rating."),
+    ("-inductance", "Inductance of an inductor."),
+    ("-capacitance", "Capacitance of a capacitor, or the zero-bias junction capacitance of a diode."),
+    ("-resistance", "Resistance of a resistor, or the series resistance of a diode."),
+    ("-saturation_current", "Saturation current of a diode, 10fA by default, or of a BJT, 0.1fA by default."),
+    ("-emission", "Emission coefficient of a diode or BJT, 1 by default."),
+    ("-breakdown_voltage", "Reverse voltage a diode breaks down at, none by default."),
+    ("-transit_time", "Forward transit time of a diode or BJT, giving its diffusion capacitance."),
+    ("-beta", "Forward current gain of a BJT, 100 by default."),
+    ("-beta_reverse", "Reverse current gain of a BJT, 1 by default."),
+    ("-early_voltage", "Forward Early voltage of a BJT, none by default."),
+    ("-early_voltage_reverse", "Reverse Early voltage of a BJT, none by default."),
+    ("-knee_current", "Collector current a BJT's beta rolls off past under high injection, none by default."),
+    ("-leakage_current", "Saturation current of a BJT's base-emitter leakage, 0 by default."),
+    ("-emitter_capacitance", "Zero-bias base-emitter junction capacitance of a BJT."),
+    ("-collector_capacitance", "Zero-bias base-collector junction capacitance of a BJT."),
+    ("-frequency", "Frequency of an AC sweep, `-freq` for short."),
+];
+
+const CIRCUIT_ARGUMENTS: [&str; 6] = ["-author", "-date", "-profile", "-subcircuit", "-port", "-param"];
+
+const NODE_ARGUMENTS: [&str; 9] = ["-in", "-out", "-base", "-in*", "-out*", "-parallel", "-instance", "-param", "-pin"];
+
+const CONNECTION_KEYWORDS: [&str; 4] = ["ground", "prev", "next", "$PROBE"];
+
+/// LSP completion item kinds.
+const KEYWORD: u32 = 14;
+const PROPERTY: u32 = 10;
+const REFERENCE: u32 = 18;
+
+/// LSP symbol kinds.
+const CLASS: u32 = 5;
+const FIELD: u32 = 8;
+const ENUM_MEMBER: u32 = 22;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Circuit,
+    Node,
+    Profile,
+}
+
+/// A circuit, node or profile line of a document, the outline the editor features work from.
+struct Symbol {
+    kind: SymbolKind,
+    name: String,
+    line: usize,
+    span: Range<usize>,
+    last_line: usize,
+}
+
+/// Lists the circuits, nodes and profiles of `text` in order, 0-based lines. It works on the
+/// syntax tree, so it still finds them while other parts of the document don't parse.
+fn outline(text: &str) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    collect_symbols(&SyntaxNode::from(text), &mut symbols);
+
+    symbols
+}
+
+fn collect_symbols(node: &SyntaxNode, symbols: &mut Vec<Symbol>) {
+    for child in node.nodes() {
+        let kind = match child.get_kind() {
+            SyntaxKind::Circuit => SymbolKind::Circuit,
+            SyntaxKind::Node => SymbolKind::Node,
+            SyntaxKind::Profile => SymbolKind::Profile,
+            _ => continue,
+        };
+
+        let tokens = child.tokens().into_iter().filter(|x| !x.is_trivia()).collect::<Vec<&Token>>();
+
+        if let (Some(name), Some(first), Some(last)) = (child.get_name(), tokens.first(), tokens.last()) {
+            symbols.push(Symbol {
+                kind,
+                name,
+                line: first.get_line_number() - 1,
+                span: first.get_column()..first.get_column() + first.get_text().chars().count(),
+                last_line: last.get_line_number() - 1,
+            });
+        }
+
+        collect_symbols(child, symbols);
+    }
+}
+
+/// The circuit that `line` is in, along with the nodes of that circuit.
+fn circuit_at(symbols: &[Symbol], line: usize) -> Option<(&Symbol, Vec<&Symbol>)> {
+    let index = symbols
+        .iter()
+        .position(|x| x.kind == SymbolKind::Circuit && x.line <= line && line <= x.last_line)?;
+
+    let nodes = symbols[index + 1..]
+        .iter()
+        .take_while(|x| x.kind != SymbolKind::Circuit)
+        .filter(|x| x.kind == SymbolKind::Node)
+        .collect();
+
+    Some((&symbols[index], nodes))
+}
+
+/// Turns a `file://` URI into a path, undoing percent-encoding.
+fn path_from_uri(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' if i + 2 < bytes.len() => std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// LSP counts columns in UTF-16 code units, the lexer in chars.
+fn utf16_column(line: &str, column: usize) -> usize {
+    line.chars().take(column).map(|x| x.len_utf16()).sum()
+}
+
+fn char_column(line: &str, utf16: usize) -> usize {
+    let mut units = 0;
+
+    for (i, ch) in line.chars().enumerate() {
+        if units >= utf16 {
+            return i;
+        }
+
+        units += ch.len_utf16();
+    }
+
+    line.chars().count()
+}
+
+/// The byte offset of an LSP position in `text`.
+fn offset(text: &str, line: usize, utf16: usize) -> usize {
+    let start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1).map_or(text.len(), |(i, _)| i + 1),
+    };
+
+    let source = text[start..].split('\n').next().unwrap_or("");
+
+    start + source.char_indices().nth(char_column(source, utf16)).map_or(source.len(), |(i, _)| i)
+}
+
+fn range(text: &str, line: usize, span: &Range<usize>) -> Value {
+    let source = text.lines().nth(line).unwrap_or("");
+
+    json!({
+        "start": { "line": line, "character": utf16_column(source, span.start) },
+        "end": { "line": line, "character": utf16_column(source, span.end) },
+    })
+}
+
+/// The whitespace separated word under `column` in `line`, with the columns it spans.
+fn word_at(line: &str, column: usize) -> Option<(Range<usize>, String)> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut start = column.min(chars.len());
+    let mut end = start;
+
+    while start > 0 && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+
+    while end < chars.len() && !chars[end].is_whitespace() {
+        end += 1;
+    }
+
+    let word = chars[start..end].iter().collect::<String>();
+    let word = word.trim_end_matches(',');
+
+    match word.is_empty() && start == end {
+        true => None,
+        false => Some((start..start + word.chars().count(), word.to_string())),
+    }
+}
+
+fn element_of(marker: &str) -> Option<&'static (&'static str, &'static str, &'static [&'static str])> {
+    ELEMENTS.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(marker))
+}
+
+fn describe_argument(name: &str) -> Option<String> {
+    let name = match name.to_lowercase().as_str() {
+        "-freq" => "-frequency".to_string(),
+        name => name.to_string(),
+    };
+
+    let (_, description) = ARGUMENTS.iter().find(|(x, _)| *x == name)?;
+
+    match Argument::physical_units(&name) {
+        [] => Some(format!("`{}`: {}", name, description)),
+        units => Some(format!("`{}`: {} Given in {}.", name, description, units.join(", "))),
+    }
+}
+
+/// A document the client has open, kept parsed as it changes.
+struct Document {
+    uri: String,
+    netlist: IncrementalNetlist,
+}
+
+impl Document {
+    fn get_text(&self) -> &str {
+        self.netlist.get_text()
+    }
+}
+
+/// A language server for `.schnl` netlists, fed one JSON-RPC message at a time.
+pub struct Server {
+    documents: Vec<Document>,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self { documents: vec![], shutdown: false, exited: false }
+    }
+
+    /// Whether the client has sent `exit`, after which nothing else is handled.
+    pub fn is_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Handles a request or notification and returns the messages to send back, i.e. the
+    /// response to a request and any notifications it gave rise to.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": true, "change": 2, "save": { "includeText": false } },
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["-", ".", "="] },
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "scheesim-lsp" },
+            })),
+            "initialized" => return vec![],
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+                let text = params["textDocument"]["text"].as_str().unwrap_or("").to_string();
+
+                self.documents.retain(|x| x.uri != uri);
+                self.documents.push(Document { uri: uri.clone(), netlist: IncrementalNetlist::from(&text) });
+
+                return vec![self.publish_diagnostics(&uri)];
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+
+                let document = match self.documents.iter_mut().find(|x| x.uri == uri) {
+                    Some(document) => document,
+                    None => return vec![],
+                };
+
+                // Changes come in order, each one against the text the one before it left.
+                for change in params["contentChanges"].as_array().unwrap_or(&vec![]) {
+                    let text = change["text"].as_str().unwrap_or("");
+
+                    match change.get("range") {
+                        Some(range) => {
+                            let position = |x: &Value| {
+                                let line = x["line"].as_u64().unwrap_or(0) as usize;
+                                offset(document.get_text(), line, x["character"].as_u64().unwrap_or(0) as usize)
+                            };
+
+                            let (start, end) = (position(&range["start"]), position(&range["end"]));
+                            document.netlist.edit(start..end.max(start), text);
+                        }
+                        None => document.netlist = IncrementalNetlist::from(text),
+                    }
+                }
+
+                return vec![self.publish_diagnostics(&uri)];
+            }
+            "textDocument/didSave" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+
+                if let (Some(document), Some(text)) =
+                    (self.documents.iter_mut().find(|x| x.uri == uri), params["text"].as_str())
+                {
+                    if document.get_text() != text {
+                        document.netlist = IncrementalNetlist::from(text);
+                    }
+                }
+
+                return vec![self.publish_diagnostics(&uri)];
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+                self.documents.retain(|x| x.uri != uri);
+
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })];
+            }
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/completion" => Ok(self.complete(params)),
+            "textDocument/definition" => Ok(self.find_definition(params)),
+            "textDocument/documentSymbol" => Ok(self.list_symbols(params)),
+            _ if id.is_none() => return vec![],
+            _ if self.shutdown => Err((-32600, "The server is shutting down".to_string())),
+            _ => Err((-32601, format!("Method '{}' is not supported", method))),
+        };
+
+        let id = match id {
+            Some(id) => id,
+            None => return vec![],
+        };
+
+        match result {
+            Ok(result) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            Err((code, message)) => {
+                vec![json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })]
+            }
+        }
+    }
+
+    /// The document a request is about, and the line and char column of its position.
+    fn locate(&self, params: &Value) -> Option<(&Document, usize, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.iter().find(|x| x.uri == uri)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let column = char_column(document.get_text().lines().nth(line).unwrap_or(""), character);
+
+        Some((document, line, column))
+    }
+
+    /// Parses, elaborates and connects the document, with its includes, and reports everything
+    /// that points into the document itself.
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let netlist = self.documents.iter().find(|x| x.uri == uri).map(|x| &x.netlist);
+        let text = netlist.map_or("", |x| x.get_text());
+        let path = path_from_uri(uri);
+
+        // The document is kept parsed as it changes, unless it has includes to splice in.
+        let (parsed, source_map) = match netlist.filter(|x| !x.has_includes()) {
+            Some(netlist) => (netlist.get_circuits(), None),
+            None => {
+                let source_map = SourceMap::from(text, &path);
+                (parse(source_map.get_text()), Some(source_map))
+            }
+        };
+
+        let mut found = source_map.as_ref().map_or(vec![], |x| x.get_diagnostics().clone());
+
+        match parsed {
+            Ok(circuits) => {
+                for circuit in circuits.iter() {
+                    match circuit.check_profiles() {
+                        Ok(warnings) => found.extend(warnings),
+                        Err(e) => found.extend(e.get_diagnostics().iter().cloned()),
+                    }
+                }
+
+                match elaborate(&circuits) {
+                    Ok(elaborated) => {
+                        for circuit in elaborated.iter().filter(|x| !x.is_subcircuit()) {
+                            if let Err(e) = NetGraph::from(circuit, "default") {
+                                found.extend(e.get_diagnostics().iter().cloned());
+                            }
+                        }
+                    }
+                    Err(e) => found.extend(e.get_diagnostics().iter().cloned()),
+                }
+            }
+            Err(e) => found.extend(e.get_diagnostics().iter().cloned()),
+        }
+
+        let diagnostics = found
+            .iter()
+            .map(|x| match &source_map {
+                Some(source_map) => source_map.locate(x),
+                None => x.clone(),
+            })
+            .filter(|x| x.get_file().is_none_or(|file| *file == path))
+            .map(|x| {
+                let line = x.get_line_number().saturating_sub(1);
+                let span = match x.get_span().is_empty() {
+                    true => 0..text.lines().nth(line).map_or(0, |x| x.chars().count()),
+                    false => x.get_span().clone(),
+                };
+
+                let message = match x.get_hint() {
+                    Some(hint) => format!("{}\nhelp: {}", x.get_message(), hint),
+                    None => x.get_message().to_string(),
+                };
+
+                json!({
+                    "range": range(text, line, &span),
+                    "severity": if x.is_warning() { 2 } else { 1 },
+                    "source": "scheesim",
+                    "message": message,
+                })
+            })
+            .collect::<Vec<Value>>();
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    /// Describes the element marker or argument under the cursor.
+    fn hover(&self, params: &Value) -> Value {
+        let (document, line, column) = match self.locate(params) {
+            Some(located) => located,
+            None => return Value::Null,
+        };
+
+        let (span, word) = match word_at(document.get_text().lines().nth(line).unwrap_or(""), column) {
+            Some(word) => word,
+            None => return Value::Null,
+        };
+
+        let contents = match word.chars().next() {
+            Some('.') => element_of(&word).map(|(name, description, arguments)| {
+                format!("`{}`: {}\n\nArguments: {}", name, description, arguments.join(", "))
+            }),
+            Some('-') => describe_argument(word.split('=').next().unwrap_or("")),
+            _ => None,
+        };
+
+        match contents {
+            Some(contents) => json!({
+                "contents": { "kind": "markdown", "value": contents },
+                "range": range(document.get_text(), line, &span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    /// Offers element markers, the arguments that fit where the cursor is, and node names for
+    /// the value of a connection.
+    fn complete(&self, params: &Value) -> Value {
+        let (document, line, column) = match self.locate(params) {
+            Some(located) => located,
+            None => return json!([]),
+        };
+
+        let source = document.get_text().lines().nth(line).unwrap_or("");
+        let before = source.chars().take(column).collect::<String>();
+        let word = before.rsplit(char::is_whitespace).next().unwrap_or("");
+        let item = |label: &str, kind: u32| json!({ "label": label, "kind": kind });
+
+        // The value of a connection or of `-control` names a node of the same circuit.
+        if let Some((key, _)) = word.split_once('=') {
+            let key = key.to_lowercase();
+
+            if !["-in", "-out", "-base", "-in*", "-out*", "-parallel", "-control"].contains(&key.as_str()) {
+                return json!([]);
+            }
+
+            let symbols = outline(document.get_text());
+            let mut items = match key.as_str() {
+                "-control" => vec![],
+                _ => CONNECTION_KEYWORDS.iter().map(|x| item(x, KEYWORD)).collect::<Vec<Value>>(),
+            };
+
+            if let Some((_, nodes)) = circuit_at(&symbols, line) {
+                items.extend(nodes.iter().filter(|x| x.line != line).map(|x| item(&x.name, REFERENCE)));
+            }
+
+            return json!(items);
+        }
+
+        let semicolons = source.trim_start().chars().take_while(|x| *x == ';').count();
+
+        let arguments: Vec<&str> = match semicolons {
+            1 => CIRCUIT_ARGUMENTS.to_vec(),
+            2 => NODE_ARGUMENTS.to_vec(),
+            3 if word.starts_with('.') => return json!(ELEMENTS.iter().map(|(x, _, _)| item(x, KEYWORD)).collect::<Vec<Value>>()),
+            3 => {
+                let marker = before.split_whitespace().rfind(|x| x.starts_with('.'));
+
+                match marker.and_then(element_of) {
+                    Some((_, _, arguments)) => arguments.to_vec(),
+                    None => return json!(ELEMENTS.iter().map(|(x, _, _)| item(x, KEYWORD)).collect::<Vec<Value>>()),
+                }
+            }
+            _ => vec![],
+        };
+
+        let items = arguments
+            .iter()
+            .map(|x| {
+                let mut completion = item(x, PROPERTY);
+
+                if let Some(description) = describe_argument(x) {
+                    completion["documentation"] = json!({ "kind": "markdown", "value": description });
+                }
+
+                completion
+            })
+            .collect::<Vec<Value>>();
+
+        json!(items)
+    }
+
+    /// Goes from a connection such as `-in*=mySource`, `-pin=port=mySource` or from `-control=name`
+    /// to the node it names, and from `-instance=name` to the subcircuit.
+    fn find_definition(&self, params: &Value) -> Value {
+        let (document, line, column) = match self.locate(params) {
+            Some(located) => located,
+            None => return Value::Null,
+        };
+
+        let word = match word_at(document.get_text().lines().nth(line).unwrap_or(""), column) {
+            Some((_, word)) => word,
+            None => return Value::Null,
+        };
+
+        let (key, name) = match word.split_once('=') {
+            Some((key, name)) => (key.to_lowercase(), name.to_string()),
+            None => return Value::Null,
+        };
+
+        let symbols = outline(document.get_text());
+
+        let target = match key.as_str() {
+            "-in" | "-out" | "-base" | "-in*" | "-out*" | "-parallel" | "-control" => circuit_at(&symbols, line)
+                .and_then(|(_, nodes)| nodes.into_iter().find(|x| x.name == name)),
+            "-pin" => circuit_at(&symbols, line)
+                .and_then(|(_, nodes)| nodes.into_iter().find(|x| Some(x.name.as_str()) == name.split_once('=').map(|(_, node)| node))),
+            "-instance" => symbols.iter().find(|x| x.kind == SymbolKind::Circuit && x.name == name),
+            _ => None,
+        };
+
+        match target {
+            Some(target) => json!({
+                "uri": document.uri,
+                "range": range(document.get_text(), target.line, &target.span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    /// The circuits of the document, with their nodes and the profiles of those nested inside.
+    fn list_symbols(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let document = match self.documents.iter().find(|x| x.uri == uri) {
+            Some(document) => document,
+            None => return json!([]),
+        };
+
+        let text = document.get_text();
+        let symbol = |x: &Symbol, kind: u32| {
+            let last = text.lines().nth(x.last_line).map_or(0, |x| x.chars().count());
+
+            json!({
+                "name": x.name,
+                "kind": kind,
+                "range": {
+                    "start": { "line": x.line, "character": 0 },
+                    "end": { "line": x.last_line, "character": utf16_column(text.lines().nth(x.last_line).unwrap_or(""), last) },
+                },
+                "selectionRange": range(text, x.line, &x.span),
+                "children": [],
+            })
+        };
+
+        let mut circuits: Vec<Value> = vec![];
+
+        for x in outline(text).iter() {
+            match x.kind {
+                SymbolKind::Circuit => circuits.push(symbol(x, CLASS)),
+                SymbolKind::Node => {
+                    if let Some(circuit) = circuits.last_mut() {
+                        circuit["children"].as_array_mut().unwrap().push(symbol(x, FIELD));
+                    }
+                }
+                SymbolKind::Profile => {
+                    let node = circuits
+                        .last_mut()
+                        .and_then(|x| x["children"].as_array_mut())
+                        .and_then(|x| x.last_mut());
+
+                    if let Some(node) = node {
+                        node["children"].as_array_mut().unwrap().push(symbol(x, ENUM_MEMBER));
+                    }
+                }
+            }
+        }
+
+        json!(circuits)
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads one message framed by a `Content-Length` header, `None` once the input ends.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Message has no Content-Length header")),
+    };
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Runs the server over a pair of streams, usually stdin and stdout, until the client sends
+/// `exit` or closes its end.
+pub fn serve(mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+    let mut server = Server::new();
+
+    while let Some(message) = read_message(&mut reader)? {
+        for outgoing in server.handle(&message) {
+            write_message(&mut writer, &outgoing)?;
+        }
+
+        if server.is_exited() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///divider.schnl";
+
+    fn frame(message: Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> String {
+        frame(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+    }
+
+    fn notify(method: &str, params: Value) -> String {
+        frame(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn position(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+    }
+
+    /// Pipes the frames through `serve` and reads back everything it wrote.
+    fn converse(frames: &[String]) -> Vec<Value> {
+        let input = frames.concat();
+        let mut output = vec![];
+
+        serve(io::Cursor::new(input.into_bytes()), &mut output).unwrap();
+
+        let mut reader = io::Cursor::new(output);
+        let mut messages = vec![];
+
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    #[test]
+    fn serves_a_session_over_streams() {
+        let text = ";divider\n;;src -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n;;r1 -in=src -out=ground,\n;;;default .resistor -resistanse=1k\n;\n";
+
+        let messages = converse(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            notify("initialized", json!({})),
+            notify("textDocument/didOpen", json!({
+                "textDocument": { "uri": URI, "languageId": "schnl", "version": 1, "text": text },
+            })),
+            // Fixes the misspelt argument on line 4.
+            notify("textDocument/didChange", json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{
+                    "range": { "start": { "line": 4, "character": 30 }, "end": { "line": 4, "character": 31 } },
+                    "text": "c",
+                }],
+            })),
+            request(2, "textDocument/hover", position(4, 13)),
+            request(3, "textDocument/definition", position(3, 10)),
+            request(4, "shutdown", Value::Null),
+            notify("exit", Value::Null),
+        ]);
+
+        assert_eq!(messages.len(), 6);
+
+        assert_eq!(messages[0]["id"], 1);
+        assert_eq!(messages[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(messages[0]["result"]["capabilities"]["definitionProvider"], true);
+
+        assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(messages[1]["params"]["uri"], URI);
+        let diagnostics = messages[1]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 4);
+
+        assert_eq!(messages[2]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(messages[2]["params"]["diagnostics"], json!([]));
+
+        assert_eq!(messages[3]["id"], 2);
+        assert_eq!(messages[3]["result"]["contents"]["kind"], "markdown");
+        assert!(messages[3]["result"]["contents"]["value"].as_str().unwrap().contains(".resistor"));
+
+        assert_eq!(messages[4]["id"], 3);
+        assert_eq!(messages[4]["result"]["uri"], URI);
+        assert_eq!(messages[4]["result"]["range"]["start"], json!({ "line": 1, "character": 0 }));
+        assert_eq!(messages[4]["result"]["range"]["end"], json!({ "line": 1, "character": 5 }));
+
+        assert_eq!(messages[5]["id"], 4);
+        assert_eq!(messages[5]["result"], Value::Null);
+    }
+}
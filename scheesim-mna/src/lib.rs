@@ -1,65 +1,108 @@
 use scheesim_lexparse::*;
+pub use scheesim_impl::ElementStampType;
+use scheesim_impl::{ConvergentF64, RhsValueType, UnknownFactor, UnknownFactors};
+use scheesim_solve::{quasi_newton_iter_linear_until_converge, LinearSystemSolve};
+
+/// kT/q at 27 °C.
+const THERMAL_VOLTAGE: f64 = 0.025864;
+
+/// The conductance put across every junction so no net floats when it's off, as SPICE does.
+const GMIN: f64 = 1e-12;
+
+/// Built-in potential, grading coefficient and the fraction of the potential past which the
+/// depletion capacitance is linearised, for junctions.
+const JUNCTION_POTENTIAL: f64 = 1.0;
+const GRADING: f64 = 0.5;
+const DEPLETION_FACTOR: f64 = 0.5;
+
+/// Emission coefficient of the base-emitter leakage of a BJT.
+const LEAKAGE_EMISSION: f64 = 1.5;
+
+/// Limits on the Newton-Raphson iterations of the DC operating point.
+const NEWTON_ITERATIONS: usize = 100;
+const NEWTON_ABS_TOL: f64 = 1e-9;
+const NEWTON_REL_TOL: f64 = 1e-6;
 
 pub struct Resistor {
     nonlinear: bool,
-    resistance: f64,
+    resistance: Quantity,
 }
 
 impl Resistor {
-    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Self {
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
         let mut nonlinear = false;
-        let mut resistance = 0.0f64;
+        let mut resistance = Quantity::from(0.0, Dimension::OHM);
 
-        for ll in lexeme_line {
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
             match ll {
                 Lexeme::Arg(arg) => match arg {
                     Argument::Nonlinear => nonlinear = true,
-                    Argument::Resistance(unit) => resistance = unit.get_corresponding_value(),
-                    _ => error_out!("Wrong argument given to resistor in line {}, optional: -nonlinear, required: -resistance", line_number)
+                    Argument::Resistance(_) => resistance = arg.get_quantity().unwrap(),
+                    _ => return Err(lexeme_line.diagnose(n, "Wrong argument given to resistor, optional: -nonlinear, required: -resistance".to_string()))
                 },
-                _ => error_out!("Wrong lexeme found in line {} for resistor. You can only pass arguments here.", line_number)
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for resistor. You can only pass arguments here.".to_string()))
             }
         }
         
-        if resistance == 0.0f64 {
-            error_out!("You either did not pass -resistance in line {} or you passed a resistance of 0.0, it's required that you revise.", line_number);
+        if resistance.get_value() == 0.0 {
+            return Err(Diagnostic::new(line_number, "You either did not pass -resistance or you passed a resistance of 0.0, it's required that you revise.".to_string()));
         }
 
 
-        Self { nonlinear, resistance }
+        Ok(Self { nonlinear, resistance })
+    }
+
+    pub fn get_resistance(&self) -> Quantity {
+        self.resistance
+    }
+
+    pub fn is_nonlinear(&self) -> bool {
+        self.nonlinear
     }
 }
 
 pub struct Capacitor {
     nonlinear: bool,
     dynamic: bool,
-    capacitance: f64,
+    capacitance: Quantity,
 }
 
 impl Capacitor {
-    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Self {
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
         let mut nonlinear = false;
         let mut dynamic = false;
-        let mut capacitance = 0.0f64;
+        let mut capacitance = Quantity::from(0.0, Dimension::FARAD);
 
-        for ll in lexeme_line {
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
             match ll {
                 Lexeme::Arg(arg) => match arg {
                     Argument::Nonlinear => nonlinear = true,
                     Argument::Dynamic => dynamic = true,
-                    Argument::Capacitance(unit) => capacitance = unit.get_corresponding_value(),
-                    _ => error_out!("Wrong argument given to capacitor in line {}, optional: -dynamic -nonlinear, required: -capacitance", line_number)
+                    Argument::Capacitance(_) => capacitance = arg.get_quantity().unwrap(),
+                    _ => return Err(lexeme_line.diagnose(n, "Wrong argument given to capacitor, optional: -dynamic -nonlinear, required: -capacitance".to_string()))
                 },
-                _ => error_out!("Wrong lexeme found in line {} for capacitor. You can only pass arguments here.", line_number)
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for capacitor. You can only pass arguments here.".to_string()))
             }
         }
         
-        if capacitance == 0.0f64 {
-            error_out!("You either did not pass -capacitance in line {} or you passed a capacitance of 0.0, it's required that you revise.", line_number);
+        if capacitance.get_value() == 0.0 {
+            return Err(Diagnostic::new(line_number, "You either did not pass -capacitance or you passed a capacitance of 0.0, it's required that you revise.".to_string()));
         }
 
 
-        Self { nonlinear, dynamic, capacitance }
+        Ok(Self { nonlinear, dynamic, capacitance })
+    }
+
+    pub fn get_capacitance(&self) -> Quantity {
+        self.capacitance
+    }
+
+    pub fn is_nonlinear(&self) -> bool {
+        self.nonlinear
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic
     }
 }
 
@@ -67,33 +110,45 @@ impl Capacitor {
 pub struct Inductor {
     nonlinear: bool,
     dynamic: bool,
-    inductance: f64,
+    inductance: Quantity,
 }
 
 impl Inductor {
-    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Self {
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
         let mut nonlinear = false;
         let mut dynamic = false;
-        let mut inductance = 0.0f64;
+        let mut inductance = Quantity::from(0.0, Dimension::HENRY);
 
-        for ll in lexeme_line {
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
             match ll {
                 Lexeme::Arg(arg) => match arg {
                     Argument::Nonlinear => nonlinear = true,
                     Argument::Dynamic => dynamic = true,
-                    Argument::Inductance(unit) => inductance = unit.get_corresponding_value(),
-                    _ => error_out!("Wrong argument given to capacitor in line {}, optional: -dynamic -nonlinear, required: -inductance", line_number)
+                    Argument::Inductance(_) => inductance = arg.get_quantity().unwrap(),
+                    _ => return Err(lexeme_line.diagnose(n, "Wrong argument given to inductor, optional: -dynamic -nonlinear, required: -inductance".to_string()))
                 },
-                _ => error_out!("Wrong lexeme found in line {} for capacitor. You can only pass arguments here.", line_number),
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for inductor. You can only pass arguments here.".to_string())),
             }
         }
         
-        if inductance == 0.0f64 {
-            error_out!("You either did not pass -inductance in line {} or you passed an inductance of 0.0, it's required that you revise.", line_number);
+        if inductance.get_value() == 0.0 {
+            return Err(Diagnostic::new(line_number, "You either did not pass -inductance or you passed an inductance of 0.0, it's required that you revise.".to_string()));
         }
 
 
-        Self { nonlinear, dynamic, inductance }
+        Ok(Self { nonlinear, dynamic, inductance })
+    }
+
+    pub fn get_inductance(&self) -> Quantity {
+        self.inductance
+    }
+
+    pub fn is_nonlinear(&self) -> bool {
+        self.nonlinear
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic
     }
 }
 
@@ -104,146 +159,424 @@ pub enum TransistorType {
     MOSFET,
 }
 
+/// A transistor, a BJT with `-junction=npn` or `pnp` and a MOSFET with `-channel=n` or `p`. Its
+/// collector is `-in`, its emitter `-out` and its base `-base`.
+///
+/// A BJT follows the Gummel-Poon model: Ebers-Moll with the Early effect and high injection
+/// through the base charge, and base-emitter leakage. Whatever isn't given takes the default of
+/// SPICE, i.e. it is left out, and `-power` and `-voltage` are ratings that only get checked
+/// against.
 pub struct Transistor {
-    power: f64,
-    voltage: f64,
+    power: Quantity,
+    voltage: Quantity,
     junction_channel: JunctionChannel,
     trantype: TransistorType,
+    saturation_current: Quantity,
+    emission: f64,
+    beta: f64,
+    beta_reverse: f64,
+    early_voltage: Option<Quantity>,
+    early_voltage_reverse: Option<Quantity>,
+    knee_current: Option<Quantity>,
+    leakage_current: Quantity,
+    emitter_capacitance: Quantity,
+    collector_capacitance: Quantity,
+    transit_time: Quantity,
 }
 
 impl Transistor {
-    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Self {
-        let mut power = 0.0f64;
-        let mut voltage = 0.0f64;
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
+        let mut power = Quantity::from(0.0, Dimension::WATT);
+        let mut voltage = Quantity::from(0.0, Dimension::VOLT);
         let mut junction_channel = JunctionChannel::NPN;
         let mut trantype = TransistorType::BJT;
+        let mut saturation_current = Quantity::from(1e-16, Dimension::AMPERE);
+        let mut emission = 1.0;
+        let mut beta = 100.0;
+        let mut beta_reverse = 1.0;
+        let mut early_voltage = None;
+        let mut early_voltage_reverse = None;
+        let mut knee_current = None;
+        let mut leakage_current = Quantity::from(0.0, Dimension::AMPERE);
+        let mut emitter_capacitance = Quantity::from(0.0, Dimension::FARAD);
+        let mut collector_capacitance = Quantity::from(0.0, Dimension::FARAD);
+        let mut transit_time = Quantity::from(0.0, Dimension::SECOND);
 
-        for ll in lexeme_line {
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
             match ll {
                 Lexeme::Arg(arg) => match arg {
-                    Argument::Power(unit) => power = unit.get_corresponding_value(),
-                    Argument::Voltage(currentage) => match currentage {
-                        Currentage::Solo(unit) => voltage = unit.get_corresponding_value(),
-                        _ => error_out!("Line {}, you may not use dependent/controlling voltage markers for transistor voltage", line_number),
+                    Argument::Power(_) => power = arg.get_quantity().unwrap(),
+                    Argument::Voltage(ref currentage) => match currentage {
+                        Currentage::Solo(_) => voltage = arg.get_quantity().unwrap(),
+                        _ => return Err(lexeme_line.diagnose(n, "You may not use dependent/controlling voltage markers for transistor voltage".to_string())),
                     },
                     Argument::JunctionChannel(jc) => {
                         match jc {
                             JunctionChannel::NPN | JunctionChannel::PNP => trantype = TransistorType::BJT,
                             JunctionChannel::N | JunctionChannel::P => trantype = TransistorType::MOSFET,
-                            _ => error_out!("You may not use a junction type reserved for diodes for transistors, in line {}", line_number),
+                            _ => return Err(lexeme_line.diagnose(n, "You may not use a junction type reserved for diodes for transistors".to_string())),
                         }
 
                         
                         junction_channel = jc;                        
                     },
-                    _ => error_out!("Transistor in line {} got wrong type of argument", line_number),
+                    Argument::SaturationCurrent(_) => saturation_current = arg.get_quantity().unwrap(),
+                    Argument::Emission(_) => emission = arg.get_quantity().unwrap().get_value(),
+                    Argument::Beta(_) => beta = arg.get_quantity().unwrap().get_value(),
+                    Argument::BetaReverse(_) => beta_reverse = arg.get_quantity().unwrap().get_value(),
+                    Argument::EarlyVoltage(_) => early_voltage = arg.get_quantity(),
+                    Argument::EarlyVoltageReverse(_) => early_voltage_reverse = arg.get_quantity(),
+                    Argument::KneeCurrent(_) => knee_current = arg.get_quantity(),
+                    Argument::LeakageCurrent(_) => leakage_current = arg.get_quantity().unwrap(),
+                    Argument::EmitterCapacitance(_) => emitter_capacitance = arg.get_quantity().unwrap(),
+                    Argument::CollectorCapacitance(_) => collector_capacitance = arg.get_quantity().unwrap(),
+                    Argument::TransitTime(_) => transit_time = arg.get_quantity().unwrap(),
+                    _ => return Err(lexeme_line.diagnose(n, "Transistor got wrong type of argument".to_string())),
                 },
-                _ => error_out!("Wrong lexeme found in line {} for transistor. You can only pass arguments here.", line_number),
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for transistor. You can only pass arguments here.".to_string())),
             }
         }
 
-        if power == 0.0f64 {
-            error_out!("You either did not pass -power in line {} or you passed a power of 0.0, it's required that you revise.", line_number);
+        if saturation_current.get_value() <= 0.0 {
+            return Err(Diagnostic::new(line_number, "You passed a saturation current of 0.0 or less, it's required that you revise.".to_string()));
+        }
+
+
+        if emission <= 0.0 || beta <= 0.0 || beta_reverse <= 0.0 {
+            return Err(Diagnostic::new(line_number, "You passed an emission coefficient or beta of 0.0 or less, it's required that you revise.".to_string()));
+        }
+
+
+        if [early_voltage, early_voltage_reverse, knee_current].iter().flatten().any(|x| x.get_value() <= 0.0) {
+            return Err(Diagnostic::new(line_number, "You passed an Early voltage or knee current of 0.0 or less, it's required that you revise.".to_string()));
         }
 
 
-        if voltage == 0.0f64 {
-            error_out!("You either did not pass -voltage in line {} or you passed a voltage of 0.0, it's required that you revise.", line_number);
+        if [leakage_current, emitter_capacitance, collector_capacitance, transit_time].iter().any(|x| x.get_value() < 0.0) {
+            return Err(Diagnostic::new(line_number, "You passed a negative leakage current, capacitance or transit time, it's required that you revise.".to_string()));
+        }
+
+
+        Ok(Self {
+            power,
+            voltage,
+            junction_channel,
+            trantype,
+            saturation_current,
+            emission,
+            beta,
+            beta_reverse,
+            early_voltage,
+            early_voltage_reverse,
+            knee_current,
+            leakage_current,
+            emitter_capacitance,
+            collector_capacitance,
+            transit_time,
+        })
+    }
+
+    /// 1 for an NPN, -1 for a PNP, whose voltages and currents are those of an NPN reversed.
+    fn get_polarity(&self) -> f64 {
+        match self.junction_channel {
+            JunctionChannel::PNP => -1.0,
+            _ => 1.0,
+        }
+    }
+
+    /// The nets of the collector, the base and the emitter of a BJT.
+    fn get_nets(&self, placement: &Placement) -> Result<(usize, usize, usize), String> {
+        match (&self.trantype, placement.get_base()) {
+            (TransistorType::MOSFET, _) => Err(format!("'{}' has no model to be solved with yet", placement.get_name())),
+            (TransistorType::BJT, Some(base)) => Ok((placement.get_from(), base, placement.get_to())),
+            (TransistorType::BJT, None) => Err(format!("'{}' needs -base for the base of the transistor", placement.get_name())),
         }
+    }
+
+    /// The base-emitter and base-collector voltages, of an NPN, at `solution`.
+    fn get_junction_voltages(&self, placement: &Placement, solution: &MnaSolution) -> Result<(f64, f64), String> {
+        let (collector, base, emitter) = self.get_nets(placement)?;
+        let base_voltage = solution.get_voltage(base);
+
+        Ok((
+            self.get_polarity() * (base_voltage - solution.get_voltage(emitter)),
+            self.get_polarity() * (base_voltage - solution.get_voltage(collector)),
+        ))
+    }
+
+    /// n times the thermal voltage, the voltage the current grows e-fold over.
+    fn get_thermal_voltage(&self) -> f64 {
+        self.emission * THERMAL_VOLTAGE
+    }
+
+    /// The forward and the reverse diffusion currents of Ebers-Moll.
+    fn diffusion_currents(&self, base_emitter: f64, base_collector: f64) -> (f64, f64) {
+        let (saturation, thermal) = (self.saturation_current.get_value(), self.get_thermal_voltage());
+
+        (saturation * ((base_emitter / thermal).exp() - 1.0), saturation * ((base_collector / thermal).exp() - 1.0))
+    }
+
+    /// The charge in the base, normalised to that at no bias, which the current across it is
+    /// divided by. It grows with the Early effect and under high injection.
+    fn base_charge(&self, base_emitter: f64, base_collector: f64) -> f64 {
+        let (forward, _) = self.diffusion_currents(base_emitter, base_collector);
+
+        // Held above 0 so that a guess far past the Early voltage doesn't divide by 0 or flip the
+        // sign of the current.
+        let early = (1.0
+            - self.early_voltage.map_or(0.0, |x| base_collector / x.get_value())
+            - self.early_voltage_reverse.map_or(0.0, |x| base_emitter / x.get_value()))
+        .max(1e-3);
+        let injection = self.knee_current.map_or(0.0, |x| forward / x.get_value());
+
+        (1.0 + (1.0 + 4.0 * injection).max(0.0).sqrt()) / (2.0 * early)
+    }
+
+    /// The current into the collector for the base-emitter and base-collector voltages of an
+    /// NPN.
+    pub fn collector_current(&self, base_emitter: f64, base_collector: f64) -> f64 {
+        let (forward, reverse) = self.diffusion_currents(base_emitter, base_collector);
+
+        (forward - reverse) / self.base_charge(base_emitter, base_collector) - reverse / self.beta_reverse
+    }
+
+    /// The current into the base for the base-emitter and base-collector voltages of an NPN.
+    pub fn base_current(&self, base_emitter: f64, base_collector: f64) -> f64 {
+        let (forward, reverse) = self.diffusion_currents(base_emitter, base_collector);
+        let leakage = self.leakage_current.get_value()
+            * ((base_emitter / (LEAKAGE_EMISSION * THERMAL_VOLTAGE)).exp() - 1.0);
+
+        forward / self.beta + leakage + reverse / self.beta_reverse
+    }
+
+    /// The base-emitter and base-collector capacitances, depletion and diffusion alike.
+    pub fn junction_capacitances(&self, base_emitter: f64, base_collector: f64) -> (f64, f64) {
+        let diffusion = derivative(&|x| self.diffusion_currents(x, base_collector).0, base_emitter);
+
+        (
+            depletion_capacitance(self.emitter_capacitance.get_value(), base_emitter)
+                + self.transit_time.get_value() * diffusion,
+            depletion_capacitance(self.collector_capacitance.get_value(), base_collector),
+        )
+    }
+
+    /// Limits the step of a junction voltage from `old` to `new`.
+    fn limit_voltage(&self, new: f64, old: f64) -> f64 {
+        let thermal = self.get_thermal_voltage();
+        let critical = thermal * (thermal / (std::f64::consts::SQRT_2 * self.saturation_current.get_value())).ln();
+
+        limit_junction(new, old, thermal, critical)
+    }
+
+    /// Stamps the currents into the collector and the base, linearised around the junction
+    /// voltages given, each flowing out through the emitter. The offsets are left out for AC.
+    fn stamp_linearised(&self, system: &mut MnaSystem, placement: &Placement, base_emitter: f64, base_collector: f64, offsets: bool) -> Result<(), String> {
+        let (collector, base, emitter) = self.get_nets(placement)?;
+        let polarity = self.get_polarity();
+
+        for (terminal, is_collector) in [(collector, true), (base, false)] {
+            let current = |x, y| match is_collector {
+                true => self.collector_current(x, y),
+                false => self.base_current(x, y),
+            };
+
+            let by_emitter = derivative(&|x| current(x, base_collector), base_emitter);
+            let by_collector = derivative(&|x| current(base_emitter, x), base_collector);
 
+            system.stamp_transconductance(terminal, emitter, base, emitter, by_emitter);
+            system.stamp_transconductance(terminal, emitter, base, collector, by_collector);
 
-        Self { power, voltage, junction_channel, trantype }
+            if offsets {
+                let offset = current(base_emitter, base_collector) - by_emitter * base_emitter - by_collector * base_collector;
+                system.stamp_current_source(terminal, emitter, polarity * offset);
+            }
+        }
+
+        system.stamp_conductance(base, emitter, GMIN);
+        system.stamp_conductance(base, collector, GMIN);
+        Ok(())
     }
 }
 
+/// Shockley diode conducting from its anode to its cathode, i.e. from `-in` to `-out` with
+/// `-junction=pn` and the other way with `np`. Whatever isn't given takes the default of SPICE,
+/// `-power` and `-voltage` being ratings that only get checked against.
 pub struct Diode {
-    power: f64,
-    voltage: f64,
+    power: Quantity,
+    voltage: Quantity,
     junction: JunctionChannel,
+    saturation_current: Quantity,
+    emission: f64,
+    resistance: Quantity,
+    breakdown_voltage: Option<Quantity>,
+    capacitance: Quantity,
+    transit_time: Quantity,
 }
 
 impl Diode {
-    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Self {
-        let mut power = 0.0f64;
-        let mut voltage = 0.0f64;
-        let mut junction = JunctionChannel::NPN;
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
+        let mut power = Quantity::from(0.0, Dimension::WATT);
+        let mut voltage = Quantity::from(0.0, Dimension::VOLT);
+        let mut junction = JunctionChannel::PN;
+        let mut saturation_current = Quantity::from(1e-14, Dimension::AMPERE);
+        let mut emission = 1.0;
+        let mut resistance = Quantity::from(0.0, Dimension::OHM);
+        let mut breakdown_voltage = None;
+        let mut capacitance = Quantity::from(0.0, Dimension::FARAD);
+        let mut transit_time = Quantity::from(0.0, Dimension::SECOND);
 
-        for ll in lexeme_line {
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
             match ll {
                 Lexeme::Arg(arg) => match arg {
-                    Argument::Power(unit) => power = unit.get_corresponding_value(),
-                    Argument::Voltage(currentage) => match currentage {
-                        Currentage::Solo(unit) => voltage = unit.get_corresponding_value(),
-                        _ => error_out!("Line {}, you may not use dependent/controlling voltage markers for transistor voltage", line_number),
+                    Argument::Power(_) => power = arg.get_quantity().unwrap(),
+                    Argument::Voltage(ref currentage) => match currentage {
+                        Currentage::Solo(_) => voltage = arg.get_quantity().unwrap(),
+                        _ => return Err(lexeme_line.diagnose(n, "You may not use dependent/controlling voltage markers for transistor voltage".to_string())),
                     },
                     Argument::JunctionChannel(jc) => match jc {
                             JunctionChannel::PN | JunctionChannel::NP => junction = jc,
-                            _ => error_out!("You may not use a junction type reserved for transistors for a diode, in line {}", line_number),
+                            _ => return Err(lexeme_line.diagnose(n, "You may not use a junction type reserved for transistors for a diode".to_string())),
                     },
-                    _ => error_out!("Diode in line {} got wrong type of argument", line_number),
+                    Argument::SaturationCurrent(_) => saturation_current = arg.get_quantity().unwrap(),
+                    Argument::Emission(_) => emission = arg.get_quantity().unwrap().get_value(),
+                    Argument::Resistance(_) => resistance = arg.get_quantity().unwrap(),
+                    Argument::BreakdownVoltage(_) => breakdown_voltage = arg.get_quantity(),
+                    Argument::Capacitance(_) => capacitance = arg.get_quantity().unwrap(),
+                    Argument::TransitTime(_) => transit_time = arg.get_quantity().unwrap(),
+                    _ => return Err(lexeme_line.diagnose(n, "Diode got wrong type of argument, optional: -junction -saturation_current -emission -resistance -breakdown_voltage -capacitance -transit_time -power -voltage".to_string())),
                 },
-                _ => error_out!("Wrong lexeme found in line {} for transistor. You can only pass arguments here.", line_number),
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for diode. You can only pass arguments here.".to_string())),
             }
         }
 
-        if power == 0.0f64 {
-            error_out!("You either did not pass -power in line {} or you passed a power of 0.0, it's required that you revise.", line_number);
+        if saturation_current.get_value() <= 0.0 {
+            return Err(Diagnostic::new(line_number, "You passed a saturation current of 0.0 or less, it's required that you revise.".to_string()));
+        }
+
+
+        if emission <= 0.0 {
+            return Err(Diagnostic::new(line_number, "You passed an emission coefficient of 0.0 or less, it's required that you revise.".to_string()));
+        }
+
+
+        if breakdown_voltage.is_some_and(|x| x.get_value() <= 0.0) {
+            return Err(Diagnostic::new(line_number, "You passed a breakdown voltage of 0.0 or less, it's required that you revise.".to_string()));
+        }
+
+
+        if resistance.get_value() < 0.0 || capacitance.get_value() < 0.0 || transit_time.get_value() < 0.0 {
+            return Err(Diagnostic::new(line_number, "You passed a negative resistance, capacitance or transit time, it's required that you revise.".to_string()));
+        }
+
+
+        Ok(Self { power, voltage, junction, saturation_current, emission, resistance, breakdown_voltage, capacitance, transit_time })
+    }
+
+    /// The nets of the anode, of the anode side of the junction, which is inside the series
+    /// resistance when there is one, and of the cathode.
+    fn get_nets(&self, placement: &Placement) -> (usize, usize, usize) {
+        let (anode, cathode) = match self.junction {
+            JunctionChannel::NP => (placement.get_to(), placement.get_from()),
+            _ => (placement.get_from(), placement.get_to()),
+        };
+
+        (anode, placement.get_internal().first().cloned().unwrap_or(anode), cathode)
+    }
+
+    /// n times the thermal voltage, the voltage the current grows e-fold over.
+    fn get_thermal_voltage(&self) -> f64 {
+        self.emission * THERMAL_VOLTAGE
+    }
+
+    /// The current through the junction for the voltage across it, breakdown included.
+    pub fn current(&self, voltage: f64) -> f64 {
+        let (saturation, thermal) = (self.saturation_current.get_value(), self.get_thermal_voltage());
+        let forward = saturation * ((voltage / thermal).exp() - 1.0);
+
+        match self.breakdown_voltage {
+            Some(breakdown) => forward - saturation * (-(breakdown.get_value() + voltage) / thermal).exp(),
+            None => forward,
         }
+    }
 
+    /// The derivative of `current`.
+    pub fn conductance(&self, voltage: f64) -> f64 {
+        let (saturation, thermal) = (self.saturation_current.get_value(), self.get_thermal_voltage());
+        let forward = saturation / thermal * (voltage / thermal).exp();
 
-        if voltage == 0.0f64 {
-            error_out!("You either did not pass -voltage in line {} or you passed a voltage of 0.0, it's required that you revise.", line_number);
+        match self.breakdown_voltage {
+            Some(breakdown) => forward + saturation / thermal * (-(breakdown.get_value() + voltage) / thermal).exp(),
+            None => forward,
         }
+    }
+
+    /// The depletion capacitance of the junction plus the diffusion capacitance of the charge in
+    /// transit.
+    pub fn junction_capacitance(&self, voltage: f64) -> f64 {
+        depletion_capacitance(self.capacitance.get_value(), voltage) + self.transit_time.get_value() * self.conductance(voltage)
+    }
 
+    /// Limits the step of the junction voltage from `old` to `new`, in breakdown as well.
+    fn limit_voltage(&self, new: f64, old: f64) -> f64 {
+        let thermal = self.get_thermal_voltage();
+        let critical = thermal * (thermal / (std::f64::consts::SQRT_2 * self.saturation_current.get_value())).ln();
 
-        Self { power, voltage, junction }
+        match self.breakdown_voltage {
+            Some(breakdown) if new < (10.0 * thermal - breakdown.get_value()).min(0.0) => {
+                let breakdown = breakdown.get_value();
+                -limit_junction(-(new + breakdown), -(old + breakdown), thermal, critical) - breakdown
+            }
+            _ => limit_junction(new, old, thermal, critical),
+        }
     }
 }
 
 pub struct ACSweep {
-    freq: f64,
-    max_voltage: f64,
+    freq: Quantity,
+    max_voltage: Quantity,
 }
 
 impl ACSweep {
-    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Self {
-        let mut freq = 0.0f64;
-        let mut max_voltage = 0.0f64;
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
+        let mut freq = Quantity::from(0.0, Dimension::HERTZ);
+        let mut max_voltage = Quantity::from(0.0, Dimension::VOLT);
 
-        for ll in lexeme_line {
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
             match ll {
                 Lexeme::Arg(arg) => match arg {
-                    Argument::Frequency(unit) => freq = unit.get_corresponding_value(),
-                    Argument::MaxVoltage(unit) => max_voltage = unit.get_corresponding_value(),
-                    _ =>  error_out!("AC Sweep in line {} got wrong type of argument", line_number),
+                    Argument::Frequency(_) => freq = arg.get_quantity().unwrap(),
+                    // A solo -voltage is the amplitude too, as SPICE imports write it.
+                    Argument::MaxVoltage(_) | Argument::Voltage(Currentage::Solo(_)) => max_voltage = arg.get_quantity().unwrap(),
+                    _ =>  return Err(lexeme_line.diagnose(n, "AC Sweep got wrong type of argument".to_string())),
                 },
-                _ => error_out!("Wrong lexeme found in line {} for AC sweep. You can only pass arguments here.", line_number),
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for AC sweep. You can only pass arguments here.".to_string())),
             }
         }
 
-        if freq == 0.0f64 {
-            error_out!("You either did not pass -freq in line {} or you passed a frequency of 0.0, it's required that you revise.", line_number);
+        if freq.get_value() == 0.0 {
+            return Err(Diagnostic::new(line_number, "You either did not pass -freq or you passed a frequency of 0.0, it's required that you revise.".to_string()));
         }
 
 
-        if max_voltage == 0.0f64 {
-            error_out!("You either did not pass -max_voltage in line {} or you passed a voltage of 0.0, it's required that you revise.", line_number);
+        if max_voltage.get_value() == 0.0 {
+            return Err(Diagnostic::new(line_number, "You either did not pass -max_voltage or you passed a voltage of 0.0, it's required that you revise.".to_string()));
         }
 
-        Self { freq, max_voltage }
+        Ok(Self { freq, max_voltage })
     }
 }
 
 pub enum VoltAmps {
-    ParentAmps(f64),
-    ChildAmps(f64),
-    IndependentAmps(f64),
-    ParentVolts(f64),
-    ChildVolts(f64),
-    IndependentVolts(f64),
+    ParentAmps(Quantity),
+    ChildAmps(Quantity),
+    IndependentAmps(Quantity),
+    ParentVolts(Quantity),
+    ChildVolts(Quantity),
+    IndependentVolts(Quantity),
 }
 
 
+/// The pair of a controlled source is its child (controlled) then its parent (controlling).
 pub enum DCSource {
     Voltage(VoltAmps),
     Current(VoltAmps),
@@ -253,29 +586,120 @@ pub enum DCSource {
     VoltageByVoltage(VoltAmps, VoltAmps),
 }
 
+impl VoltAmps {
+    /// Wraps the quantity of a `-voltage` or `-current` argument, which has to be in volts or
+    /// amperes respectively.
+    fn from(wrap: fn(Quantity) -> Self, quantity: Quantity, dimension: Dimension) -> Result<Self, String> {
+        match quantity.get_dimension() == dimension {
+            true => Ok(wrap(quantity)),
+            false => Err(format!("DCSource was given {} where {} was expected", quantity.get_dimension(), dimension)),
+        }
+    }
+
+    pub fn get_quantity(&self) -> Quantity {
+        match self {
+            Self::ParentAmps(x)
+            | Self::ChildAmps(x)
+            | Self::IndependentAmps(x)
+            | Self::ParentVolts(x)
+            | Self::ChildVolts(x)
+            | Self::IndependentVolts(x) => *x,
+        }
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.get_quantity().get_value()
+    }
+}
+
 impl DCSource {
-    pub fn from(lexems: LexemeLine, line_number: usize) -> Self {
-        let count_arguments = lexems.count_arguments();
-
-        let self = match count_arguments {
-            0 => error_out!("DCSorce needs at least one solo voltage/current or a pair of parent/child voltages/currents in line {}", line_number),
-            1 => {
-                
-                let voltamps = for ll in lexems {
-                    match ll {
-                        Lexeme::Arg(arg) =>  match arg {
-                            Argument::Current(currentage) => {
-                                match currentage {
-                                    Currentage::Solo(unit) => break VoltAmps::IndependentAmps(unit.get_corresponding_value()),
-                                    _ => error_out!("When you have passed only one voltage/current, it must be independent at line {}", line_number),
-                                }
-                            },
-                            _ => error_out!("You can only pass voltage/current to DCSource at line {}", line_number),
-                        }
+    /// An independent source takes a single `-voltage` or `-current`. A controlled one takes a `*`
+    /// (controlling, parent) and a `^` (controlled, child) voltage or current, along with the
+    /// `-control` node the parent is of, and is named controlled by controlling, e.g. `-current^`
+    /// with `-voltage*` is `CurrentByVoltage`. Its gain is the child over the parent.
+    pub fn from(lexeme_line: LexemeLine, line_number: usize) -> Result<Self, Diagnostic> {
+        let mut independent = None;
+        let mut parent = None;
+        let mut child = None;
+        let mut control = false;
+
+        for (n, ll) in lexeme_line.get_lexemes().iter().cloned().enumerate() {
+            let quantity = match &ll {
+                Lexeme::Arg(arg) => arg.get_quantity(),
+                _ => None,
+            };
+
+            let (slot, wrap, dimension): (_, fn(Quantity) -> VoltAmps, _) = match ll {
+                Lexeme::Arg(arg) => match arg {
+                    Argument::Voltage(currentage) => match currentage {
+                        Currentage::Solo(_) => (&mut independent, VoltAmps::IndependentVolts, Dimension::VOLT),
+                        Currentage::Dom(_) => (&mut parent, VoltAmps::ParentVolts, Dimension::VOLT),
+                        Currentage::Sub(_) => (&mut child, VoltAmps::ChildVolts, Dimension::VOLT),
+                    },
+                    Argument::Current(currentage) => match currentage {
+                        Currentage::Solo(_) => (&mut independent, VoltAmps::IndependentAmps, Dimension::AMPERE),
+                        Currentage::Dom(_) => (&mut parent, VoltAmps::ParentAmps, Dimension::AMPERE),
+                        Currentage::Sub(_) => (&mut child, VoltAmps::ChildAmps, Dimension::AMPERE),
+                    },
+                    Argument::Control(_) => {
+                        control = true;
+                        continue;
                     }
-                };
+                    _ => return Err(lexeme_line.diagnose(n, "You can only pass voltage/current and -control to DCSource".to_string())),
+                },
+                _ => return Err(lexeme_line.diagnose(n, "Wrong lexeme found for DCSource. You can only pass arguments here.".to_string())),
+            };
+
+            let volt_amps = VoltAmps::from(wrap, quantity.unwrap(), dimension).map_err(|x| lexeme_line.diagnose(n, x))?;
+
+            if slot.replace(volt_amps).is_some() {
+                return Err(lexeme_line.diagnose(n, "DCSource was already given a voltage/current of this kind".to_string()));
             }
         }
+
+        match (independent, parent, child) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => Err(Diagnostic::new(
+                line_number,
+                "DCSource is either independent with one solo voltage/current or controlled with a parent/child pair, not both".to_string(),
+            )),
+            (Some(_), None, None) if control => Err(Diagnostic::new(
+                line_number,
+                "Only a controlled DCSource takes -control".to_string(),
+            )),
+            (Some(volt_amps @ VoltAmps::IndependentVolts(_)), None, None) => Ok(Self::Voltage(volt_amps)),
+            (Some(volt_amps), None, None) => Ok(Self::Current(volt_amps)),
+            (None, Some(_), Some(_)) if !control => Err(Diagnostic::new(
+                line_number,
+                "Controlled DCSource needs -control naming the node whose voltage/current controls it".to_string(),
+            )),
+            (None, Some(parent), Some(_)) if parent.get_value() == 0.0 => Err(Diagnostic::new(
+                line_number,
+                "You passed a controlling voltage/current of 0.0, the gain would be undefined, it's required that you revise.".to_string(),
+            )),
+            (None, Some(parent), Some(child)) => match (&child, &parent) {
+                (VoltAmps::ChildVolts(_), VoltAmps::ParentVolts(_)) => Ok(Self::VoltageByVoltage(child, parent)),
+                (VoltAmps::ChildVolts(_), _) => Ok(Self::VoltageByCurrent(child, parent)),
+                (_, VoltAmps::ParentVolts(_)) => Ok(Self::CurrentByVoltage(child, parent)),
+                _ => Ok(Self::CurrentByCurrent(child, parent)),
+            },
+            (None, None, None) => Err(Diagnostic::new(line_number, "DCSource needs at least one solo voltage/current or a pair of parent/child voltages/currents".to_string())),
+            (None, _, _) => Err(Diagnostic::new(
+                line_number,
+                "Controlled DCSource needs both a parent (*) and a child (^) voltage/current".to_string(),
+            )),
+        }
+    }
+
+    /// The child over the parent, e.g. the transconductance of `CurrentByVoltage` in siemens, a
+    /// plain 1.0 for independent sources.
+    pub fn get_gain(&self) -> Quantity {
+        match self {
+            Self::Voltage(_) | Self::Current(_) => Quantity::from(1.0, Dimension::NONE),
+            Self::CurrentByCurrent(child, parent)
+            | Self::CurrentByVoltage(child, parent)
+            | Self::VoltageByCurrent(child, parent)
+            | Self::VoltageByVoltage(child, parent) => child.get_quantity().divide(&parent.get_quantity()).unwrap(),
+        }
     }
 }
 
@@ -289,23 +713,1794 @@ pub enum Component {
     DCSource(DCSource),    
 }
 
+impl Component {
+    /// Builds the model of a component from the arguments it was given in the netlist.
+    pub fn from(component: &EelectroCircuitComponent) -> Result<Self, Diagnostic> {
+        match component {
+            EelectroCircuitComponent::Resistor(ll) => Resistor::from(ll.clone(), ll.get_line_number()).map(Self::Resistor),
+            EelectroCircuitComponent::Capacitor(ll) => Capacitor::from(ll.clone(), ll.get_line_number()).map(Self::Capacitor),
+            EelectroCircuitComponent::Inductor(ll) => Inductor::from(ll.clone(), ll.get_line_number()).map(Self::Inductor),
+            EelectroCircuitComponent::Transistor(ll) => Transistor::from(ll.clone(), ll.get_line_number()).map(Self::Transistor),
+            EelectroCircuitComponent::Diode(ll) => Diode::from(ll.clone(), ll.get_line_number()).map(Self::Diode),
+            EelectroCircuitComponent::ACSweep(ll) => ACSweep::from(ll.clone(), ll.get_line_number()).map(Self::ACSweep),
+            EelectroCircuitComponent::DCSource(ll) => DCSource::from(ll.clone(), ll.get_line_number()).map(Self::DCSource),
+            EelectroCircuitComponent::Init => Err(Diagnostic::new(0, "Component was never given an element".to_string())),
+        }
+    }
 
-pub enum Profile {
-    Named(String),
-    Default,
+    /// Whether the current through it is an unknown of the MNA system, which current controlled
+    /// sources need of the element controlling them.
+    fn has_current_row(&self) -> bool {
+        matches!(
+            self,
+            Self::Inductor(_)
+                | Self::ACSweep(_)
+                | Self::DCSource(DCSource::Voltage(_) | DCSource::VoltageByVoltage(_, _) | DCSource::VoltageByCurrent(_, _))
+        )
+    }
 }
 
+/// Where an element sits in the circuit: its name, which labels any current it adds as an
+/// unknown, and the nets its `-in`, `-out` and `-base` terminals are on. A controlled source
+/// also knows where the element controlling it sits, and elements with nets of their own, see
+/// `Stamp::count_internal_nets`, which those are.
+#[derive(Clone)]
+pub struct Placement {
+    name: String,
+    from: usize,
+    to: usize,
+    base: Option<usize>,
+    control: Option<Box<Placement>>,
+    internal: Vec<usize>,
+}
 
-pub struct  NodeProfile {
-    profile: Profile,
-    components: Vec<Component>,
+impl Placement {
+    pub fn new(name: &str, from: usize, to: usize, base: Option<usize>) -> Self {
+        Self { name: name.to_string(), from, to, base, control: None, internal: vec![] }
+    }
+
+    pub fn with_internal(mut self, internal: Vec<usize>) -> Self {
+        self.internal = internal;
+        self
+    }
+
+    pub fn with_control(mut self, control: Placement) -> Self {
+        self.control = Some(Box::new(control));
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_from(&self) -> usize {
+        self.from
+    }
+
+    pub fn get_to(&self) -> usize {
+        self.to
+    }
+
+    pub fn get_base(&self) -> Option<usize> {
+        self.base
+    }
+
+    pub fn get_control(&self) -> Option<&Placement> {
+        self.control.as_deref()
+    }
+
+    pub fn get_internal(&self) -> &Vec<usize> {
+        &self.internal
+    }
 }
 
-pub struct Node {
-    number: u32,
-    node_profiles: Vec<NodeProfile>,
-    ins: Vec<Connection>,
-    outs: Vec<Connection>,
-    bases: Option<Vec<Connection>>
+/// The derivative of `f` at `x`, by central differences.
+fn derivative(f: &dyn Fn(f64) -> f64, x: f64) -> f64 {
+    let h = 1e-6 * x.abs().max(1.0);
+
+    (f(x + h) - f(x - h)) / (2.0 * h)
+}
+
+/// How an element puts itself into the MNA system of each analysis. Every built-in component
+/// implements it, and so can models from other crates, see `Element`.
+///
+/// Only `stamp_type` is required, the stamps default to what the `ElementStampType` says the
+/// element is. Elements that aren't a relation between current and voltage, like sources, or
+/// that know their derivatives better, override them.
+pub trait Stamp {
+    /// How the current through the element relates to the voltages on its terminals.
+    fn stamp_type(&self) -> ElementStampType<'_>;
+
+    /// How many nets the element has inside it, e.g. between the series resistance and the
+    /// junction of a diode. They are numbered after those of the circuit.
+    fn count_internal_nets(&self) -> usize {
+        0
+    }
+
+    /// Whether direct current flows between the terminals of the element on its own, which is
+    /// what holds a net to ground.
+    fn is_dc_path(&self) -> bool {
+        true
+    }
+
+    /// How far the operating point goes past the ratings of the element, `None` while it stays
+    /// within them or has none.
+    fn check_ratings(&self, _placement: &Placement, _operating_point: &MnaSolution) -> Option<String> {
+        None
+    }
+
+    /// Pulls `guess` back towards `previous`, the voltages the element was last linearised
+    /// around, where a Newton-Raphson step went further than it can follow, e.g. up the
+    /// exponential of a junction.
+    fn limit(&self, _placement: &Placement, _previous: &MnaSolution, _guess: &mut MnaSolution) {}
+
+    /// Stamps the element for the DC operating point. Nonlinear elements are linearised around
+    /// all voltages being 0, which is where the Newton iterations start from.
+    fn stamp_dc(&self, system: &mut MnaSystem, placement: &Placement) -> Result<(), String> {
+        match self.stamp_type() {
+            ElementStampType::Linear(conductance) => {
+                system.stamp_conductance(placement.get_from(), placement.get_to(), conductance);
+                Ok(())
+            }
+            ElementStampType::NonLinear(_) | ElementStampType::MultiTerminalNonLinear(_) => {
+                self.stamp_jacobian(system, placement, &MnaSolution::new(system.get_net_count()))
+            }
+            ElementStampType::Dynamic(_) => Ok(()),
+            ElementStampType::MultiTerminalDyanmic(_) => {
+                Err(format!("'{}' has to give its own DC stamp", placement.get_name()))
+            }
+        }
+    }
+
+    /// Stamps the small-signal model at the angular frequency `omega`, linearised around the
+    /// operating point. Sources with no AC part are left at 0.
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, omega: f64, operating_point: &MnaSolution) -> Result<(), String> {
+        let (from, to) = (placement.get_from(), placement.get_to());
+
+        match self.stamp_type() {
+            ElementStampType::Linear(conductance) => system.stamp_conductance(from, to, conductance),
+            ElementStampType::NonLinear(current) => {
+                let voltage = operating_point.get_voltage(from) - operating_point.get_voltage(to);
+                system.stamp_conductance(from, to, derivative(&*current, voltage));
+            }
+            ElementStampType::Dynamic(capacitance) => {
+                let voltage = operating_point.get_voltage(from) - operating_point.get_voltage(to);
+                system.stamp_susceptance(from, to, omega * capacitance(voltage, 0));
+            }
+            ElementStampType::MultiTerminalNonLinear(current) => {
+                let base = placement.get_base().unwrap_or(to);
+                let (conductance, transconductance, _) = linearise_multi_terminal(&*current, from, to, base, operating_point);
+
+                system.stamp_conductance(from, to, conductance);
+                system.stamp_transconductance(from, to, base, to, transconductance);
+            }
+            ElementStampType::MultiTerminalDyanmic(_) => {
+                return Err(format!("'{}' has to give its own AC stamp", placement.get_name()))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stamps the companion model for the `k`th time step of `step` seconds, given the solution
+    /// of the step before. Storage elements are integrated by backward Euler, as a conductance
+    /// of C/h beside a source carrying the charge over.
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, step: f64, k: u64, previous: &MnaSolution) -> Result<(), String> {
+        let (from, to) = (placement.get_from(), placement.get_to());
+
+        match self.stamp_type() {
+            ElementStampType::Dynamic(capacitance) => {
+                let voltage = previous.get_voltage(from) - previous.get_voltage(to);
+                let conductance = capacitance(voltage, k) / step;
+
+                system.stamp_conductance(from, to, conductance);
+                system.stamp_current_source(from, to, -conductance * voltage);
+                Ok(())
+            }
+            ElementStampType::MultiTerminalDyanmic(_) => {
+                Err(format!("'{}' has to give its own transient stamp", placement.get_name()))
+            }
+            _ => self.stamp_jacobian(system, placement, previous),
+        }
+    }
+
+    /// Stamps the element linearised around `guess`, for one Newton-Raphson iteration: the
+    /// derivative of its current as a conductance, and what the line misses at `guess` as a
+    /// current source. Linear elements stamp as they do at DC.
+    fn stamp_jacobian(&self, system: &mut MnaSystem, placement: &Placement, guess: &MnaSolution) -> Result<(), String> {
+        let (from, to) = (placement.get_from(), placement.get_to());
+
+        match self.stamp_type() {
+            ElementStampType::NonLinear(current) => {
+                let voltage = guess.get_voltage(from) - guess.get_voltage(to);
+                let conductance = derivative(&*current, voltage);
+
+                system.stamp_conductance(from, to, conductance);
+                system.stamp_current_source(from, to, current(voltage) - conductance * voltage);
+                Ok(())
+            }
+            ElementStampType::MultiTerminalNonLinear(current) => {
+                let base = placement.get_base().unwrap_or(to);
+                let (conductance, transconductance, offset) = linearise_multi_terminal(&*current, from, to, base, guess);
+
+                system.stamp_conductance(from, to, conductance);
+                system.stamp_transconductance(from, to, base, to, transconductance);
+                system.stamp_current_source(from, to, offset);
+                Ok(())
+            }
+            ElementStampType::MultiTerminalDyanmic(_) => {
+                Err(format!("'{}' has to give its own Jacobian stamp", placement.get_name()))
+            }
+            _ => self.stamp_dc(system, placement),
+        }
+    }
+}
+
+/// The partial derivatives of a three terminal current by the voltages of `-in` and of `-base`
+/// against `-out`, along with the current the line misses at `guess`.
+fn linearise_multi_terminal(current: &dyn Fn(f64, f64) -> f64, from: usize, to: usize, base: usize, guess: &MnaSolution) -> (f64, f64, f64) {
+    let v_in = guess.get_voltage(from) - guess.get_voltage(to);
+    let v_base = guess.get_voltage(base) - guess.get_voltage(to);
+
+    let conductance = derivative(&|x| current(x, v_base), v_in);
+    let transconductance = derivative(&|x| current(v_in, x), v_base);
+
+    (conductance, transconductance, current(v_in, v_base) - conductance * v_in - transconductance * v_base)
+}
+
+/// The depletion capacitance of a junction with `zero_bias` at no bias, linearised past half its
+/// built-in potential as SPICE does.
+fn depletion_capacitance(zero_bias: f64, voltage: f64) -> f64 {
+    match voltage < DEPLETION_FACTOR * JUNCTION_POTENTIAL {
+        true => zero_bias / (1.0 - voltage / JUNCTION_POTENTIAL).powf(GRADING),
+        false => {
+            zero_bias / (1.0 - DEPLETION_FACTOR).powf(1.0 + GRADING)
+                * (1.0 - DEPLETION_FACTOR * (1.0 + GRADING) + GRADING * voltage / JUNCTION_POTENTIAL)
+        }
+    }
+}
+
+/// Limits the step of a junction voltage from `old` to `new` as SPICE does: past the `critical`
+/// voltage, where the current starts to shoot up, the step is taken on the current instead.
+fn limit_junction(new: f64, old: f64, thermal: f64, critical: f64) -> f64 {
+    if new <= critical || (new - old).abs() <= 2.0 * thermal {
+        return new;
+    }
+
+    match old > 0.0 {
+        true => match 1.0 + (new - old) / thermal {
+            arg if arg > 0.0 => old + thermal * arg.ln(),
+            _ => critical,
+        },
+        false => thermal * (new / thermal).ln(),
+    }
+}
+
+/// Compares the power an element dissipates and the voltage across it with its `-power` and
+/// `-voltage` ratings, a rating of 0.0 being one that wasn't given.
+fn exceeded_ratings(power: f64, voltage: f64, power_rating: Quantity, voltage_rating: Quantity) -> Option<String> {
+    let mut exceeded = vec![];
+
+    if power_rating.get_value() > 0.0 && power.abs() > power_rating.get_value() {
+        exceeded.push(format!(
+            "dissipates {} over its rating of {}",
+            Quantity::from(power.abs(), Dimension::WATT),
+            power_rating
+        ));
+    }
+
+    if voltage_rating.get_value() > 0.0 && voltage.abs() > voltage_rating.get_value() {
+        exceeded.push(format!(
+            "has {} across it over its rating of {}",
+            Quantity::from(voltage.abs(), Dimension::VOLT),
+            voltage_rating
+        ));
+    }
+
+    match exceeded.is_empty() {
+        true => None,
+        false => Some(exceeded.join(" and ")),
+    }
+}
+
+impl Stamp for Resistor {
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        ElementStampType::Linear(1.0 / self.resistance.get_value())
+    }
+}
+
+impl Stamp for Capacitor {
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        ElementStampType::Dynamic(Box::new(|_, _| self.capacitance.get_value()))
+    }
+
+    fn is_dc_path(&self) -> bool {
+        false
+    }
+}
+
+impl Stamp for Inductor {
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        ElementStampType::Dynamic(Box::new(|_, _| self.inductance.get_value()))
+    }
+
+    /// A short, i.e. a source of 0 V.
+    fn stamp_dc(&self, system: &mut MnaSystem, placement: &Placement) -> Result<(), String> {
+        system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), 0.0);
+        Ok(())
+    }
+
+    /// An impedance of jωL, in the row of its current.
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, omega: f64, _: &MnaSolution) -> Result<(), String> {
+        let k = system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), 0.0);
+        system.stamp_entry_imag(k, k, -omega * self.inductance.get_value());
+        Ok(())
+    }
+
+    /// By backward Euler, the voltage across it is L/h times the change in its current.
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, step: f64, _: u64, previous: &MnaSolution) -> Result<(), String> {
+        let resistance = self.inductance.get_value() / step;
+        let current = previous.get_current(placement.get_name()).unwrap_or(0.0);
+
+        let k = system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), -resistance * current);
+        system.stamp_entry(k, k, -resistance);
+        Ok(())
+    }
+}
+
+impl Stamp for Transistor {
+    /// The current into the collector of a BJT.
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        let polarity = self.get_polarity();
+
+        match self.trantype {
+            TransistorType::BJT => ElementStampType::MultiTerminalNonLinear(Box::new(move |x, y| {
+                polarity * self.collector_current(polarity * y, polarity * (y - x))
+            })),
+            TransistorType::MOSFET => ElementStampType::Linear(0.0),
+        }
+    }
+
+    /// The power goes into the collector and the base, the voltage rating is that from the
+    /// collector to the emitter.
+    fn check_ratings(&self, placement: &Placement, operating_point: &MnaSolution) -> Option<String> {
+        let (collector, base, emitter) = self.get_nets(placement).ok()?;
+        let (base_emitter, base_collector) = self.get_junction_voltages(placement, operating_point).ok()?;
+
+        let emitter_voltage = operating_point.get_voltage(emitter);
+        let collector_emitter = operating_point.get_voltage(collector) - emitter_voltage;
+        let power = collector_emitter * self.get_polarity() * self.collector_current(base_emitter, base_collector)
+            + (operating_point.get_voltage(base) - emitter_voltage) * self.get_polarity() * self.base_current(base_emitter, base_collector);
+
+        exceeded_ratings(power, collector_emitter, self.power, self.voltage)
+    }
+
+    fn limit(&self, placement: &Placement, previous: &MnaSolution, guess: &mut MnaSolution) {
+        let (Ok((collector, base, emitter)), Ok((old_emitter, old_collector))) =
+            (self.get_nets(placement), self.get_junction_voltages(placement, previous))
+        else {
+            return;
+        };
+
+        let polarity = self.get_polarity();
+
+        if let Ok((new, _)) = self.get_junction_voltages(placement, guess) {
+            let limited = self.limit_voltage(new, old_emitter);
+
+            match (limited == new, base) {
+                (true, _) => (),
+                (false, 0) => guess.modify_voltage(emitter, -polarity * limited),
+                (false, _) => guess.modify_voltage(base, guess.get_voltage(emitter) + polarity * limited),
+            }
+        }
+
+        if let Ok((_, new)) = self.get_junction_voltages(placement, guess) {
+            let limited = self.limit_voltage(new, old_collector);
 
-}
\ No newline at end of file
+            match (limited == new, collector) {
+                (true, _) => (),
+                (false, 0) => guess.modify_voltage(base, polarity * limited),
+                (false, _) => guess.modify_voltage(collector, guess.get_voltage(base) - polarity * limited),
+            }
+        }
+    }
+
+    fn stamp_dc(&self, system: &mut MnaSystem, placement: &Placement) -> Result<(), String> {
+        self.stamp_jacobian(system, placement, &MnaSolution::new(system.get_net_count()))
+    }
+
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, omega: f64, operating_point: &MnaSolution) -> Result<(), String> {
+        let (collector, base, emitter) = self.get_nets(placement)?;
+        let (base_emitter, base_collector) = self.get_junction_voltages(placement, operating_point)?;
+        let (emitter_capacitance, collector_capacitance) = self.junction_capacitances(base_emitter, base_collector);
+
+        system.stamp_susceptance(base, emitter, omega * emitter_capacitance);
+        system.stamp_susceptance(base, collector, omega * collector_capacitance);
+        self.stamp_linearised(system, placement, base_emitter, base_collector, false)
+    }
+
+    /// The transistor at the voltages of the step before, beside the companion models of the
+    /// capacitances of its junctions.
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, step: f64, _: u64, previous: &MnaSolution) -> Result<(), String> {
+        let (collector, base, emitter) = self.get_nets(placement)?;
+        let (base_emitter, base_collector) = self.get_junction_voltages(placement, previous)?;
+        let (emitter_capacitance, collector_capacitance) = self.junction_capacitances(base_emitter, base_collector);
+        let polarity = self.get_polarity();
+
+        for (other, capacitance, voltage) in [(emitter, emitter_capacitance, base_emitter), (collector, collector_capacitance, base_collector)] {
+            let conductance = capacitance / step;
+
+            system.stamp_conductance(base, other, conductance);
+            system.stamp_current_source(base, other, -conductance * polarity * voltage);
+        }
+
+        self.stamp_jacobian(system, placement, previous)
+    }
+
+    fn stamp_jacobian(&self, system: &mut MnaSystem, placement: &Placement, guess: &MnaSolution) -> Result<(), String> {
+        let (base_emitter, base_collector) = self.get_junction_voltages(placement, guess)?;
+        self.stamp_linearised(system, placement, base_emitter, base_collector, true)
+    }
+}
+
+impl Stamp for Diode {
+    /// Leaves out the series resistance.
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        match self.junction {
+            JunctionChannel::NP => ElementStampType::NonLinear(Box::new(|x| -self.current(-x))),
+            _ => ElementStampType::NonLinear(Box::new(|x| self.current(x))),
+        }
+    }
+
+    /// The power is the voltage from anode to cathode times the current through the junction.
+    fn check_ratings(&self, placement: &Placement, operating_point: &MnaSolution) -> Option<String> {
+        let (anode, junction, cathode) = self.get_nets(placement);
+        let voltage = operating_point.get_voltage(anode) - operating_point.get_voltage(cathode);
+        let current = self.current(operating_point.get_voltage(junction) - operating_point.get_voltage(cathode));
+
+        exceeded_ratings(voltage * current, voltage, self.power, self.voltage)
+    }
+
+    fn count_internal_nets(&self) -> usize {
+        usize::from(self.resistance.get_value() > 0.0)
+    }
+
+    fn limit(&self, placement: &Placement, previous: &MnaSolution, guess: &mut MnaSolution) {
+        let (_, anode, cathode) = self.get_nets(placement);
+        let new = guess.get_voltage(anode) - guess.get_voltage(cathode);
+        let limited = self.limit_voltage(new, previous.get_voltage(anode) - previous.get_voltage(cathode));
+
+        match (limited == new, anode) {
+            (true, _) => (),
+            (false, 0) => guess.modify_voltage(cathode, -limited),
+            (false, _) => guess.modify_voltage(anode, guess.get_voltage(cathode) + limited),
+        }
+    }
+
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, omega: f64, operating_point: &MnaSolution) -> Result<(), String> {
+        let (outside, anode, cathode) = self.get_nets(placement);
+        let voltage = operating_point.get_voltage(anode) - operating_point.get_voltage(cathode);
+
+        if outside != anode {
+            system.stamp_conductance(outside, anode, 1.0 / self.resistance.get_value());
+        }
+
+        system.stamp_conductance(anode, cathode, self.conductance(voltage) + GMIN);
+        system.stamp_susceptance(anode, cathode, omega * self.junction_capacitance(voltage));
+        Ok(())
+    }
+
+    /// The junction at the voltage of the step before, beside the companion model of its
+    /// capacitance.
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, step: f64, _: u64, previous: &MnaSolution) -> Result<(), String> {
+        let (_, anode, cathode) = self.get_nets(placement);
+        let voltage = previous.get_voltage(anode) - previous.get_voltage(cathode);
+        let conductance = self.junction_capacitance(voltage) / step;
+
+        system.stamp_conductance(anode, cathode, conductance);
+        system.stamp_current_source(anode, cathode, -conductance * voltage);
+        self.stamp_jacobian(system, placement, previous)
+    }
+
+    /// The junction linearised around `guess`, a conductance beside the current source making up
+    /// for where the tangent misses the exponential.
+    fn stamp_jacobian(&self, system: &mut MnaSystem, placement: &Placement, guess: &MnaSolution) -> Result<(), String> {
+        let (outside, anode, cathode) = self.get_nets(placement);
+        let voltage = guess.get_voltage(anode) - guess.get_voltage(cathode);
+        let conductance = self.conductance(voltage);
+
+        if outside != anode {
+            system.stamp_conductance(outside, anode, 1.0 / self.resistance.get_value());
+        }
+
+        system.stamp_conductance(anode, cathode, conductance + GMIN);
+        system.stamp_current_source(anode, cathode, self.current(voltage) - conductance * voltage);
+        Ok(())
+    }
+}
+
+impl Stamp for ACSweep {
+    /// A source has no conductance of its own.
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        ElementStampType::Linear(0.0)
+    }
+
+    /// A short, as the sine averages out to nothing.
+    fn stamp_dc(&self, system: &mut MnaSystem, placement: &Placement) -> Result<(), String> {
+        system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), 0.0);
+        Ok(())
+    }
+
+    /// The sweep is the stimulus of the small-signal analysis, at its amplitude.
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, _: f64, _: &MnaSolution) -> Result<(), String> {
+        system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), self.max_voltage.get_value());
+        Ok(())
+    }
+
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, step: f64, k: u64, _: &MnaSolution) -> Result<(), String> {
+        let time = step * k as f64;
+        let voltage = self.max_voltage.get_value() * (2.0 * std::f64::consts::PI * self.freq.get_value() * time).sin();
+
+        system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), voltage);
+        Ok(())
+    }
+}
+
+impl Stamp for DCSource {
+    /// A source has no conductance of its own.
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        ElementStampType::Linear(0.0)
+    }
+
+    /// Voltage sources hold their nets together, current sources don't.
+    fn is_dc_path(&self) -> bool {
+        matches!(self, Self::Voltage(_) | Self::VoltageByCurrent(_, _) | Self::VoltageByVoltage(_, _))
+    }
+
+    /// A controlled source is measured on the node controlling it: its voltage is that of its
+    /// `-out` over its `-in`, and its current the one solved for it, i.e. into its `-out`.
+    fn stamp_dc(&self, system: &mut MnaSystem, placement: &Placement) -> Result<(), String> {
+        let (name, from, to) = (placement.get_name(), placement.get_from(), placement.get_to());
+        let gain = self.get_gain().get_value();
+
+        let control = match self {
+            Self::Voltage(voltage) => {
+                system.stamp_voltage_source(name, from, to, voltage.get_value());
+                return Ok(());
+            }
+            Self::Current(current) => {
+                system.stamp_current_source(from, to, current.get_value());
+                return Ok(());
+            }
+            _ => match placement.get_control() {
+                Some(control) => control,
+                None => return Err(format!("'{}' is a controlled source with no node controlling it", name)),
+            },
+        };
+
+        match self {
+            Self::VoltageByVoltage(_, _) => {
+                let k = system.stamp_voltage_source(name, from, to, 0.0);
+
+                for (net, sign) in [(control.get_to(), -1.0), (control.get_from(), 1.0)] {
+                    if let Some(column) = MnaSystem::get_row(net) {
+                        system.stamp_entry(k, column, sign * gain);
+                    }
+                }
+            }
+            Self::VoltageByCurrent(_, _) => {
+                let k = system.stamp_voltage_source(name, from, to, 0.0);
+                system.stamp_current_entry(k, control.get_name(), -gain);
+            }
+            Self::CurrentByVoltage(_, _) => {
+                system.stamp_transconductance(from, to, control.get_to(), control.get_from(), gain)
+            }
+            _ => {
+                for (net, sign) in [(to, -1.0), (from, 1.0)] {
+                    if let Some(row) = MnaSystem::get_row(net) {
+                        system.stamp_current_entry(row, control.get_name(), sign * gain);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// An independent voltage source is a short in the small-signal model, a current source
+    /// open. Controlled sources are linear and stay as they are.
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, _: f64, _: &MnaSolution) -> Result<(), String> {
+        match self {
+            Self::Voltage(_) => {
+                system.stamp_voltage_source(placement.get_name(), placement.get_from(), placement.get_to(), 0.0);
+            }
+            Self::Current(_) => (),
+            _ => return self.stamp_dc(system, placement),
+        }
+
+        Ok(())
+    }
+
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, _: f64, _: u64, _: &MnaSolution) -> Result<(), String> {
+        self.stamp_dc(system, placement)
+    }
+}
+
+impl Stamp for Component {
+    fn stamp_type(&self) -> ElementStampType<'_> {
+        match self {
+            Self::Resistor(x) => x.stamp_type(),
+            Self::Capacitor(x) => x.stamp_type(),
+            Self::Inductor(x) => x.stamp_type(),
+            Self::Transistor(x) => x.stamp_type(),
+            Self::Diode(x) => x.stamp_type(),
+            Self::ACSweep(x) => x.stamp_type(),
+            Self::DCSource(x) => x.stamp_type(),
+        }
+    }
+
+    fn check_ratings(&self, placement: &Placement, operating_point: &MnaSolution) -> Option<String> {
+        match self {
+            Self::Resistor(x) => x.check_ratings(placement, operating_point),
+            Self::Capacitor(x) => x.check_ratings(placement, operating_point),
+            Self::Inductor(x) => x.check_ratings(placement, operating_point),
+            Self::Transistor(x) => x.check_ratings(placement, operating_point),
+            Self::Diode(x) => x.check_ratings(placement, operating_point),
+            Self::ACSweep(x) => x.check_ratings(placement, operating_point),
+            Self::DCSource(x) => x.check_ratings(placement, operating_point),
+        }
+    }
+
+    fn is_dc_path(&self) -> bool {
+        match self {
+            Self::Resistor(x) => x.is_dc_path(),
+            Self::Capacitor(x) => x.is_dc_path(),
+            Self::Inductor(x) => x.is_dc_path(),
+            Self::Transistor(x) => x.is_dc_path(),
+            Self::Diode(x) => x.is_dc_path(),
+            Self::ACSweep(x) => x.is_dc_path(),
+            Self::DCSource(x) => x.is_dc_path(),
+        }
+    }
+
+    fn count_internal_nets(&self) -> usize {
+        match self {
+            Self::Resistor(x) => x.count_internal_nets(),
+            Self::Capacitor(x) => x.count_internal_nets(),
+            Self::Inductor(x) => x.count_internal_nets(),
+            Self::Transistor(x) => x.count_internal_nets(),
+            Self::Diode(x) => x.count_internal_nets(),
+            Self::ACSweep(x) => x.count_internal_nets(),
+            Self::DCSource(x) => x.count_internal_nets(),
+        }
+    }
+
+    fn limit(&self, placement: &Placement, previous: &MnaSolution, guess: &mut MnaSolution) {
+        match self {
+            Self::Resistor(x) => x.limit(placement, previous, guess),
+            Self::Capacitor(x) => x.limit(placement, previous, guess),
+            Self::Inductor(x) => x.limit(placement, previous, guess),
+            Self::Transistor(x) => x.limit(placement, previous, guess),
+            Self::Diode(x) => x.limit(placement, previous, guess),
+            Self::ACSweep(x) => x.limit(placement, previous, guess),
+            Self::DCSource(x) => x.limit(placement, previous, guess),
+        }
+    }
+
+    fn stamp_dc(&self, system: &mut MnaSystem, placement: &Placement) -> Result<(), String> {
+        match self {
+            Self::Resistor(x) => x.stamp_dc(system, placement),
+            Self::Capacitor(x) => x.stamp_dc(system, placement),
+            Self::Inductor(x) => x.stamp_dc(system, placement),
+            Self::Transistor(x) => x.stamp_dc(system, placement),
+            Self::Diode(x) => x.stamp_dc(system, placement),
+            Self::ACSweep(x) => x.stamp_dc(system, placement),
+            Self::DCSource(x) => x.stamp_dc(system, placement),
+        }
+    }
+
+    fn stamp_ac(&self, system: &mut MnaSystem, placement: &Placement, omega: f64, operating_point: &MnaSolution) -> Result<(), String> {
+        match self {
+            Self::Resistor(x) => x.stamp_ac(system, placement, omega, operating_point),
+            Self::Capacitor(x) => x.stamp_ac(system, placement, omega, operating_point),
+            Self::Inductor(x) => x.stamp_ac(system, placement, omega, operating_point),
+            Self::Transistor(x) => x.stamp_ac(system, placement, omega, operating_point),
+            Self::Diode(x) => x.stamp_ac(system, placement, omega, operating_point),
+            Self::ACSweep(x) => x.stamp_ac(system, placement, omega, operating_point),
+            Self::DCSource(x) => x.stamp_ac(system, placement, omega, operating_point),
+        }
+    }
+
+    fn stamp_transient(&self, system: &mut MnaSystem, placement: &Placement, step: f64, k: u64, previous: &MnaSolution) -> Result<(), String> {
+        match self {
+            Self::Resistor(x) => x.stamp_transient(system, placement, step, k, previous),
+            Self::Capacitor(x) => x.stamp_transient(system, placement, step, k, previous),
+            Self::Inductor(x) => x.stamp_transient(system, placement, step, k, previous),
+            Self::Transistor(x) => x.stamp_transient(system, placement, step, k, previous),
+            Self::Diode(x) => x.stamp_transient(system, placement, step, k, previous),
+            Self::ACSweep(x) => x.stamp_transient(system, placement, step, k, previous),
+            Self::DCSource(x) => x.stamp_transient(system, placement, step, k, previous),
+        }
+    }
+
+    fn stamp_jacobian(&self, system: &mut MnaSystem, placement: &Placement, guess: &MnaSolution) -> Result<(), String> {
+        match self {
+            Self::Resistor(x) => x.stamp_jacobian(system, placement, guess),
+            Self::Capacitor(x) => x.stamp_jacobian(system, placement, guess),
+            Self::Inductor(x) => x.stamp_jacobian(system, placement, guess),
+            Self::Transistor(x) => x.stamp_jacobian(system, placement, guess),
+            Self::Diode(x) => x.stamp_jacobian(system, placement, guess),
+            Self::ACSweep(x) => x.stamp_jacobian(system, placement, guess),
+            Self::DCSource(x) => x.stamp_jacobian(system, placement, guess),
+        }
+    }
+}
+
+/// An element of the circuit to be stamped: where it sits, the line it was declared on and its
+/// model. `from` gives every branch of a net graph its built-in model, any of which can then be
+/// swapped for another with `modify_model`, and `new` places models the netlist can't name.
+/// Internal nets are numbered after those of the graph, in the order of the elements.
+pub struct Element {
+    placement: Placement,
+    line_number: usize,
+    model: Box<dyn Stamp + Send + Sync>,
+}
+
+impl Element {
+    pub fn new(placement: Placement, line_number: usize, model: Box<dyn Stamp + Send + Sync>) -> Self {
+        Self { placement, line_number, model }
+    }
+
+    pub fn from(graph: &NetGraph) -> Result<Vec<Self>, ParseError> {
+        let mut elements = vec![];
+        let mut diagnostics = vec![];
+
+        let mut components = vec![];
+
+        for branch in graph.get_branches() {
+            match Component::from(branch.get_component()) {
+                Ok(component) => components.push(Some(component)),
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    components.push(None);
+                }
+            }
+        }
+
+        // The branch of a node standing for it as a control is the one with its current as an
+        // unknown, all of them carry the same current.
+        let control = |node: usize| {
+            let from = graph.get_terminal_net(node, Terminal::In)?;
+            let to = graph.get_terminal_net(node, Terminal::Out)?;
+            let branches = graph.get_branches().iter().zip(components.iter()).filter(|(x, _)| x.get_node() == node);
+
+            let branch = branches
+                .clone()
+                .find(|(_, x)| x.as_ref().is_some_and(|x| x.has_current_row()))
+                .or(branches.clone().next())?;
+
+            Some(Placement::new(branch.0.get_name(), from, to, None))
+        };
+
+        let mut placements = vec![];
+        let mut next_net = graph.get_net_count();
+
+        for (branch, component) in graph.get_branches().iter().zip(components.iter()) {
+            let count = component.as_ref().map_or(0, |x| x.count_internal_nets());
+            let placement = Placement::new(branch.get_name(), branch.get_from(), branch.get_to(), branch.get_base())
+                .with_internal((next_net..next_net + count).collect());
+
+            next_net += count;
+
+            match branch.get_control().map(control) {
+                Some(Some(control)) => placements.push(Some(placement.with_control(control))),
+                Some(None) => {
+                    diagnostics.push(Diagnostic::new(
+                        branch.get_line_number(),
+                        format!("'{}' is controlled by a node no component is on", branch.get_name()),
+                    ));
+                    placements.push(None);
+                }
+                None => placements.push(Some(placement)),
+            }
+        }
+
+        for ((branch, placement), component) in graph.get_branches().iter().zip(placements).zip(components) {
+            if let (Some(placement), Some(component)) = (placement, component) {
+                elements.push(Self::new(placement, branch.get_line_number(), Box::new(component)));
+            }
+        }
+
+        match diagnostics.is_empty() {
+            true => Ok(elements),
+            false => {
+                diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+                Err(ParseError::new(diagnostics))
+            }
+        }
+    }
+
+    pub fn get_placement(&self) -> &Placement {
+        &self.placement
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number
+    }
+
+    pub fn get_model(&self) -> &(dyn Stamp + Send + Sync) {
+        &*self.model
+    }
+
+    pub fn modify_model(&mut self, model: Box<dyn Stamp + Send + Sync>) {
+        self.model = model;
+    }
+}
+
+
+pub enum Profile {
+    Named(String),
+    Default,
+}
+
+
+pub struct  NodeProfile {
+    profile: Profile,
+    components: Vec<Component>,
+}
+
+impl NodeProfile {
+    pub fn get_profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn get_components(&self) -> &Vec<Component> {
+        &self.components
+    }
+}
+
+pub struct Node {
+    number: u32,
+    node_profiles: Vec<NodeProfile>,
+    ins: Vec<Connection>,
+    outs: Vec<Connection>,
+    bases: Option<Vec<Connection>>
+
+}
+
+impl Node {
+    pub fn get_number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn get_node_profiles(&self) -> &Vec<NodeProfile> {
+        &self.node_profiles
+    }
+
+    pub fn get_ins(&self) -> &Vec<Connection> {
+        &self.ins
+    }
+
+    pub fn get_outs(&self) -> &Vec<Connection> {
+        &self.outs
+    }
+
+    pub fn get_bases(&self) -> Option<&Vec<Connection>> {
+        self.bases.as_ref()
+    }
+}
+
+/// How a branch of the net graph behaves at DC, as far as the topology checks care.
+#[derive(Clone, Copy, PartialEq)]
+enum BranchKind {
+    Conductive,
+    VoltageSource,
+    Inductor,
+    CurrentSource,
+    Capacitor,
+}
+
+impl BranchKind {
+    fn from(component: &EelectroCircuitComponent) -> Self {
+        let arguments = component.get_arguments().map(|x| x.get_args()).unwrap_or_default();
+
+        // A controlled source is the kind of its controlled (or only) quantity.
+        let drives = |voltage: bool| {
+            arguments.iter().any(|x| {
+                matches!(
+                    (x, voltage),
+                    (Argument::Voltage(Currentage::Solo(_) | Currentage::Sub(_)), true)
+                        | (Argument::Current(Currentage::Solo(_) | Currentage::Sub(_)), false)
+                )
+            })
+        };
+
+        match component {
+            EelectroCircuitComponent::Inductor(_) => Self::Inductor,
+            EelectroCircuitComponent::Capacitor(_) => Self::Capacitor,
+            EelectroCircuitComponent::DCSource(_) | EelectroCircuitComponent::ACSweep(_) => match (drives(true), drives(false)) {
+                (true, _) => Self::VoltageSource,
+                (false, true) => Self::CurrentSource,
+                _ => Self::Conductive,
+            },
+            _ => Self::Conductive,
+        }
+    }
+
+    /// Whether direct current can flow through the branch on its own.
+    fn is_dc_path(&self) -> bool {
+        matches!(self, Self::Conductive | Self::VoltageSource | Self::Inductor)
+    }
+}
+
+/// Labels every net with the connected part of the graph it belongs to, given the edges.
+fn connected_parts(net_count: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut parent = (0..net_count).collect::<Vec<usize>>();
+
+    fn find(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+
+        x
+    }
+
+    for (a, b) in edges {
+        let (a, b) = (find(&mut parent, *a), find(&mut parent, *b));
+        parent[a] = b;
+    }
+
+    (0..net_count).map(|x| find(&mut parent, x)).collect()
+}
+
+/// Checks the topology of a circuit before it is solved, since each of these leaves the MNA
+/// matrix singular:
+///
+/// - components shorted to themselves, with both ends on one net
+/// - loops made only of voltage sources and inductors
+/// - nets that current sources drive with no DC path to take the current away
+///
+/// Nets without a DC path to ground, e.g. only reached through capacitors, are held there by a
+/// GMIN shunt when the system is stamped and come back as warnings.
+pub fn check_topology(graph: &NetGraph) -> Result<Vec<Diagnostic>, ParseError> {
+    let branches = graph.get_branches();
+    let kinds = branches.iter().map(|x| BranchKind::from(x.get_component())).collect::<Vec<BranchKind>>();
+    let mut diagnostics = vec![];
+
+    for branch in branches.iter() {
+        if branch.get_from() == branch.get_to() {
+            diagnostics.push(Diagnostic::new(
+                branch.get_line_number(),
+                format!("'{}' is shorted, both of its ends are on net {}", branch.get_name(), branch.get_from()),
+            ));
+        }
+    }
+
+    // A voltage source or inductor whose ends the others already connect closes a loop.
+    let mut loop_edges = vec![];
+
+    for (branch, kind) in branches.iter().zip(kinds.iter()) {
+        if !matches!(kind, BranchKind::VoltageSource | BranchKind::Inductor) || branch.get_from() == branch.get_to() {
+            continue;
+        }
+
+        let parts = connected_parts(graph.get_net_count(), &loop_edges);
+
+        match parts[branch.get_from()] == parts[branch.get_to()] {
+            true => diagnostics.push(Diagnostic::new(
+                branch.get_line_number(),
+                format!("'{}' closes a loop made only of voltage sources and inductors", branch.get_name()),
+            )),
+            false => loop_edges.push((branch.get_from(), branch.get_to())),
+        }
+    }
+
+    // Nets cut off from ground once capacitors and current sources are taken out.
+    let mut dc_edges = vec![];
+
+    for (branch, kind) in branches.iter().zip(kinds.iter()) {
+        if kind.is_dc_path() {
+            dc_edges.push((branch.get_from(), branch.get_to()));
+
+            if let Some(base) = branch.get_base() {
+                dc_edges.push((base, branch.get_to()));
+            }
+        }
+    }
+
+    let parts = connected_parts(graph.get_net_count(), &dc_edges);
+    let mut reported = vec![];
+    let mut warnings = vec![];
+
+    for net in 1..graph.get_net_count() {
+        let part = parts[net];
+
+        if part == parts[0] || reported.contains(&part) {
+            continue;
+        }
+
+        reported.push(part);
+
+        let nets = (1..graph.get_net_count()).filter(|x| parts[*x] == part).collect::<Vec<usize>>();
+        let touching = branches
+            .iter()
+            .zip(kinds.iter())
+            .filter(|(x, _)| {
+                nets.contains(&x.get_from()) || nets.contains(&x.get_to()) || x.get_base().is_some_and(|x| nets.contains(&x))
+            })
+            .collect::<Vec<(&NetBranch, &BranchKind)>>();
+
+        let line_number = touching.first().map_or(0, |(x, _)| x.get_line_number());
+        let nets = match nets.len() {
+            1 => format!("Net {}", nets[0]),
+            _ => format!("Nets {}", nets.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", ")),
+        };
+
+        let sources = touching
+            .iter()
+            .filter(|(_, kind)| **kind == BranchKind::CurrentSource)
+            .map(|(x, _)| x.get_name())
+            .collect::<Vec<&str>>();
+
+        match sources.is_empty() {
+            true => warnings.push(
+                Diagnostic::new(line_number, format!("{} without a DC path to ground, held there by GMIN", nets)).as_warning(),
+            ),
+            false => diagnostics.push(Diagnostic::new(
+                line_number,
+                format!(
+                    "{} cut off by current sources and capacitors only, the current of {} has nowhere to go",
+                    nets,
+                    sources.join(", ")
+                ),
+            )),
+        }
+    }
+
+    match diagnostics.is_empty() {
+        true => {
+            warnings.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+            Ok(warnings)
+        }
+        false => {
+            diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+            Err(ParseError::new(diagnostics))
+        }
+    }
+}
+
+/// Checks the elements with `-power` and `-voltage` ratings against the operating point, handing
+/// back a warning for every one that goes past them.
+pub fn check_ratings(graph: &NetGraph, operating_point: &MnaSolution) -> Result<Vec<Diagnostic>, ParseError> {
+    let warnings = Element::from(graph)?
+        .iter()
+        .filter_map(|x| {
+            x.get_model().check_ratings(x.get_placement(), operating_point).map(|exceeded| {
+                Diagnostic::new(x.get_line_number(), format!("'{}' {}", x.get_placement().get_name(), exceeded)).as_warning()
+            })
+        })
+        .collect();
+
+    Ok(warnings)
+}
+
+/// The Modified Nodal Analysis system of a circuit. There is a row for every net but ground,
+/// which is the reference, then one for the current through every voltage source and inductor,
+/// as those can't be written as a conductance.
+///
+/// ```text
+/// | G  B | | v |   | i |
+/// | C  0 | | j | = | e |
+/// ```
+///
+/// Which stamps of the elements go in makes it the system of one analysis, see `dc`, `ac`,
+/// `transient` and `jacobian`. Only the small-signal system has an imaginary part.
+pub struct MnaSystem {
+    coeffs: Vec<Vec<f64>>,
+    coeffs_imag: Vec<Vec<f64>>,
+    rhs: Vec<f64>,
+    net_count: usize,
+    currents: Vec<(String, usize)>,
+    current_entries: Vec<(usize, String, f64)>,
+}
+
+impl MnaSystem {
+    /// An empty system, with a row for every net but ground.
+    pub fn new(net_count: usize) -> Self {
+        let size = net_count.saturating_sub(1);
+
+        Self {
+            coeffs: vec![vec![0.0; size]; size],
+            coeffs_imag: vec![vec![0.0; size]; size],
+            rhs: vec![0.0; size],
+            net_count,
+            currents: vec![],
+            current_entries: vec![],
+        }
+    }
+
+    /// The system at the DC operating point, with the built-in model of every branch. At DC
+    /// capacitors are open and inductors and AC sources are shorts, i.e. sources of 0 V.
+    pub fn from(graph: &NetGraph) -> Result<Self, ParseError> {
+        Self::dc(graph.get_net_count(), &Element::from(graph)?)
+    }
+
+    pub fn dc(net_count: usize, elements: &[Element]) -> Result<Self, ParseError> {
+        Self::stamp_all(net_count, elements, |x, system| x.get_model().stamp_dc(system, x.get_placement()))
+    }
+
+    pub fn ac(net_count: usize, elements: &[Element], omega: f64, operating_point: &MnaSolution) -> Result<Self, ParseError> {
+        Self::stamp_all(net_count, elements, |x, system| {
+            x.get_model().stamp_ac(system, x.get_placement(), omega, operating_point)
+        })
+    }
+
+    pub fn transient(net_count: usize, elements: &[Element], step: f64, k: u64, previous: &MnaSolution) -> Result<Self, ParseError> {
+        Self::stamp_all(net_count, elements, |x, system| {
+            x.get_model().stamp_transient(system, x.get_placement(), step, k, previous)
+        })
+    }
+
+    pub fn jacobian(net_count: usize, elements: &[Element], guess: &MnaSolution) -> Result<Self, ParseError> {
+        Self::stamp_all(net_count, elements, |x, system| x.get_model().stamp_jacobian(system, x.get_placement(), guess))
+    }
+
+    fn stamp_all<F>(net_count: usize, elements: &[Element], stamp: F) -> Result<Self, ParseError>
+    where
+        F: Fn(&Element, &mut Self) -> Result<(), String>,
+    {
+        let net_count = elements
+            .iter()
+            .flat_map(|x| x.get_placement().get_internal())
+            .fold(net_count, |count, x| count.max(x + 1));
+
+        let mut system = Self::new(net_count);
+        let mut diagnostics = vec![];
+        let mut stamped_by = vec![];
+
+        for element in elements {
+            if let Err(message) = stamp(element, &mut system) {
+                diagnostics.push(Diagnostic::new(element.get_line_number(), message));
+            }
+
+            stamped_by.resize(system.current_entries.len(), element);
+        }
+
+        // Nets with no DC path to ground, e.g. only reached through capacitors, would leave the
+        // matrix singular, so they are held there by GMIN as in SPICE.
+        let mut dc_edges = vec![];
+
+        for element in elements.iter().filter(|x| x.get_model().is_dc_path()) {
+            let placement = element.get_placement();
+            dc_edges.push((placement.get_from(), placement.get_to()));
+            dc_edges.extend(placement.get_base().map(|x| (x, placement.get_to())));
+            dc_edges.extend(placement.get_internal().iter().map(|x| (*x, placement.get_from())));
+        }
+
+        let parts = connected_parts(net_count, &dc_edges);
+
+        for net in (1..net_count).filter(|x| parts[*x] != parts[0]) {
+            system.stamp_conductance(net, 0, GMIN);
+        }
+
+        // Every current has its row now.
+        for ((row, name, value), element) in system.current_entries.drain(..).collect::<Vec<_>>().into_iter().zip(stamped_by) {
+            match system.currents.iter().find(|(x, _)| *x == name) {
+                Some((_, column)) => system.coeffs[row][*column] += value,
+                None => diagnostics.push(Diagnostic::new(
+                    element.get_line_number(),
+                    format!(
+                        "'{}' is controlled by the current through '{}', which has to be a voltage source or inductor",
+                        element.get_placement().get_name(),
+                        name
+                    ),
+                )),
+            }
+        }
+
+        match diagnostics.is_empty() {
+            true => Ok(system),
+            false => {
+                diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+                Err(ParseError::new(diagnostics))
+            }
+        }
+    }
+
+    /// The row of a net, ground having none.
+    pub fn get_row(net: usize) -> Option<usize> {
+        net.checked_sub(1)
+    }
+
+    /// Adds a row and a column for the current through `name` as a new unknown, returning its
+    /// index.
+    pub fn add_row(&mut self, name: &str) -> usize {
+        for row in self.coeffs.iter_mut().chain(self.coeffs_imag.iter_mut()) {
+            row.push(0.0);
+        }
+
+        self.coeffs.push(vec![0.0; self.rhs.len() + 1]);
+        self.coeffs_imag.push(vec![0.0; self.rhs.len() + 1]);
+        self.rhs.push(0.0);
+        self.currents.push((name.to_string(), self.rhs.len() - 1));
+
+        self.rhs.len() - 1
+    }
+
+    /// Adds `value` at a row and column of unknowns, as given by `get_row` or `add_row`.
+    pub fn stamp_entry(&mut self, row: usize, column: usize, value: f64) {
+        self.coeffs[row][column] += value;
+    }
+
+    /// Adds `value` at a row and the column of the current through `name`. That column may not be
+    /// there until every element is stamped, so it is only added then, by `dc`, `ac`,
+    /// `transient` and `jacobian`.
+    pub fn stamp_current_entry(&mut self, row: usize, name: &str, value: f64) {
+        self.current_entries.push((row, name.to_string(), value));
+    }
+
+    /// Adds `value` to the imaginary part at a row and column of unknowns.
+    pub fn stamp_entry_imag(&mut self, row: usize, column: usize, value: f64) {
+        self.coeffs_imag[row][column] += value;
+    }
+
+    pub fn stamp_conductance(&mut self, from: usize, to: usize, conductance: f64) {
+        for (a, b, sign) in [(from, from, 1.0), (to, to, 1.0), (from, to, -1.0), (to, from, -1.0)] {
+            if let (Some(a), Some(b)) = (Self::get_row(a), Self::get_row(b)) {
+                self.coeffs[a][b] += sign * conductance;
+            }
+        }
+    }
+
+    /// The imaginary counterpart of `stamp_conductance`, e.g. ωC for a capacitor.
+    pub fn stamp_susceptance(&mut self, from: usize, to: usize, susceptance: f64) {
+        for (a, b, sign) in [(from, from, 1.0), (to, to, 1.0), (from, to, -1.0), (to, from, -1.0)] {
+            if let (Some(a), Some(b)) = (Self::get_row(a), Self::get_row(b)) {
+                self.coeffs_imag[a][b] += sign * susceptance;
+            }
+        }
+    }
+
+    /// A current of `transconductance` times the voltage from `control_from` to `control_to`,
+    /// flowing from `from` to `to` through the element.
+    pub fn stamp_transconductance(&mut self, from: usize, to: usize, control_from: usize, control_to: usize, transconductance: f64) {
+        for (a, b, sign) in [(from, control_from, 1.0), (from, control_to, -1.0), (to, control_from, -1.0), (to, control_to, 1.0)] {
+            if let (Some(a), Some(b)) = (Self::get_row(a), Self::get_row(b)) {
+                self.coeffs[a][b] += sign * transconductance;
+            }
+        }
+    }
+
+    /// A source driving `current` out of its `-out` side, taking it in on the `-in` side.
+    pub fn stamp_current_source(&mut self, from: usize, to: usize, current: f64) {
+        if let Some(to) = Self::get_row(to) {
+            self.rhs[to] += current;
+        }
+
+        if let Some(from) = Self::get_row(from) {
+            self.rhs[from] -= current;
+        }
+    }
+
+    /// A source holding its `-out` side `voltage` above its `-in` side, returning the row of its
+    /// current. Its current is counted as flowing into the `-out` side, as SPICE does.
+    pub fn stamp_voltage_source(&mut self, name: &str, from: usize, to: usize, voltage: f64) -> usize {
+        let k = self.add_row(name);
+
+        for (net, sign) in [(to, 1.0), (from, -1.0)] {
+            if let Some(row) = Self::get_row(net) {
+                self.coeffs[row][k] += sign;
+                self.coeffs[k][row] += sign;
+            }
+        }
+
+        self.rhs[k] = voltage;
+
+        k
+    }
+
+    pub fn get_net_count(&self) -> usize {
+        self.net_count
+    }
+
+    pub fn get_coeffs(&self) -> &Vec<Vec<f64>> {
+        &self.coeffs
+    }
+
+    pub fn get_coeffs_imag(&self) -> &Vec<Vec<f64>> {
+        &self.coeffs_imag
+    }
+
+    pub fn get_rhs(&self) -> &Vec<f64> {
+        &self.rhs
+    }
+
+    /// Solves the real part of the system by LU factorisation.
+    pub fn solve(&self) -> Result<MnaSolution, ParseError> {
+        let unknowns = Self::solve_real(&self.coeffs, &self.rhs)?;
+
+        Ok(self.solution(&unknowns))
+    }
+
+    /// Solves the complex system, as the real one twice its size, giving the real and the
+    /// imaginary parts of the solution.
+    ///
+    /// ```text
+    /// | G  -B | | x |   | b |
+    /// | B   G | | y | = | 0 |
+    /// ```
+    pub fn solve_complex(&self) -> Result<(MnaSolution, MnaSolution), ParseError> {
+        let size = self.rhs.len();
+        let mut coeffs = vec![vec![0.0; 2 * size]; 2 * size];
+
+        for i in 0..size {
+            for j in 0..size {
+                coeffs[i][j] = self.coeffs[i][j];
+                coeffs[i][j + size] = -self.coeffs_imag[i][j];
+                coeffs[i + size][j] = self.coeffs_imag[i][j];
+                coeffs[i + size][j + size] = self.coeffs[i][j];
+            }
+        }
+
+        let mut rhs = self.rhs.clone();
+        rhs.extend(vec![0.0; size]);
+
+        let unknowns = Self::solve_real(&coeffs, &rhs)?;
+
+        Ok((self.solution(&unknowns[..size]), self.solution(&unknowns[size..])))
+    }
+
+    fn solve_real(coeffs: &Vec<Vec<f64>>, rhs: &Vec<f64>) -> Result<Vec<f64>, ParseError> {
+        let unknowns = match rhs.is_empty() {
+            true => vec![],
+            false => LinearSystemSolve::LFactorize.solve(coeffs, rhs, None, None, None, None),
+        };
+
+        match unknowns.iter().all(|x| x.is_finite()) {
+            true => Ok(unknowns),
+            false => Err(ParseError::new(vec![Diagnostic::new(
+                0,
+                "The MNA matrix is singular, some voltage or current is left undetermined".to_string(),
+            )])),
+        }
+    }
+
+    fn solution(&self, unknowns: &[f64]) -> MnaSolution {
+        let mut voltages = vec![0.0];
+        voltages.extend(unknowns[..self.net_count.saturating_sub(1)].iter().cloned());
+
+        let currents = self.currents.iter().map(|(name, k)| (name.clone(), unknowns[*k])).collect();
+
+        MnaSolution { voltages, currents }
+    }
+}
+
+/// A solution of the system, i.e. the voltage of every net and the current through every branch
+/// that has a row of its own.
+pub struct MnaSolution {
+    voltages: Vec<f64>,
+    currents: Vec<(String, f64)>,
+}
+
+impl MnaSolution {
+    /// All voltages at 0 and no currents, where iterating starts from.
+    pub fn new(net_count: usize) -> Self {
+        Self { voltages: vec![0.0; net_count], currents: vec![] }
+    }
+
+    /// The DC operating point of the circuit, with the built-in model of every branch.
+    pub fn from(graph: &NetGraph) -> Result<Self, ParseError> {
+        Self::operating_point(graph.get_net_count(), &Element::from(graph)?)
+    }
+
+    /// The DC operating point. A linear circuit is solved right away, one with nonlinear elements
+    /// by Newton-Raphson from all voltages at 0, every element linearised around the last guess
+    /// after limiting its step.
+    pub fn operating_point(net_count: usize, elements: &[Element]) -> Result<Self, ParseError> {
+        let system = MnaSystem::dc(net_count, elements)?;
+
+        let is_nonlinear = elements.iter().any(|x| {
+            matches!(
+                x.get_model().stamp_type(),
+                ElementStampType::NonLinear(_) | ElementStampType::MultiTerminalNonLinear(_)
+            )
+        });
+
+        if !is_nonlinear {
+            return system.solve();
+        }
+
+        let mut init_guess: Vec<UnknownFactor> = (0..system.get_rhs().len())
+            .map(|k| match k < system.net_count.saturating_sub(1) {
+                true => UnknownFactor::Voltage(0.0),
+                false => UnknownFactor::Current(0.0),
+            })
+            .collect();
+
+        let mut previous = MnaSolution::new(system.net_count);
+        let mut error = None;
+
+        let mut linearize = |unknowns: &mut Vec<f64>| {
+            let mut guess = system.solution(unknowns);
+
+            for element in elements {
+                element.get_model().limit(element.get_placement(), &previous, &mut guess);
+            }
+
+            unknowns[..guess.voltages.len() - 1].copy_from_slice(&guess.voltages[1..]);
+            previous = MnaSolution { voltages: guess.voltages.clone(), currents: vec![] };
+
+            match MnaSystem::jacobian(net_count, elements, &guess) {
+                Ok(jacobian) => {
+                    let rhs = jacobian.rhs.iter().map(|x| RhsValueType::Constant(*x)).collect();
+                    Some((jacobian.coeffs, rhs))
+                }
+                Err(e) => {
+                    error = Some(e);
+                    None
+                }
+            }
+        };
+
+        let iterates = quasi_newton_iter_linear_until_converge(
+            &mut linearize,
+            &mut init_guess,
+            1.0,
+            NEWTON_ITERATIONS,
+            NEWTON_ABS_TOL,
+            NEWTON_REL_TOL,
+            LinearSystemSolve::LFactorize,
+            None,
+            None,
+            None,
+        );
+
+        if let Some(error) = error {
+            return Err(error);
+        }
+
+        let (last, before) = match iterates.as_slice() {
+            [.., before, last] => (last.get_all(), before.get_all()),
+            _ => return system.solve(),
+        };
+
+        match (last.iter().all(|x| x.is_finite()), last.is_convergent(&before, NEWTON_REL_TOL, NEWTON_ABS_TOL)) {
+            (false, _) => Err(ParseError::new(vec![Diagnostic::new(
+                0,
+                "The MNA matrix is singular, some voltage or current is left undetermined".to_string(),
+            )])),
+            (true, false) => Err(ParseError::new(vec![Diagnostic::new(
+                0,
+                format!("The operating point didn't converge in {} iterations, it's required that you revise the circuit.", NEWTON_ITERATIONS),
+            )])),
+            (true, true) => Ok(system.solution(&last)),
+        }
+    }
+
+    /// The voltage of a net against ground.
+    pub fn get_voltage(&self, net: usize) -> f64 {
+        self.voltages.get(net).cloned().unwrap_or(0.0)
+    }
+
+    pub fn get_voltages(&self) -> &Vec<f64> {
+        &self.voltages
+    }
+
+    pub fn modify_voltage(&mut self, net: usize, voltage: f64) {
+        if let Some(x) = self.voltages.get_mut(net) {
+            *x = voltage;
+        }
+    }
+
+    /// The current through a branch, if it has a row of its own.
+    pub fn get_current(&self, name: &str) -> Option<f64> {
+        self.currents.iter().find(|(x, _)| x == name).map(|(_, x)| *x)
+    }
+
+    pub fn get_currents(&self) -> &Vec<(String, f64)> {
+        &self.currents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_of(netlist: &str) -> NetGraph {
+        let circuits = parse(netlist).unwrap_or_else(|e| panic!("{}", e));
+        NetGraph::from(&circuits[0], "default").unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn solve(netlist: &str) -> Result<MnaSolution, ParseError> {
+        let graph = graph_of(netlist);
+        check_topology(&graph)?;
+        MnaSolution::from(&graph)
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() <= 1e-9 * expected.abs().max(1.0), "{} is not {}", value, expected);
+    }
+
+    #[test]
+    fn solves_a_resistor_divider() {
+        let solution = solve(
+            ";divider\n\
+             ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n\
+             ;;r1 -in=prev -out=next,\n;;;default .resistor -resistance=1k,\n\
+             ;;r2 -in=prev -out=next,\n;;;default .resistor -resistance=3k,\n\
+             ;;r3 -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n;\n",
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+
+        assert_close(solution.get_voltage(0), 0.0);
+        assert_close(solution.get_voltage(1), 10.0);
+        assert_close(solution.get_voltage(2), 8.0);
+        assert_close(solution.get_voltage(3), 2.0);
+
+        // The current through a source is the one into its -out.
+        assert_close(solution.get_current("src").unwrap(), -2e-3);
+    }
+
+    #[test]
+    fn solves_a_diode_behind_a_resistor() {
+        let solution = solve(
+            ";clamp\n\
+             ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=1k,\n\
+             ;;r -in=prev -out=next,\n;;;default .resistor -resistance=10,\n\
+             ;;d -in=prev -out=ground,\n;;;default .diode -junction=pn\n;\n",
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+
+        let (anode, current) = (solution.get_voltage(2), -solution.get_current("src").unwrap());
+
+        assert!(anode > 0.8 && anode < 1.2, "the diode drops {} V", anode);
+        assert_close(current, (1000.0 - anode) / 10.0);
+        assert_close(current, 1e-14 * ((anode / THERMAL_VOLTAGE).exp() - 1.0) + GMIN * anode);
+    }
+
+    #[test]
+    fn reports_a_floating_node() {
+        let floating = ";floating\n\
+                        ;;src -in=ground -out=next,\n;;;default .dcsource -current=1m,\n\
+                        ;;c -in=prev -out=ground,\n;;;default .capacitor -capacitance=1u\n;\n";
+
+        let diagnostics = check_topology(&graph_of(floating)).err().unwrap().get_diagnostics().clone();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get_line_number(), 3);
+        assert!(diagnostics[0].get_message().contains("the current of src has nowhere to go"));
+
+        // Without the current source the net is only held by GMIN, which is worth a warning.
+        let held = ";held\n\
+                    ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=1,\n\
+                    ;;c -in=prev -out=$PROBE,\n;;;default .capacitor -capacitance=1u\n;\n";
+
+        let graph = graph_of(held);
+        let warnings = check_topology(&graph).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].is_warning());
+        assert_close(MnaSolution::from(&graph).unwrap().get_voltage(2), 0.0);
+    }
+
+    #[test]
+    fn reports_a_singular_matrix() {
+        // Two sources fighting over one net, which check_topology would have caught.
+        let graph = graph_of(
+            ";fight\n\
+             ;;a -in=ground -out=next,\n;;;default .dcsource -voltage=1,\n\
+             ;;b -in=ground -out=a,\n;;;default .dcsource -voltage=2\n;\n",
+        );
+
+        assert!(check_topology(&graph).is_err());
+
+        let error = MnaSolution::from(&graph).err().unwrap();
+        assert!(error.get_diagnostics()[0].get_message().contains("singular"));
+    }
+
+    /// A stand-in for a resistor, either of a fixed conductance or drawing the square of the
+    /// voltage across it in mA.
+    enum Custom {
+        Conductance(f64),
+        SquareLaw,
+    }
+
+    impl Stamp for Custom {
+        fn stamp_type(&self) -> ElementStampType<'_> {
+            match self {
+                Self::Conductance(conductance) => ElementStampType::Linear(*conductance),
+                Self::SquareLaw => ElementStampType::NonLinear(Box::new(|x| x * x / 1000.0)),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_models_stand_in_for_built_in_ones() {
+        let graph = graph_of(
+            ";divider\n\
+             ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n\
+             ;;r1 -in=prev -out=next,\n;;;default .resistor -resistance=1k,\n\
+             ;;r2 -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n;\n",
+        );
+
+        let mut elements = Element::from(&graph).unwrap_or_else(|e| panic!("{}", e));
+        let operating_point = |elements: &[Element]| {
+            MnaSolution::operating_point(graph.get_net_count(), elements).unwrap_or_else(|e| panic!("{}", e))
+        };
+
+        assert_close(operating_point(&elements).get_voltage(2), 5.0);
+
+        let r2 = elements.iter().position(|x| x.get_placement().get_name() == "r2").unwrap();
+        elements[r2].modify_model(Box::new(Custom::Conductance(1.0 / 3000.0)));
+        assert_close(operating_point(&elements).get_voltage(2), 7.5);
+
+        // (10 - v) / 1k = v² / 1k, so v² + v - 10 = 0.
+        elements[r2].modify_model(Box::new(Custom::SquareLaw));
+        let voltage = operating_point(&elements).get_voltage(2);
+        assert!((voltage - (41f64.sqrt() - 1.0) / 2.0).abs() < 1e-6, "r2 is at {} V", voltage);
+    }
+
+    #[test]
+    fn controlled_sources_follow_their_control() {
+        // src holds 10 V over its -in and draws -10 mA into its -out, which is what each source
+        // below is controlled by. Each drives a 1k load on net 2.
+        let circuit = |source: &str| {
+            format!(
+                ";controlled\n\
+                 ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n\
+                 ;;rc -in=prev -out=ground,\n;;;default .resistor -resistance=1k,\n\
+                 ;;e -in=ground -out=next,\n;;;default .dcsource {} -control=src,\n\
+                 ;;rl -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n;\n",
+                source
+            )
+        };
+
+        let cases = [
+            // A VCVS holds its -out at the gain times the -out over the -in of the control.
+            ("-voltage^=2 -voltage*=1", 20.0),
+            // A VCCS drives the transconductance times that voltage out of its -out.
+            ("-current^=1m -voltage*=1", 10.0),
+            // A CCVS and a CCCS go by the current into the -out of the control, here -10 mA.
+            ("-voltage^=1k -current*=1", -10.0),
+            ("-current^=2 -current*=1", -20.0),
+        ];
+
+        for (source, voltage) in cases {
+            let solution = solve(&circuit(source)).unwrap_or_else(|e| panic!("{}: {}", source, e));
+
+            assert_close(solution.get_voltage(1), 10.0);
+            assert_close(solution.get_current("src").unwrap(), -10e-3);
+            assert_close(solution.get_voltage(2), voltage);
+
+            // Only the voltage sources solve for their current, which is the load's, reversed.
+            match source.starts_with("-voltage") {
+                true => assert_close(solution.get_current("e").unwrap(), -voltage / 1000.0),
+                false => assert_eq!(solution.get_current("e"), None),
+            }
+        }
+
+        let diagnostic = |source: &str| solve(&circuit(source)).err().map(|x| x.get_diagnostics()[0].get_message().to_string());
+
+        assert_eq!(
+            diagnostic("-voltage=2 -current*=1m"),
+            Some("DCSource is either independent with one solo voltage/current or controlled with a parent/child pair, not both".to_string())
+        );
+        assert_eq!(
+            diagnostic("-voltage=2 -voltage*=10"),
+            Some("DCSource is either independent with one solo voltage/current or controlled with a parent/child pair, not both".to_string())
+        );
+    }
+
+    #[test]
+    fn a_zener_holds_its_breakdown_and_reports_its_power() {
+        // An np diode has its anode on -out, so here it is reverse biased from net 2 to ground. With
+        // the default saturation current it takes about 0.7 V past breakdown to pass 14 mA.
+        let zener = ";zener\n\
+                     ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=20,\n\
+                     ;;r -in=prev -out=next,\n;;;default .resistor -resistance=1k,\n\
+                     ;;d -in=prev -out=ground,\n;;;default .diode -junction=np -breakdown_voltage=5.1 -power=50m\n;\n";
+
+        let graph = graph_of(zener);
+        let solution = MnaSolution::from(&graph).unwrap_or_else(|e| panic!("{}", e));
+        let (cathode, current) = (solution.get_voltage(2), -solution.get_current("src").unwrap());
+
+        assert!(cathode > 5.1 && cathode < 6.0, "the zener holds {} V", cathode);
+        assert_close(current, (20.0 - cathode) / 1000.0);
+
+        let reverse = 1e-14 * (((cathode - 5.1) / THERMAL_VOLTAGE).exp() + 1.0 - (-cathode / THERMAL_VOLTAGE).exp());
+        assert!((current - reverse - GMIN * cathode).abs() < 1e-9, "{} A goes through the zener, not {} A", current, reverse);
+
+        // About 5.8 V at 14.2 mA is over the 50 mW it is rated for.
+        let warnings = check_ratings(&graph, &solution).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].is_warning());
+        assert_eq!(warnings[0].get_line_number(), 7);
+        assert!(warnings[0].get_message().starts_with("'d' dissipates "), "{}", warnings[0].get_message());
+        assert!(warnings[0].get_message().ends_with(" over its rating of 50mW"), "{}", warnings[0].get_message());
+
+        let rated = graph_of(&zener.replace("-power=50m", "-power=1"));
+        assert!(check_ratings(&rated, &MnaSolution::from(&rated).unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn series_resistance_gets_a_net_and_steps_are_limited() {
+        let graph = graph_of(
+            ";series\n\
+             ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n\
+             ;;r -in=prev -out=next,\n;;;default .resistor -resistance=1k,\n\
+             ;;d -in=prev -out=ground,\n;;;default .diode -junction=pn -resistance=100\n;\n",
+        );
+
+        let elements = Element::from(&graph).unwrap_or_else(|e| panic!("{}", e));
+        let d = elements.iter().find(|x| x.get_placement().get_name() == "d").unwrap();
+
+        // The junction sits on a net of its own, numbered after those of the circuit.
+        let junction = graph.get_net_count();
+        assert_eq!(d.get_placement().get_internal(), &vec![junction]);
+
+        let solution = MnaSolution::from(&graph).unwrap_or_else(|e| panic!("{}", e));
+        let current = -solution.get_current("src").unwrap();
+        assert_close(solution.get_voltage(2) - solution.get_voltage(junction), 100.0 * current);
+        assert_close(current, (10.0 - solution.get_voltage(2)) / 1000.0);
+
+        // A step from 0 V to 10 V across the junction is cut back to where the exponential can
+        // still be followed, a step that small is left as it is.
+        let previous = MnaSolution::new(junction + 1);
+        let mut guess = MnaSolution::new(junction + 1);
+        guess.modify_voltage(junction, 10.0);
+        d.get_model().limit(d.get_placement(), &previous, &mut guess);
+        assert!(guess.get_voltage(junction) > 0.0 && guess.get_voltage(junction) < 1.0, "limited to {} V", guess.get_voltage(junction));
+
+        guess.modify_voltage(junction, 0.1);
+        d.get_model().limit(d.get_placement(), &previous, &mut guess);
+        assert_close(guess.get_voltage(junction), 0.1);
+    }
+
+    #[test]
+    fn a_common_emitter_stage_amplifies_its_base_current() {
+        // The base is fed through 1 MΩ from the supply, the collector through 1 kΩ.
+        let common_emitter = ";ce\n\
+                              ;;vcc -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n\
+                              ;;rc -in=prev -out=next,\n;;;default .resistor -resistance=1k,\n\
+                              ;;q -in=prev -out=ground -base=rb,\n;;;default .transistor -junction=npn -beta=100,\n\
+                              ;;rb -in=vcc -out=$PROBE,\n;;;default .resistor -resistance=1meg\n;\n";
+
+        for (junction, polarity) in [("npn", 1.0), ("pnp", -1.0)] {
+            // A PNP is the NPN with every voltage and current the other way around.
+            let netlist = common_emitter.replace("npn", junction).replace("-voltage=10", &format!("-voltage={}", polarity * 10.0));
+            let solution = solve(&netlist).unwrap_or_else(|e| panic!("{}: {}", junction, e));
+
+            let (collector, base) = (polarity * solution.get_voltage(2), polarity * solution.get_voltage(3));
+            let (collector_current, base_current) = ((10.0 - collector) / 1e3, (10.0 - base) / 1e6);
+
+            assert!(base > 0.6 && base < 0.9, "{}: the base-emitter junction drops {} V", junction, base);
+            assert!(collector > 0.9, "{}: the collector is at {} V, the transistor is saturated", junction, collector);
+            assert!((collector_current / base_current - 100.0).abs() < 0.01, "{}: Ic is {} A for Ib {} A", junction, collector_current, base_current);
+            assert_close(polarity * solution.get_current("vcc").unwrap(), -(collector_current + base_current));
+        }
+    }
+
+    #[test]
+    fn the_base_charge_stays_positive_past_the_early_voltage() {
+        let graph = graph_of(
+            ";early\n\
+             ;;vcc -in=ground -out=next,\n;;;default .dcsource -voltage=10,\n\
+             ;;q -in=prev -out=ground -base=vcc,\n;;;default .transistor -junction=npn -early_voltage_reverse=0.5\n;\n",
+        );
+
+        let elements = Element::from(&graph).unwrap_or_else(|e| panic!("{}", e));
+        let q = elements.iter().find(|x| x.get_placement().get_name() == "q").unwrap();
+
+        // At 0.5 V and 0.7 V from base to emitter the Early term reaches and passes 0.
+        let stamp_type = q.get_model().stamp_type();
+
+        match stamp_type {
+            ElementStampType::MultiTerminalNonLinear(current) => {
+                for base_emitter in [0.5, 0.7] {
+                    let collector_current = current(5.0, base_emitter);
+                    assert!(collector_current.is_finite() && collector_current > 0.0, "Ic is {} A at {} V", collector_current, base_emitter);
+                }
+            }
+            _ => panic!("a BJT is a multi-terminal nonlinear element"),
+        }
+    }
+}
@@ -30,16 +30,6 @@ fn make_eye_matrix(n: usize, m: usize) -> Vec<Vec<f64>> {
     eye
 }
 
-fn produce_list_of_intermittent_0s_and_1s(n: usize, odds: usize, evens: usize) -> Vec<usize> {
-    (0..n)
-        .into_iter()
-        .map(|i| match i % 2 == 0 {
-            true => evens,
-            false => odds,
-        })
-        .collect()
-}
-
 trait IsZeroAt {
     fn is_zero_at(&self, i: usize, j: usize) -> bool;
 }
@@ -53,8 +43,43 @@ impl IsZeroAt for Arc<RwLock<Vec<Vec<f64>>>> {
     }
 }
 
+/// Swaps the row with the largest entry in column `i` up to row `i`, along with its row of the
+/// permutation and the multipliers already found for it, so MNA rows with a zero on the diagonal
+/// (those of voltage sources) can still be eliminated.
+fn pivot_partial(
+    coeffs: &Arc<RwLock<Vec<Vec<f64>>>>,
+    lvals: &Arc<RwLock<Vec<Vec<f64>>>>,
+    permutation: &Arc<RwLock<Vec<Vec<f64>>>>,
+    i: usize,
+    size: usize,
+) {
+    let pivot = {
+        let coeffs = coeffs.read();
+
+        (i..size).fold(i, |best, k| match coeffs[k][i].abs() > coeffs[best][i].abs() {
+            true => k,
+            false => best,
+        })
+    };
+
+    if pivot == i {
+        return;
+    }
+
+    coeffs.write().swap(i, pivot);
+    permutation.write().swap(i, pivot);
+
+    let mut lvals = lvals.write();
+
+    for k in 0..i {
+        let lval = lvals[i][k];
+        lvals[i][k] = lvals[pivot][k];
+        lvals[pivot][k] = lval;
+    }
+}
+
 /// This function takes the barriers and select number of columns. Wait for all threads to reach
-/// that row. Then factorizes and eliminates those columns.
+/// that row. Then one of them pivots, and each eliminates the rows it was given below it.
 fn barrier_rows_and_solve_cols(
     coeffs: Arc<RwLock<Vec<Vec<f64>>>>,
     lvals: Arc<RwLock<Vec<Vec<f64>>>>,
@@ -64,23 +89,11 @@ fn barrier_rows_and_solve_cols(
     pivot_barrier: Arc<Barrier>,
     this_cols: Vec<usize>,
     size: AtomicUsize,
-    thread_modulo: AtomicUsize,
 ) {
     let size_loaded = size.load(Ordering::Relaxed);
-    let tn = thread_modulo.load(Ordering::Relaxed);
     (0..size_loaded).into_iter().for_each(|i| {
-        row_barrier.wait();
-        for k in (i..size_loaded) {
-            let coeffs_clone = Arc::clone(&coeffs);
-            let permutation_clone = Arc::clone(&permutation);
-
-            match coeffs_clone.is_zero_at(i, i) {
-                true => {
-                    coeffs_clone.write().swap(i, k + 1);
-                    permutation_clone.write().swap(i, k + 1);
-                }
-                false => break,
-            }
+        if row_barrier.wait().is_leader() {
+            pivot_partial(&coeffs, &lvals, &permutation, i, size_loaded);
         }
 
         pivot_barrier.wait();
@@ -88,7 +101,7 @@ fn barrier_rows_and_solve_cols(
         this_cols
             .iter()
             .cloned()
-            .filter(|j| (*j > i) && (j % 2 == tn))
+            .filter(|j| *j > i)
             .for_each(|j| {
                 let ii = coeffs.read()[i][i];
                 let ji = coeffs.read()[j][i];
@@ -178,7 +191,7 @@ pub struct EliminatorSolver {
 impl EliminatorSolver {
     pub fn new(coefficients: &Vec<Vec<f64>>, right_hand_side: &Vec<f64>) -> Self {
         let (n, m) = (coefficients.len(), coefficients[0].len());
-        let num_threads = m - 1;
+        let num_threads = thread::available_parallelism().map_or(1, |x| x.get()).min(n - 1).max(1);
 
         let (lvals, permutation) = (
             Arc::new(RwLock::new(make_eye_matrix(n, m))),
@@ -192,7 +205,10 @@ impl EliminatorSolver {
         let phase_barrier = Arc::new(Barrier::new(num_threads));
         let pivot_barrier = Arc::new(Barrier::new(num_threads));
 
-        let col_nums = vec![(1..n).into_iter().collect(); num_threads];
+        // Each thread eliminates every num_threads-th row.
+        let col_nums = (0..num_threads)
+            .map(|t| (1..n).filter(|j| j % num_threads == t).collect())
+            .collect();
 
         Self {
             coeffs,
@@ -209,16 +225,11 @@ impl EliminatorSolver {
     }
 
     fn pivot_factor_eliminate_parallel_col(&self) {
-        let (evens, odds) = (1, 0);
-        let intermittent_vec =
-            produce_list_of_intermittent_0s_and_1s(self.num_threads, odds, evens);
-
         let thrds = self
             .col_nums
             .iter()
             .cloned()
-            .zip(intermittent_vec.into_iter())
-            .map(|(this_cols, tn)| {
+            .map(|this_cols| {
                 let coeffs = Arc::clone(&self.coeffs);
                 let lvals = Arc::clone(&self.lvals);
                 let permutation = Arc::clone(&self.permutation);
@@ -226,7 +237,6 @@ impl EliminatorSolver {
                 let phase_barrier = Arc::clone(&self.phase_barrier);
                 let pivot_barrier = Arc::clone(&self.pivot_barrier);
                 let size = AtomicUsize::new(self.n);
-                let thread_modulo = AtomicUsize::new(tn);
 
                 thread::spawn(|| {
                     barrier_rows_and_solve_cols(
@@ -238,7 +248,6 @@ impl EliminatorSolver {
                         pivot_barrier,
                         this_cols,
                         size,
-                        thread_modulo,
                     )
                 })
             })
@@ -388,9 +397,16 @@ impl LinearSystemSolve {
     }
 }
 
+/// The coefficients and the right hand side of a system linearised around some unknowns.
+pub type LinearizedSystem = (Vec<Vec<f64>>, Vec<RhsValueType>);
+
+/// Newton-Raphson iterations from `init_guess`: `linearize` gives the system linearised around
+/// the last unknowns, which it may first pull back where a step went too far, and that is solved
+/// for the next ones, until two in a row are within the tolerances or `num_iter` runs out.
+/// Dampened entries of the right hand side are dampened with `alpha`. Gives back every iterate,
+/// `init_guess` first, stopping early when `linearize` can't linearise.
 pub fn quasi_newton_iter_linear_until_converge<'a>(
-    coeffs_linear: &Vec<Vec<f64>>,
-    rhs: Vec<RhsValueType>,
+    linearize: &mut dyn FnMut(&mut Vec<f64>) -> Option<LinearizedSystem>,
     init_guess: &mut Vec<UnknownFactor>,
     alpha: f64,
     num_iter: usize,
@@ -401,19 +417,23 @@ pub fn quasi_newton_iter_linear_until_converge<'a>(
     abs_tol_solver: Option<f64>,
     rel_tol_solver: Option<f64>,
 ) -> Vec<Vec<UnknownFactor>> {
-    let n = coeffs_linear.len();
     let mut return_factors: Vec<Vec<UnknownFactor>> = vec![];
     return_factors.push(init_guess.to_vec());
 
     let mut last_unknowns = init_guess.clone();
-    let mut last_rhs = rhs.clone();
 
     for k in 0..num_iter {
-        let last_unknowns_values = last_unknowns.get_all();
-        let rhs_at_k = last_rhs.calculate_linearize_at_kth(alpha, k);
+        let mut last_unknowns_values = last_unknowns.get_all();
+
+        let (coeffs_at_k, rhs) = match linearize(&mut last_unknowns_values) {
+            Some(linearized) => linearized,
+            None => return return_factors,
+        };
+
+        let rhs_at_k = rhs.calculate_linearize_at_kth(alpha, k);
 
         let solved_at_k = solver.solve(
-            coeffs_linear,
+            &coeffs_at_k,
             &rhs_at_k,
             Some(&last_unknowns_values),
             num_iter_solver,
@@ -421,14 +441,14 @@ pub fn quasi_newton_iter_linear_until_converge<'a>(
             rel_tol_solver,
         );
 
+        let converged = solved_at_k.is_convergent(&last_unknowns_values, rel_tol, abs_tol);
+
+        last_unknowns.set_all(solved_at_k);
         return_factors.push(last_unknowns.clone());
 
-        if solved_at_k.is_convergent(&last_unknowns_values, rel_tol, abs_tol) {
+        if converged {
             return return_factors;
         }
-
-        last_unknowns.set_all(solved_at_k);
-        last_rhs.update_all(rhs_at_k);
     }
 
     return_factors
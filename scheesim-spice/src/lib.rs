@@ -0,0 +1,1352 @@
+use std::ops::Range;
+
+use scheesim_lexparse::*;
+
+/// A word of a SPICE card, with the line and columns it was read from.
+#[derive(Clone)]
+struct Word {
+    text: String,
+    line_number: usize,
+    columns: Range<usize>,
+}
+
+impl Word {
+    fn diagnose(&self, message: String) -> Diagnostic {
+        Diagnostic::at_span(self.line_number, self.columns.clone(), &self.text, message)
+    }
+
+    fn lowercase(&self) -> String {
+        self.text.to_lowercase()
+    }
+
+    fn is_assignment(&self) -> bool {
+        self.text.contains('=')
+    }
+}
+
+/// One SPICE card, continuation lines (`+`) included.
+struct Card {
+    words: Vec<Word>,
+    line_number: usize,
+}
+
+impl Card {
+    fn keyword(&self) -> String {
+        self.words[0].lowercase()
+    }
+
+    fn diagnose(&self, message: String) -> Diagnostic {
+        self.words[0].diagnose(message)
+    }
+
+    /// Where the `name=value` parameters of an `X` or `.subckt` card start, after its nets.
+    fn parameters_start(&self) -> usize {
+        self.words.iter().position(|x| x.is_assignment() || x.lowercase() == "params:").unwrap_or(self.words.len())
+    }
+}
+
+/// Splits a line into words on whitespace, parentheses and commas, leaving `{...}` expressions
+/// whole. `name = value` is put back together into a single `name=value` word.
+fn split_words(line: &str, line_number: usize) -> Vec<Word> {
+    let mut words: Vec<Word> = vec![];
+    let mut current = String::new();
+    let mut start = 0;
+    let mut depth = 0;
+
+    for (column, ch) in line.chars().enumerate() {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ => (),
+        }
+
+        match depth == 0 && (ch.is_whitespace() || ch == '(' || ch == ')' || ch == ',') {
+            true => if !current.is_empty() {
+                words.push(Word { text: current.clone(), line_number, columns: start..column });
+                current.clear();
+            },
+            false => {
+                if current.is_empty() {
+                    start = column;
+                }
+
+                current.push(ch);
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        let end = start + current.chars().count();
+        words.push(Word { text: current, line_number, columns: start..end });
+    }
+
+    let mut joined: Vec<Word> = vec![];
+
+    for word in words {
+        match joined.last_mut() {
+            Some(last) if last.text.ends_with('=') || word.text.starts_with('=') => {
+                last.text.push_str(&word.text);
+                last.columns = last.columns.start..word.columns.end;
+            }
+            _ => joined.push(word),
+        }
+    }
+
+    joined
+}
+
+/// Reads the cards of a deck. The first line is always the title, `*` starts a comment line,
+/// `;` and ` $` start a comment at the end of a line and `+` continues the previous card.
+fn read_cards(deck: &str) -> (String, Vec<Card>) {
+    let mut lines = deck.lines().enumerate();
+    let title = lines.next().map(|(_, x)| x.trim().to_string()).unwrap_or_default();
+    let mut cards: Vec<Card> = vec![];
+
+    for (i, line) in lines {
+        let line = match (line.find(';'), line.find(" $")) {
+            (Some(a), Some(b)) => &line[..a.min(b)],
+            (Some(a), None) | (None, Some(a)) => &line[..a],
+            (None, None) => line,
+        };
+
+        let trimmed = line.trim_start();
+
+        if trimmed.is_empty() || trimmed.starts_with('*') {
+            continue;
+        }
+
+        match (trimmed.strip_prefix('+'), cards.last_mut()) {
+            (Some(_), Some(card)) => {
+                // Blank the `+` out so the columns stay those of the line.
+                let continued = line.replacen('+', " ", 1);
+                card.words.extend(split_words(&continued, i + 1));
+            }
+            _ => {
+                let words = split_words(line, i + 1);
+
+                if !words.is_empty() {
+                    cards.push(Card { words, line_number: i + 1 });
+                }
+            }
+        }
+    }
+
+    (title, cards)
+}
+
+/// Reads a SPICE number such as `4.7k`, `1meg`, `10uF` or `2.5e-3`. As in SPICE, letters after
+/// the scale factor are units and are ignored.
+pub fn spice_value(s: &str) -> Option<f64> {
+    let s = s.to_lowercase();
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut i = 0;
+
+    if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+        i += 1;
+    }
+
+    while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+        i += 1;
+    }
+
+    if i < chars.len() && chars[i] == 'e' {
+        let digits_at = match chars.get(i + 1) {
+            Some('+') | Some('-') => i + 2,
+            _ => i + 1,
+        };
+
+        if chars.get(digits_at).is_some_and(|x| x.is_ascii_digit()) {
+            i = digits_at;
+
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+    }
+
+    let mantissa = chars[..i].iter().collect::<String>().parse::<f64>().ok()?;
+    let suffix = chars[i..].iter().collect::<String>();
+
+    let scale = match suffix.as_str() {
+        _ if suffix.starts_with("meg") => 1e6,
+        _ if suffix.starts_with("mil") => 25.4e-6,
+        _ => match suffix.chars().next() {
+            Some('t') => 1e12,
+            Some('g') => 1e9,
+            Some('k') => 1e3,
+            Some('m') => 1e-3,
+            Some('u') | Some('µ') => 1e-6,
+            Some('n') => 1e-9,
+            Some('p') => 1e-12,
+            Some('f') => 1e-15,
+            Some('a') => 1e-18,
+            Some(ch) if !ch.is_alphabetic() => return None,
+            _ => 1.0,
+        },
+    };
+
+    Some(mantissa * scale)
+}
+
+/// What a `.model` card says a device is.
+#[derive(Clone, PartialEq)]
+enum ModelKind {
+    Diode,
+    Npn,
+    Pnp,
+    Nmos,
+    Pmos,
+    Other,
+}
+
+impl std::str::FromStr for ModelKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "d" => Ok(Self::Diode),
+            "npn" => Ok(Self::Npn),
+            "pnp" => Ok(Self::Pnp),
+            "nmos" => Ok(Self::Nmos),
+            "pmos" => Ok(Self::Pmos),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for ModelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Diode => write!(f, "D"),
+            Self::Npn => write!(f, "NPN"),
+            Self::Pnp => write!(f, "PNP"),
+            Self::Nmos => write!(f, "NMOS"),
+            Self::Pmos => write!(f, "PMOS"),
+            Self::Other => write!(f, "?"),
+        }
+    }
+}
+
+/// The Scheesim argument a `.model` parameter of `kind` is carried over as, if there is one.
+fn model_argument(kind: &ModelKind, parameter: &str) -> Option<&'static str> {
+    match (kind, parameter) {
+        (ModelKind::Diode, "is") => Some("-saturation_current"),
+        (ModelKind::Diode, "n") => Some("-emission"),
+        (ModelKind::Diode, "rs") => Some("-resistance"),
+        (ModelKind::Diode, "bv") => Some("-breakdown_voltage"),
+        (ModelKind::Diode, "cjo" | "cj0") => Some("-capacitance"),
+        (ModelKind::Diode, "tt") => Some("-transit_time"),
+        (ModelKind::Npn | ModelKind::Pnp, "is") => Some("-saturation_current"),
+        (ModelKind::Npn | ModelKind::Pnp, "nf") => Some("-emission"),
+        (ModelKind::Npn | ModelKind::Pnp, "bf") => Some("-beta"),
+        (ModelKind::Npn | ModelKind::Pnp, "br") => Some("-beta_reverse"),
+        (ModelKind::Npn | ModelKind::Pnp, "vaf" | "va") => Some("-early_voltage"),
+        (ModelKind::Npn | ModelKind::Pnp, "var" | "vb") => Some("-early_voltage_reverse"),
+        (ModelKind::Npn | ModelKind::Pnp, "ikf" | "ik") => Some("-knee_current"),
+        (ModelKind::Npn | ModelKind::Pnp, "ise") => Some("-leakage_current"),
+        (ModelKind::Npn | ModelKind::Pnp, "cje") => Some("-emitter_capacitance"),
+        (ModelKind::Npn | ModelKind::Pnp, "cjc") => Some("-collector_capacitance"),
+        (ModelKind::Npn | ModelKind::Pnp, "tf") => Some("-transit_time"),
+        _ => None,
+    }
+}
+
+/// A `.model` card: its kind and the parameters Scheesim has arguments for, as the argument
+/// and the `name=value` word it came from.
+struct Model {
+    kind: ModelKind,
+    arguments: Vec<(&'static str, Word)>,
+}
+
+impl Model {
+    /// Reads the parameters after the name and the kind of a `.model` card. Those Scheesim can't
+    /// model are dropped with a warning, unless no element can use the model anyway.
+    fn from(kind: ModelKind, words: &[Word], diagnostics: &mut Vec<Diagnostic>) -> Self {
+        let mut arguments = vec![];
+
+        for word in words.iter().filter(|_| kind != ModelKind::Other) {
+            match word.text.split_once('=') {
+                Some((name, value)) if !name.is_empty() && !value.is_empty() => match model_argument(&kind, &name.to_lowercase()) {
+                    Some(argument) => arguments.push((argument, word.clone())),
+                    None => diagnostics.push(
+                        word.diagnose(format!("Model parameter '{}' has no Scheesim argument, it was dropped", name)).as_warning(),
+                    ),
+                },
+                _ => diagnostics.push(word.diagnose("Model parameters are given as name=value".to_string())),
+            }
+        }
+
+        Self { kind, arguments }
+    }
+
+    /// The Scheesim arguments of the parameters, with the word each one came from.
+    fn get_arguments(&self) -> Result<Vec<(Argument, &Word)>, Diagnostic> {
+        self.arguments
+            .iter()
+            .map(|(name, word)| {
+                let value = &word.text[word.text.find('=').unwrap() + 1..];
+
+                let value = match value.starts_with('{') {
+                    true => value.to_string(),
+                    false => format!("{}", spice_value(value).ok_or(word.diagnose(format!("Not a SPICE value '{}'", value)))?),
+                };
+
+                Argument::from(&format!("{}={}", name, value), word.line_number)
+                    .map(|x| (x, word))
+                    .map_err(|x| word.diagnose(x.get_message().to_string()))
+            })
+            .collect()
+    }
+}
+
+/// Builds one circuit out of the element cards between `.subckt` and `.ends`, or of the whole
+/// deck. SPICE connects elements through nets while Scheesim connects nodes to each other, so
+/// every net becomes a node without profiles, i.e. a wire, and each element is joined to the
+/// wires of its pins: the first pin through `-in`, the second through `-out` and the third,
+/// for transistors, through `-base`. Instances connect any further pins through `-pin`.
+struct CircuitBuilder {
+    circuit: ElectroCircuit,
+    elements: Vec<ElectroCircuitNode>,
+    nets: Vec<(String, usize)>,
+    probes: Vec<Word>,
+}
+
+impl CircuitBuilder {
+    fn new(name: String, line_number: usize) -> Self {
+        let mut circuit = ElectroCircuit::new();
+        circuit.modify_name(name);
+        circuit.modify_line_number(line_number);
+
+        Self { circuit, elements: vec![], nets: vec![], probes: vec![] }
+    }
+
+    fn connect(&mut self, pin: &Word) -> ConnectionType {
+        let net = pin.lowercase();
+
+        match net.as_str() {
+            "0" | "gnd" | "ground" => ConnectionType::Ground,
+            _ => {
+                if !self.circuit.get_ports().contains(&net) && !self.nets.iter().any(|(x, _)| *x == net) {
+                    self.nets.push((net.clone(), pin.line_number));
+                }
+
+                ConnectionType::Named(net)
+            }
+        }
+    }
+
+    fn add_element(&mut self, name: &Word, pins: &[Word], profiles: Vec<ElectroCircuitNodeProfile>) {
+        let mut node = ElectroCircuitNode::init();
+        node.modify_name(name.text.clone());
+        node.modify_line_number(name.line_number);
+
+        for (k, pin) in pins.iter().enumerate() {
+            let connection = self.connect(pin);
+
+            match k {
+                0 => node.get_connections_mut().modify_serial_in(connection),
+                1 => node.get_connections_mut().modify_serial_out(connection),
+                _ => node.get_connections_mut().modify_serial_base(connection),
+            }
+        }
+
+        for profile in profiles {
+            node.add_profile(profile);
+        }
+
+        self.elements.push(node);
+    }
+
+    fn finish(mut self, diagnostics: &mut Vec<Diagnostic>) -> ElectroCircuit {
+        let element_names = self.elements.iter().map(|x| x.get_name().to_lowercase()).collect::<Vec<String>>();
+
+        for (net, line_number) in self.nets.iter() {
+            // Nets and elements share one namespace in Scheesim, where some names are also taken.
+            let name = match element_names.contains(net) || ["next", "prev", "previous", "grnd"].contains(&net.as_str()) {
+                true => format!("net_{}", net),
+                false => net.clone(),
+            };
+
+            for element in self.elements.iter_mut() {
+                let rename = |connection: &mut ConnectionType| {
+                    if let ConnectionType::Named(x) = connection {
+                        if x == net {
+                            *x = name.clone();
+                        }
+                    }
+                };
+
+                element.get_connections_mut().for_each_mut(|_, connection| rename(connection));
+
+                for (_, connection) in element.get_pins_mut().iter_mut() {
+                    rename(connection);
+                }
+            }
+
+            if element_names.contains(&name) {
+                diagnostics.push(Diagnostic::new(*line_number, format!("Net '{}' clashes with an element name", net)));
+            }
+
+            let mut wire = ElectroCircuitNode::init();
+            wire.modify_name(name);
+            wire.modify_line_number(*line_number);
+
+            if self.probes.iter().any(|x| x.lowercase() == *net) {
+                wire.get_connections_mut().add_parallel_out(ConnectionType::Probe);
+            }
+
+            self.elements.push(wire);
+        }
+
+        for probe in self.probes.iter().filter(|x| !self.nets.iter().any(|(net, _)| *net == x.lowercase())) {
+            diagnostics.push(probe.diagnose(format!("There is no net named '{}' to print", probe.text)));
+        }
+
+        for element in self.elements {
+            self.circuit.add_node(element);
+        }
+
+        self.circuit
+    }
+}
+
+/// Makes a profile holding a single component with the given arguments.
+fn profile(name: &str, marker: ElementMarker, card: &Card, arguments: Vec<(Argument, &Word)>) -> ElectroCircuitNodeProfile {
+    let mut component = EelectroCircuitComponent::from(marker, card.line_number);
+
+    for (argument, word) in arguments {
+        // Words from continuation lines can't be pointed at on the line of the card.
+        let columns = match word.line_number == card.line_number {
+            true => word.columns.clone(),
+            false => 0..0,
+        };
+
+        component.push_argument(argument, (columns, word.text.clone()));
+    }
+
+    let mut profile = ElectroCircuitNodeProfile::init();
+    profile.modify_name(name.to_string());
+    profile.modify_line_number(card.line_number);
+    profile.init_components(1);
+    profile.set_nth_components(component, 0);
+
+    profile
+}
+
+/// Turns a SPICE value into the Scheesim argument `name` (e.g. `-resistance`). `{...}`
+/// expressions are kept for elaboration.
+fn value_argument(name: &str, word: &Word) -> Result<Argument, Diagnostic> {
+    match word.text.starts_with('{') {
+        true => Argument::from(&format!("{}={}", name, word.text), word.line_number)
+            .map_err(|x| word.diagnose(x.get_message().to_string())),
+        false => {
+            let value = spice_value(&word.text).ok_or(word.diagnose(format!("Not a SPICE value '{}'", word.text)))?;
+
+            Ok(match name {
+                "-voltage" => Argument::Voltage(Currentage::Solo(Unit::One(value))),
+                "-current" => Argument::Current(Currentage::Solo(Unit::One(value))),
+                "-inductance" => Argument::Inductance(Unit::One(value)),
+                "-capacitance" => Argument::Capacitance(Unit::One(value)),
+                "-frequency" => Argument::Frequency(Unit::One(value)),
+                _ => Argument::Resistance(Unit::One(value)),
+            })
+        }
+    }
+}
+
+/// Turns `name=value` words into parameters, plain values being written as Scheesim numbers.
+fn parameters(words: &[Word], diagnostics: &mut Vec<Diagnostic>) -> Vec<(String, String)> {
+    let mut params = vec![];
+
+    for word in words.iter().filter(|x| x.lowercase() != "params:") {
+        match word.text.split_once('=') {
+            Some((name, value)) if !name.is_empty() && !value.is_empty() => match value.starts_with('{') {
+                true => params.push((name.to_lowercase(), value.to_string())),
+                false => match spice_value(value) {
+                    Some(x) => params.push((name.to_lowercase(), format!("{}", x))),
+                    None => diagnostics.push(word.diagnose(format!("Not a SPICE value '{}'", value))),
+                },
+            },
+            _ => diagnostics.push(word.diagnose("Parameters are given as name=value".to_string())),
+        }
+    }
+
+    params
+}
+
+/// Everything an element card needs to know about the rest of the deck.
+struct Deck {
+    models: Vec<(String, Model)>,
+    subcircuits: Vec<(String, Vec<String>)>,
+    ac_frequency: Option<f64>,
+}
+
+impl Deck {
+    fn model(&self, word: &Word) -> Option<&Model> {
+        self.models.iter().find(|(x, _)| *x == word.lowercase()).map(|(_, model)| model)
+    }
+
+    /// The ports of the `.subckt` named `word`, in order.
+    fn ports(&self, word: &Word) -> Option<&Vec<String>> {
+        self.subcircuits.iter().find(|(x, _)| *x == word.lowercase()).map(|(_, ports)| ports)
+    }
+}
+
+/// Imports one element card into `builder`, reporting the ones Scheesim has no element for.
+fn import_element(
+    card: &Card,
+    deck: &Deck,
+    builder: &mut CircuitBuilder,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<(), Diagnostic> {
+    let words = &card.words;
+    let name = &words[0];
+    let letter = name.lowercase().chars().next().unwrap_or(' ');
+
+    let needs = |count: usize, form: &str| match words.len() >= count {
+        true => Ok(()),
+        false => Err(card.diagnose(format!("Card '{}' is too short, it is written as {}", name.text, form))),
+    };
+
+    match letter {
+        'r' | 'c' | 'l' => {
+            needs(4, "<name> <net> <net> <value>")?;
+
+            let (marker, argument) = match letter {
+                'r' => (ElementMarker::Resistor, "-resistance"),
+                'c' => (ElementMarker::Capacitor, "-capacitance"),
+                _ => (ElementMarker::Inductor, "-inductance"),
+            };
+
+            let value = value_argument(argument, &words[3])?;
+            builder.add_element(name, &words[1..3], vec![profile("default", marker, card, vec![(value, &words[3])])]);
+        }
+        'v' | 'i' => {
+            needs(4, "<name> <net> <net> [DC] <value> [AC <magnitude>]")?;
+
+            let argument = match letter {
+                'v' => "-voltage",
+                _ => "-current",
+            };
+
+            let mut dc = None;
+            let mut ac = None;
+            let mut rest = words[3..].iter();
+
+            while let Some(word) = rest.next() {
+                match word.lowercase().as_str() {
+                    "dc" => dc = rest.next(),
+                    "ac" => ac = rest.next(),
+                    "sin" | "pulse" | "pwl" | "exp" | "sffm" | "am" => {
+                        return Err(word.diagnose(format!("Transient source '{}' is not supported", word.text)))
+                    }
+                    _ if dc.is_none() => dc = Some(word),
+                    _ => return Err(word.diagnose(format!("Unexpected '{}' in source card", word.text))),
+                }
+            }
+
+            let mut profiles = vec![];
+
+            if let Some(word) = dc {
+                let value = value_argument(argument, word)?;
+                profiles.push(profile("default", ElementMarker::DCSource, card, vec![(value, word)]));
+            }
+
+            if let (Some(word), Some(frequency)) = (ac, deck.ac_frequency) {
+                let value = value_argument(argument, word)?;
+                let frequency = Argument::Frequency(Unit::One(frequency));
+                profiles.push(profile("ac", ElementMarker::ACSweep, card, vec![(value, word), (frequency, word)]));
+            }
+
+            // Scheesim sources drive their -out, which is n+ of a voltage source but n- of a
+            // current source, whose current flows from n+ through it to n-.
+            let pins = match letter {
+                'v' => [words[2].clone(), words[1].clone()],
+                _ => [words[1].clone(), words[2].clone()],
+            };
+
+            builder.add_element(name, &pins, profiles);
+        }
+        'd' => {
+            needs(4, "<name> <anode> <cathode> <model>")?;
+
+            let model = match deck.model(&words[3]) {
+                Some(model) if model.kind == ModelKind::Diode => model,
+                _ => return Err(words[3].diagnose(format!("There is no diode .model named '{}'", words[3].text))),
+            };
+
+            let mut arguments = vec![(Argument::JunctionChannel(JunctionChannel::PN), &words[3])];
+            arguments.extend(model.get_arguments()?);
+            builder.add_element(name, &words[1..3], vec![profile("default", ElementMarker::Diode, card, arguments)]);
+        }
+        'q' | 'm' => {
+            let model = match letter {
+                'q' => {
+                    needs(5, "<name> <collector> <base> <emitter> <model>")?;
+                    &words[4]
+                }
+                _ => {
+                    needs(6, "<name> <drain> <gate> <source> <bulk> <model>")?;
+                    &words[5]
+                }
+            };
+
+            let (junction_channel, parameters) = match deck.model(model).map(|x| (&x.kind, x)) {
+                Some((ModelKind::Npn, x)) if letter == 'q' => (JunctionChannel::NPN, x.get_arguments()?),
+                Some((ModelKind::Pnp, x)) if letter == 'q' => (JunctionChannel::PNP, x.get_arguments()?),
+                Some((ModelKind::Nmos, x)) if letter == 'm' => (JunctionChannel::N, x.get_arguments()?),
+                Some((ModelKind::Pmos, x)) if letter == 'm' => (JunctionChannel::P, x.get_arguments()?),
+                _ => return Err(model.diagnose(format!("There is no transistor .model named '{}' for this card", model.text))),
+            };
+
+            // Collector/drain in, emitter/source out, base/gate as the base.
+            let pins = [words[1].clone(), words[3].clone(), words[2].clone()];
+            let mut arguments = vec![(Argument::JunctionChannel(junction_channel), model)];
+            arguments.extend(parameters);
+            builder.add_element(name, &pins, vec![profile("default", ElementMarker::Transistor, card, arguments)]);
+        }
+        'x' => {
+            let end = card.parameters_start();
+            needs(3, "<name> <net>... <subcircuit> [params: name=value...]")?;
+
+            if end < 3 {
+                return Err(card.diagnose(format!("Card '{}' needs nets and a subcircuit", name.text)));
+            }
+
+            let pins = &words[1..end - 1];
+            let definition = &words[end - 1];
+
+            // The first three nets go through -in, -out and -base, the rest through -pin, named
+            // after the port in the same place on the .subckt card.
+            let ports = match (pins.len() > 3, deck.ports(definition)) {
+                (false, _) => vec![],
+                (true, Some(ports)) if ports.len() == pins.len() => ports[3..].to_vec(),
+                (true, _) => {
+                    return Err(definition.diagnose(format!(
+                        "There is no .subckt named '{}' with {} ports for this card",
+                        definition.text,
+                        pins.len()
+                    )))
+                }
+            };
+
+            let params = parameters(&words[end..], diagnostics);
+            builder.add_element(name, &pins[..pins.len().min(3)], vec![]);
+
+            let connections = pins.iter().skip(3).map(|pin| builder.connect(pin)).collect::<Vec<ConnectionType>>();
+            let node = builder.elements.last_mut().unwrap();
+            node.modify_instance(definition.lowercase());
+
+            for (name, value) in params {
+                node.add_param(name, value);
+            }
+
+            for (port, connection) in ports.into_iter().zip(connections) {
+                node.add_pin(port, connection);
+            }
+        }
+        'e' | 'f' | 'g' | 'h' => {
+            return Err(card.diagnose(format!("Controlled source '{}' is not supported yet", name.text)))
+        }
+        _ => return Err(card.diagnose(format!("SPICE element '{}' is not supported", name.text))),
+    }
+
+    Ok(())
+}
+
+/// Reads a SPICE deck into Scheesim circuits: the deck itself, named after its title, and one
+/// subcircuit per `.subckt`. Cards that can't be carried over are skipped and reported, so the
+/// rest of the deck still comes through.
+///
+/// Supported are `R`, `C`, `L`, independent `V`/`I` sources (DC, and AC when the deck has an
+/// `.ac` card, as an `ac` profile), `D`, `Q` and `M` with their `.model`, `X` instances,
+/// `.subckt`/`.ends`, `.param` and `.op`, and `.print` of node voltages, which are probed. Diode and BJT `.model` parameters become arguments of the
+/// element; the ones without a Scheesim argument are reported as warnings.
+pub fn import(deck: &str) -> (Vec<ElectroCircuit>, Vec<Diagnostic>) {
+    let (title, cards) = read_cards(deck);
+    let mut diagnostics = vec![];
+
+    let mut models = vec![];
+    let mut subcircuits = vec![];
+    let mut ac_frequency = None;
+
+    for card in cards.iter() {
+        match card.keyword().as_str() {
+            ".model" => match card.words.get(1..3) {
+                Some([name, kind]) => {
+                    let kind = kind.text.parse().unwrap_or(ModelKind::Other);
+                    models.push((name.lowercase(), Model::from(kind, &card.words[3..], &mut diagnostics)));
+                }
+                _ => diagnostics.push(card.diagnose("A .model needs a name and a type".to_string())),
+            },
+            ".subckt" => if let Some(name) = card.words.get(1) {
+                let ports = card.words[2..card.parameters_start().max(2)].iter().map(|x| x.lowercase()).collect();
+                subcircuits.push((name.lowercase(), ports));
+            },
+            ".ac" => match card.words.get(3).and_then(|x| spice_value(&x.text)) {
+                Some(frequency) => ac_frequency = Some(frequency),
+                None => diagnostics.push(card.diagnose("An .ac card is written as .ac <dec|oct|lin> <points> <start> <stop>".to_string())),
+            },
+            _ => (),
+        }
+    }
+
+    let deck = Deck { models, subcircuits, ac_frequency };
+
+    let name = title
+        .trim_start_matches('*')
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("_");
+
+    let mut top = CircuitBuilder::new(
+        match name.is_empty() {
+            true => "spice".to_string(),
+            false => name,
+        },
+        1,
+    );
+
+    if deck.ac_frequency.is_some() {
+        top.circuit.add_profile("ac".to_string());
+    }
+
+    let mut subcircuit: Option<CircuitBuilder> = None;
+    let mut circuits = vec![];
+
+    for card in cards.iter() {
+        let keyword = card.keyword();
+
+        match keyword.as_str() {
+            ".subckt" => {
+                if subcircuit.is_some() {
+                    diagnostics.push(card.diagnose("Subcircuits cannot be defined inside subcircuits".to_string()));
+                    continue;
+                }
+
+                let name = match card.words.get(1) {
+                    Some(name) => name.lowercase(),
+                    None => {
+                        diagnostics.push(card.diagnose("A .subckt needs a name".to_string()));
+                        continue;
+                    }
+                };
+
+                let end = card.parameters_start();
+                let mut builder = CircuitBuilder::new(name, card.line_number);
+                builder.circuit.modify_subcircuit(true);
+
+                for pin in card.words[2..end.max(2)].iter() {
+                    builder.circuit.add_port(pin.lowercase());
+                }
+
+                for (name, value) in parameters(&card.words[end..], &mut diagnostics) {
+                    builder.circuit.add_param(name, value);
+                }
+
+                subcircuit = Some(builder);
+            }
+            ".ends" => match subcircuit.take() {
+                Some(builder) => circuits.push(builder.finish(&mut diagnostics)),
+                None => diagnostics.push(card.diagnose(".ends without a .subckt".to_string())),
+            },
+            ".param" => {
+                let params = parameters(&card.words[1..], &mut diagnostics);
+                let circuit = match subcircuit.as_mut() {
+                    Some(builder) => &mut builder.circuit,
+                    None => &mut top.circuit,
+                };
+
+                for (name, value) in params {
+                    circuit.add_param(name, value);
+                }
+            }
+            ".print" | ".probe" => {
+                let builder = match subcircuit.as_mut() {
+                    Some(builder) => builder,
+                    None => &mut top,
+                };
+
+                let mut words = card.words[1..].iter();
+
+                while let Some(word) = words.next() {
+                    match word.lowercase().as_str() {
+                        "dc" | "op" | "ac" | "tran" => (),
+                        "v" => match words.next() {
+                            Some(net) => builder.probes.push(net.clone()),
+                            None => diagnostics.push(word.diagnose("A voltage is printed as v(net)".to_string())),
+                        },
+                        _ => {
+                            if word.lowercase() == "i" {
+                                words.next();
+                            }
+
+                            diagnostics.push(
+                                word.diagnose(format!("Only voltages such as v(out) can be printed, '{}' was skipped", word.text))
+                                    .as_warning(),
+                            );
+                        }
+                    }
+                }
+            }
+            ".model" | ".ac" | ".op" => (),
+            ".end" => break,
+            _ if keyword.starts_with('.') => {
+                diagnostics.push(card.diagnose(format!("SPICE card '{}' is not supported, it was skipped", card.words[0].text)))
+            }
+            _ => {
+                let builder = match subcircuit.as_mut() {
+                    Some(builder) => builder,
+                    None => &mut top,
+                };
+
+                if let Err(diagnostic) = import_element(card, &deck, builder, &mut diagnostics) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+
+    if let Some(builder) = subcircuit {
+        diagnostics.push(Diagnostic::new(
+            builder.circuit.get_line_number(),
+            format!("Subcircuit '{}' is never closed with .ends", builder.circuit.get_name()),
+        ));
+    }
+
+    circuits.push(top.finish(&mut diagnostics));
+    diagnostics.sort_by_key(|x| (x.get_line_number(), x.get_span().start));
+
+    (circuits, diagnostics)
+}
+
+/// Writes a value the way SPICE reads it, where `M` is milli and mega is `meg`.
+fn spice_number(unit: &Unit) -> String {
+    let value = unit.get_corresponding_value();
+    let written = Unit::One(value).to_string();
+
+    match written.chars().last() {
+        Some('M') => format!("{}meg", &written[..written.len() - 1]),
+        Some('T' | 'G' | 'k' | 'm' | 'u' | 'n' | 'p' | 'f') => written,
+        Some(ch) if ch.is_ascii_digit() => written,
+        _ => format!("{:e}", value),
+    }
+}
+
+/// SPICE element names start with the letter of the element and can't hold the dots
+/// elaboration puts in node names.
+fn spice_name(letter: char, name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|x| match x.is_ascii_alphanumeric() {
+            true => x,
+            false => '_',
+        })
+        .collect::<String>();
+
+    match name.to_uppercase().starts_with(letter) {
+        true => name,
+        false => format!("{}{}", letter, name),
+    }
+}
+
+/// The parameters of a `.model` card out of the arguments of an element, as `(name, argument,
+/// default)`. A parameter that wasn't given is written with its default when it has one, and
+/// left out otherwise.
+type ModelParameter = (&'static str, fn(&Argument) -> Option<&Unit>, Option<f64>);
+
+fn model_parameters(arguments: &[Argument], parameters: &[ModelParameter]) -> String {
+    parameters
+        .iter()
+        .filter_map(|(name, wanted, default)| {
+            let value = arguments.iter().find_map(|x| wanted(x).cloned()).or(default.map(Unit::One))?;
+            Some(format!("{}={}", name, spice_number(&value)))
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Writes an elaborated circuit as a SPICE deck, simulating `profile` and falling back to
+/// `default` on nodes that don't have it. The nets are those of `NetGraph`, ground being net 0.
+/// Probed nets are printed with `.print`.
+pub fn export(circuit: &ElectroCircuit, profile: &str) -> Result<String, ParseError> {
+    let graph = NetGraph::from(circuit, profile)?;
+    let branches = graph.get_branches();
+    let mut diagnostics = vec![];
+    let mut cards = vec![];
+    let mut models = vec![];
+    let mut ac_frequency = None;
+
+    for branch in branches.iter() {
+        let (from, to) = (branch.get_from(), branch.get_to());
+        let arguments = branch.get_component().get_arguments().unwrap().get_args();
+        let diagnose = |message: String| Diagnostic::new(branch.get_line_number(), message);
+
+        if arguments.iter().any(|x| matches!(x, Argument::Deferred(_, _))) {
+            diagnostics.push(diagnose(format!("'{}' has parameters left, elaborate it first", branch.get_name())));
+            continue;
+        }
+
+        let unit = |wanted: fn(&Argument) -> Option<&Unit>| arguments.iter().find_map(|x| wanted(x).cloned());
+
+        let (letter, pins, rest) = match branch.get_component() {
+            EelectroCircuitComponent::Resistor(_) => match unit(|x| match x {
+                Argument::Resistance(unit) => Some(unit),
+                _ => None,
+            }) {
+                Some(unit) => ('R', vec![from, to], spice_number(&unit)),
+                None => {
+                    diagnostics.push(diagnose("Resistor has no -resistance".to_string()));
+                    continue;
+                }
+            },
+            EelectroCircuitComponent::Capacitor(_) => match unit(|x| match x {
+                Argument::Capacitance(unit) => Some(unit),
+                _ => None,
+            }) {
+                Some(unit) => ('C', vec![from, to], spice_number(&unit)),
+                None => {
+                    diagnostics.push(diagnose("Capacitor has no -capacitance".to_string()));
+                    continue;
+                }
+            },
+            EelectroCircuitComponent::Inductor(_) => match unit(|x| match x {
+                Argument::Inductance(unit) => Some(unit),
+                _ => None,
+            }) {
+                Some(unit) => ('L', vec![from, to], spice_number(&unit)),
+                None => {
+                    diagnostics.push(diagnose("Inductor has no -inductance".to_string()));
+                    continue;
+                }
+            },
+            EelectroCircuitComponent::DCSource(_) | EelectroCircuitComponent::ACSweep(_) => {
+                if arguments.iter().any(|x| {
+                    matches!(
+                        x,
+                        Argument::Voltage(Currentage::Dom(_) | Currentage::Sub(_))
+                            | Argument::Current(Currentage::Dom(_) | Currentage::Sub(_))
+                    )
+                }) {
+                    diagnostics.push(diagnose("Controlled sources cannot be exported yet".to_string()));
+                    continue;
+                }
+
+                let voltage = unit(|x| match x {
+                    Argument::Voltage(Currentage::Solo(unit)) | Argument::MaxVoltage(unit) => Some(unit),
+                    _ => None,
+                });
+
+                let current = unit(|x| match x {
+                    Argument::Current(Currentage::Solo(unit)) => Some(unit),
+                    _ => None,
+                });
+
+                let sweep = matches!(branch.get_component(), EelectroCircuitComponent::ACSweep(_));
+
+                if sweep {
+                    if let Some(frequency) = unit(|x| match x {
+                        Argument::Frequency(unit) => Some(unit),
+                        _ => None,
+                    }) {
+                        ac_frequency = Some(frequency.get_corresponding_value());
+                    }
+                }
+
+                let value = |unit: &Unit| match sweep {
+                    true => format!("DC 0 AC {}", spice_number(unit)),
+                    false => format!("DC {}", spice_number(unit)),
+                };
+
+                // The source drives its -out, so that is the positive pin of a voltage
+                // source and where the current of a current source comes out.
+                match (voltage, current) {
+                    (Some(voltage), _) => ('V', vec![to, from], value(&voltage)),
+                    (None, Some(current)) => ('I', vec![from, to], value(&current)),
+                    (None, None) => {
+                        diagnostics.push(diagnose("Source has neither -voltage nor -current".to_string()));
+                        continue;
+                    }
+                }
+            }
+            EelectroCircuitComponent::Diode(_) => {
+                // Each diode gets a model of its own, named after it.
+                let model = spice_name('D', branch.get_name());
+
+                let parameters = model_parameters(
+                    &arguments,
+                    &[
+                        ("IS", |x| match x {
+                            Argument::SaturationCurrent(unit) => Some(unit),
+                            _ => None,
+                        }, Some(1e-14)),
+                        ("N", |x| match x {
+                            Argument::Emission(unit) => Some(unit),
+                            _ => None,
+                        }, Some(1.0)),
+                        ("RS", |x| match x {
+                            Argument::Resistance(unit) => Some(unit),
+                            _ => None,
+                        }, None),
+                        ("BV", |x| match x {
+                            Argument::BreakdownVoltage(unit) => Some(unit),
+                            _ => None,
+                        }, None),
+                        ("CJO", |x| match x {
+                            Argument::Capacitance(unit) => Some(unit),
+                            _ => None,
+                        }, None),
+                        ("TT", |x| match x {
+                            Argument::TransitTime(unit) => Some(unit),
+                            _ => None,
+                        }, None),
+                    ],
+                );
+
+                models.push(format!("{} D({})", model, parameters));
+
+                // The anode comes first, which is -out of an np diode.
+                let pins = match arguments.iter().any(|x| matches!(x, Argument::JunctionChannel(JunctionChannel::NP))) {
+                    true => vec![to, from],
+                    false => vec![from, to],
+                };
+
+                ('D', pins, model)
+            }
+            EelectroCircuitComponent::Transistor(_) => {
+                let kind = arguments.iter().find_map(|x| match x {
+                    Argument::JunctionChannel(kind) => Some(kind.clone()),
+                    _ => None,
+                });
+
+                let base = branch.get_base().unwrap();
+
+                match kind {
+                    Some(JunctionChannel::N | JunctionChannel::P) => {
+                        let model = match kind {
+                            Some(JunctionChannel::N) => "MNMOS NMOS",
+                            _ => "MPMOS PMOS",
+                        };
+
+                        if !models.iter().any(|x| x == model) {
+                            models.push(model.to_string());
+                        }
+
+                        ('M', vec![from, base, to, to], model.split(' ').next().unwrap().to_string())
+                    }
+                    _ => {
+                        // Each BJT gets a Gummel-Poon model of its own, named after it.
+                        let model = spice_name('Q', branch.get_name());
+                        let kind = match kind {
+                            Some(JunctionChannel::PNP) => ModelKind::Pnp,
+                            _ => ModelKind::Npn,
+                        };
+
+                        let parameters = model_parameters(
+                            &arguments,
+                            &[
+                                ("IS", |x| match x {
+                                    Argument::SaturationCurrent(unit) => Some(unit),
+                                    _ => None,
+                                }, Some(1e-16)),
+                                ("NF", |x| match x {
+                                    Argument::Emission(unit) => Some(unit),
+                                    _ => None,
+                                }, Some(1.0)),
+                                ("BF", |x| match x {
+                                    Argument::Beta(unit) => Some(unit),
+                                    _ => None,
+                                }, Some(100.0)),
+                                ("BR", |x| match x {
+                                    Argument::BetaReverse(unit) => Some(unit),
+                                    _ => None,
+                                }, Some(1.0)),
+                                ("VAF", |x| match x {
+                                    Argument::EarlyVoltage(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                                ("VAR", |x| match x {
+                                    Argument::EarlyVoltageReverse(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                                ("IKF", |x| match x {
+                                    Argument::KneeCurrent(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                                ("ISE", |x| match x {
+                                    Argument::LeakageCurrent(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                                ("CJE", |x| match x {
+                                    Argument::EmitterCapacitance(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                                ("CJC", |x| match x {
+                                    Argument::CollectorCapacitance(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                                ("TF", |x| match x {
+                                    Argument::TransitTime(unit) => Some(unit),
+                                    _ => None,
+                                }, None),
+                            ],
+                        );
+
+                        models.push(format!("{} {}({})", model, kind, parameters));
+
+                        ('Q', vec![from, base, to], model)
+                    }
+                }
+            }
+            EelectroCircuitComponent::Init => unreachable!(),
+        };
+
+        cards.push((spice_name(letter, branch.get_name()), pins, rest));
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(ParseError::new(diagnostics));
+    }
+
+    let mut deck = format!("* {}\n* profile {}\n", circuit.get_name(), profile);
+
+    for (name, pins, rest) in cards {
+        let pins = pins.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        deck.push_str(&format!("{} {} {}\n", name, pins.join(" "), rest));
+    }
+
+    for model in models {
+        deck.push_str(&format!(".model {}\n", model));
+    }
+
+    let analysis = match ac_frequency {
+        Some(frequency) => {
+            deck.push_str(&format!(".ac lin 1 {} {}\n", frequency, frequency));
+            "ac"
+        }
+        None => {
+            deck.push_str(".op\n");
+            "dc"
+        }
+    };
+
+    if !graph.get_probes().is_empty() {
+        let probed = graph
+            .get_probes()
+            .iter()
+            .map(|(_, net)| format!("v({})", net))
+            .collect::<Vec<String>>();
+
+        deck.push_str(&format!(".print {} {}\n", analysis, probed.join(" ")));
+    }
+
+    deck.push_str(".end\n");
+
+    Ok(deck)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The nets a node of `circuit` is joined to through -in, -out and -base.
+    fn pins(circuit: &ElectroCircuit, node: &str) -> (String, String, Option<String>) {
+        let connections = circuit.find_node(node).unwrap().get_connections();
+
+        (
+            connections.get_serial_in().to_string(),
+            connections.get_serial_out().to_string(),
+            connections.get_serial_base().map(|x| x.to_string()),
+        )
+    }
+
+    /// The arguments of the component `node` has in its `default` profile, as written in a netlist.
+    fn arguments(circuit: &ElectroCircuit, node: &str) -> Vec<String> {
+        let profile = circuit.find_node(node).unwrap().select_profile("default").unwrap();
+
+        profile.get_components()[0].get_arguments().unwrap().get_args().iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn spice_values_take_scale_factors_and_ignore_units() {
+        let values = [
+            ("4.7k", Some(4.7e3)),
+            ("1meg", Some(1e6)),
+            ("1MEG", Some(1e6)),
+            ("10uF", Some(10e-6)),
+            ("1mil", Some(25.4e-6)),
+            ("2.5e-3", Some(2.5e-3)),
+            ("1f", Some(1e-15)),
+            ("3mV", Some(3e-3)),
+            ("-2", Some(-2.0)),
+            ("k1", None),
+        ];
+
+        for (s, expected) in values {
+            match (spice_value(s), expected) {
+                (Some(value), Some(expected)) => assert!((value - expected).abs() <= expected.abs() * 1e-12, "{}: {}", s, value),
+                (value, expected) => assert_eq!(value, expected, "{}", s),
+            }
+        }
+    }
+
+    #[test]
+    fn continuation_lines_and_comments_are_read_through() {
+        let (circuits, diagnostics) = import(
+            "* a divider\n\
+             V1 in 0 ; the supply\n\
+             + DC 5 $ volts\n\
+             * R2 in 0 1k\n\
+             R1 in out 4.7k\n\
+             R2 out 0\n\
+             +   1meg ; to ground\n\
+             .end\n\
+             R3 out 0 1\n",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics.iter().map(|x| x.to_string()).collect::<Vec<String>>());
+
+        let circuit = circuits.find_circuit("a_divider").unwrap();
+        assert_eq!(arguments(circuit, "V1"), ["-voltage=5"]);
+        assert_eq!(arguments(circuit, "R1"), ["-resistance=4.7k"]);
+        assert_eq!(arguments(circuit, "R2"), ["-resistance=1M"]);
+        assert!(circuit.find_node("R3").is_none());
+    }
+
+    #[test]
+    fn model_parameters_carry_over_or_are_reported() {
+        let (circuits, diagnostics) = import(
+            "models\n\
+             .model dmod D (IS=1e-14 N=1.5 RS=2 BV=50 XTI=3)\n\
+             .model qmod NPN BF=200 VAF=80 CJE=2p\n\
+             D1 a 0 dmod\n\
+             Q1 c b 0 qmod\n\
+             R1 a c 1k\n\
+             R2 b 0 1k\n",
+        );
+
+        let circuit = circuits.find_circuit("models").unwrap();
+        assert_eq!(
+            arguments(circuit, "D1"),
+            [
+                "-junction=pn",
+                "-saturation_current=10f",
+                "-emission=1.5",
+                "-resistance=2",
+                "-breakdown_voltage=50",
+            ]
+        );
+        assert_eq!(
+            arguments(circuit, "Q1"),
+            ["-junction=npn", "-beta=200", "-early_voltage=80", "-emitter_capacitance=2p"]
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_warning());
+        assert_eq!(diagnostics[0].get_message(), "Model parameter 'XTI' has no Scheesim argument, it was dropped");
+        assert_eq!((diagnostics[0].get_line_number(), diagnostics[0].get_lexeme()), (2, "XTI=3"));
+    }
+
+    #[test]
+    fn sources_drive_their_out_pin() {
+        let (circuits, diagnostics) = import("sources\nV1 p 0 DC 5\nI1 p 0 2m\nR1 p 0 1k\n");
+        assert!(diagnostics.is_empty());
+
+        let circuit = &circuits[0];
+
+        // A voltage source is swapped so its n+ is -out, a current source already drives n-.
+        assert_eq!(pins(circuit, "V1"), ("ground".to_string(), "p".to_string(), None));
+        assert_eq!(pins(circuit, "I1"), ("p".to_string(), "ground".to_string(), None));
+        assert_eq!(pins(circuit, "R1"), ("p".to_string(), "ground".to_string(), None));
+    }
+
+    #[test]
+    fn instances_carry_their_params_and_every_pin() {
+        let (circuits, diagnostics) = import(
+            "instances\n\
+             .subckt stage inp out inn vcc vee params: g=2\n\
+             R1 inp inn {g*1k}\n\
+             R2 vcc out 1k\n\
+             R3 vee out 1k\n\
+             .ends\n\
+             V1 vdd 0 5\n\
+             X1 a b 0 vdd 0 stage params: g=3\n\
+             X2 a b stage\n\
+             R1 a 0 1k\n\
+             R2 b 0 1k\n",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics.iter().map(|x| x.to_string()).collect::<Vec<String>>());
+
+        let stage = circuits.find_circuit("stage").unwrap();
+        assert!(stage.is_subcircuit());
+        assert_eq!(stage.get_ports(), &["inp", "out", "inn", "vcc", "vee"]);
+        assert_eq!(stage.get_params(), &vec![("g".to_string(), "2".to_string())]);
+
+        let top = circuits.find_circuit("instances").unwrap();
+        let x1 = top.find_node("X1").unwrap();
+        assert_eq!(x1.get_instance().map(|x| x.as_str()), Some("stage"));
+        assert_eq!(x1.get_params(), &vec![("g".to_string(), "3".to_string())]);
+        assert_eq!(pins(top, "X1"), ("a".to_string(), "b".to_string(), Some("ground".to_string())));
+        assert_eq!(
+            x1.get_pins().iter().map(|(port, x)| format!("{}={}", port, x)).collect::<Vec<String>>(),
+            ["vcc=vdd", "vee=ground"]
+        );
+        assert!(top.find_node("X2").unwrap().get_pins().is_empty());
+
+        let elaborated = match elaborate(&circuits) {
+            Ok(elaborated) => elaborated,
+            Err(e) => panic!("{}", e),
+        };
+
+        assert_eq!(arguments(&elaborated[0], "X1.R1"), ["-resistance=3k"]);
+        assert_eq!(arguments(&elaborated[0], "X2.R1"), ["-resistance=2k"]);
+
+        let (_, diagnostics) = import("short\nX1 a b c d nothing\nR1 a 0 1\n");
+        assert_eq!(diagnostics[0].get_message(), "There is no .subckt named 'nothing' with 4 ports for this card");
+    }
+
+    #[test]
+    fn exported_decks_import_and_export_card_for_card() {
+        let netlist = ";amp\n\
+                       ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=5\n\
+                       ;;d1 -in=prev -out=next,\n;;;default .diode -junction=np -saturation_current=1p\n\
+                       ;;rc -in=prev -out=$PROBE,\n;;;default .resistor -resistance=4.7k\n\
+                       ;;q1 -in=rc -out=ground -base=src,\n;;;default .transistor -junction=npn -beta=150\n\
+                       ;;bias -in=src -out=ground,\n;;;default .resistor -resistance=100k\n;\n";
+
+        let exported = match export(&parse(netlist).unwrap()[0], "default") {
+            Ok(exported) => exported,
+            Err(e) => panic!("{}", e),
+        };
+
+        let cards = exported.lines().collect::<Vec<&str>>();
+
+        // Ground is net 0 and a source's -out is its n+.
+        assert!(cards.contains(&"Vsrc 1 0 DC 5"), "{}", exported);
+        // An np diode has its anode on -out.
+        assert!(cards.contains(&"d1 2 1 d1"), "{}", exported);
+        assert!(cards.contains(&"q1 3 1 0 q1"), "{}", exported);
+        // Each diode and transistor gets a .model of its own.
+        assert!(cards.iter().any(|x| x.starts_with(".model d1 D(IS=1p ")), "{}", exported);
+        assert!(cards.iter().any(|x| x.starts_with(".model q1 NPN(") && x.contains("BF=150")), "{}", exported);
+        assert!(cards.contains(&".print dc v(3)"), "{}", exported);
+
+        let (circuits, diagnostics) = import(&exported);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics.iter().map(|x| x.to_string()).collect::<Vec<String>>());
+
+        let printed = format_netlist(&circuits);
+        let reparsed = match parse(&printed).and_then(|x| elaborate(&x)) {
+            Ok(circuits) => circuits,
+            Err(e) => panic!("{}\n{}", e, printed),
+        };
+
+        assert_eq!(export(&reparsed[0], "default").ok(), Some(exported.clone()), "{}", printed);
+
+        // A lone `f` is femto, as it is in SPICE, not farads.
+        let small = ";c\n;;c1 -in=ground -out=next,\n;;;default .capacitor -capacitance=1f\n\
+                     ;;r1 -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n;\n";
+        let exported = export(&parse(small).unwrap()[0], "default").unwrap_or_default();
+        assert!(exported.lines().any(|x| x == "c1 0 1 1f"), "{}", exported);
+    }
+}
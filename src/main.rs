@@ -1,3 +1,526 @@
+use std::{env, fs, io, process, sync::{mpsc, Arc}, thread};
+
+use scheesim_concurrent::ThreadPool;
+use scheesim_lexparse::*;
+use scheesim_mna::{check_ratings, check_topology, MnaSolution};
+use scheesim_spice::{export, import};
+
+const USAGE: &str = "Usage: scheesim <netlist.schnl> [--circuit <name>] [--profile <name>]\n       scheesim batch <netlist.schnl> [--circuit <name>]\n       scheesim fmt [--check] <netlist.schnl>...\n       scheesim import <deck.cir>\n       scheesim export <netlist.schnl> [--circuit <name>] [--profile <name>]\n       scheesim lsp";
+
+fn error_out(message: &str) -> ! {
+    eprintln!("\x1b[1;31mError:\x1b[0m {}", message);
+    process::exit(1);
+}
+
+struct Options {
+    netlist: String,
+    circuit: Option<String>,
+    profile: Option<String>,
+}
+
+impl Options {
+    fn from(args: Vec<String>) -> Result<Self, String> {
+        let mut netlist = None;
+        let mut circuit = None;
+        let mut profile = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--circuit" | "-c" => match args.next() {
+                    Some(name) => circuit = Some(name),
+                    None => return Err("--circuit needs the name of a circuit".to_string()),
+                },
+                "--profile" | "-p" => match args.next() {
+                    Some(name) => profile = Some(name),
+                    None => return Err("--profile needs the name of a profile".to_string()),
+                },
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'\n{}", arg, USAGE)),
+                _ => match netlist {
+                    None => netlist = Some(arg),
+                    Some(_) => return Err(format!("Only one netlist can be given\n{}", USAGE)),
+                },
+            }
+        }
+
+        match netlist {
+            Some(netlist) => Ok(Self { netlist, circuit, profile }),
+            None => Err(USAGE.to_string()),
+        }
+    }
+}
+
+/// Picks the circuit to simulate. A name has to be given when the netlist holds more than one.
+fn select_circuit<'a>(circuits: &'a Vec<ElectroCircuit>, name: Option<&String>) -> Result<&'a ElectroCircuit, String> {
+    match name {
+        Some(name) => circuits.find_circuit(name).ok_or(format!(
+            "There is no circuit named '{}', available circuits: {}",
+            name,
+            circuits.circuit_names().join(", ")
+        )),
+        None => match circuits.len() {
+            1 => Ok(&circuits[0]),
+            _ => Err(format!(
+                "The netlist holds several circuits, choose one with --circuit: {}",
+                circuits.circuit_names().join(", ")
+            )),
+        },
+    }
+}
+
+fn describe(circuit: &ElectroCircuit, profile: &str, graph: &NetGraph) {
+    println!(
+        "Circuit '{}' with profile '{}' ({} nodes, {} nets)",
+        circuit.get_name(),
+        profile,
+        circuit.get_nodes().len(),
+        graph.get_net_count()
+    );
+
+    for (i, node) in circuit.get_nodes().iter().enumerate() {
+        let profiles = node
+            .get_profiles()
+            .iter()
+            .map(|x| x.get_name())
+            .collect::<Vec<&str>>();
+
+        let net = |terminal| match graph.get_terminal_net(i, terminal) {
+            Some(net) => net.to_string(),
+            None => "-".to_string(),
+        };
+
+        println!(
+            "  {} [{}] nets {} -> {}",
+            node.get_name(),
+            profiles.join(", "),
+            net(Terminal::In),
+            net(Terminal::Out)
+        );
+    }
+}
+
+/// Prints the DC operating point: every net, then the probes and the branch currents.
+fn report(graph: &NetGraph, solution: &MnaSolution) {
+    let volts = |x: f64| Quantity::from(x, Dimension::VOLT);
+    let amps = |x: f64| Quantity::from(x, Dimension::AMPERE);
+
+    println!("DC operating point");
+
+    for net in 1..graph.get_net_count() {
+        println!("  v({}) = {}", net, volts(solution.get_voltage(net)));
+    }
+
+    for (name, net) in graph.get_probes() {
+        println!("  v({}) = {}", name, volts(solution.get_voltage(*net)));
+    }
+
+    for (name, current) in solution.get_currents() {
+        println!("  i({}) = {}", name, amps(*current));
+    }
+}
+
+/// What one profile of a circuit gives for each of its probes.
+fn probe_profile(circuit: &ElectroCircuit, profile: &str) -> Result<Vec<(String, String)>, ParseError> {
+    let graph = NetGraph::from(circuit, profile)?;
+    check_topology(&graph)?;
+    let solution = MnaSolution::from(&graph)?;
+
+    Ok(graph
+        .get_probes()
+        .iter()
+        .map(|(name, net)| (format!("v({})", name), Quantity::from(solution.get_voltage(*net), Dimension::VOLT).to_string()))
+        .collect())
+}
+
+/// Lays out the probes as rows and the profiles as columns, with a dash where a profile failed.
+fn format_report(profiles: &[String], outcomes: &[Result<Vec<(String, String)>, ParseError>]) -> String {
+    let mut rows: Vec<String> = vec![];
+
+    for (probe, _) in outcomes.iter().flatten().flatten() {
+        if !rows.contains(probe) {
+            rows.push(probe.clone());
+        }
+    }
+
+    let mut table = vec![];
+    let mut header = vec!["probe".to_string()];
+    header.extend(profiles.iter().cloned());
+    table.push(header);
+
+    for probe in rows.iter() {
+        let mut row = vec![probe.clone()];
+
+        for outcome in outcomes.iter() {
+            let cell = match outcome {
+                Ok(values) => values.iter().find(|(x, _)| x == probe).map_or("-".to_string(), |(_, v)| v.clone()),
+                Err(_) => "-".to_string(),
+            };
+
+            row.push(cell);
+        }
+
+        table.push(row);
+    }
+
+    let widths = (0..=profiles.len())
+        .map(|i| table.iter().map(|x| x[i].chars().count()).max().unwrap_or(0))
+        .collect::<Vec<usize>>();
+
+    table
+        .iter()
+        .map(|row| {
+            let cells = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<String>>();
+
+            format!("{}\n", cells.join("  ").trim_end())
+        })
+        .collect()
+}
+
+/// Runs `probe_profile` for every profile on a thread pool, giving the outcomes in the order of
+/// `profiles`.
+fn probe_profiles(circuit: &Arc<ElectroCircuit>, profiles: &[String]) -> Vec<Result<Vec<(String, String)>, ParseError>> {
+    let threads = thread::available_parallelism().map_or(1, |x| x.get()).min(profiles.len()).max(1);
+    let (sender, receiver) = mpsc::channel();
+
+    // The pool joins its workers when it drops, so every profile is done once this block ends.
+    {
+        let pool = ThreadPool::new(threads);
+
+        for (i, profile) in profiles.iter().enumerate() {
+            let circuit = Arc::clone(circuit);
+            let profile = profile.clone();
+            let sender = sender.clone();
+
+            pool.execute(move || {
+                sender.send((i, probe_profile(&circuit, &profile))).unwrap();
+            });
+        }
+    }
+
+    drop(sender);
+
+    let mut outcomes = receiver.iter().collect::<Vec<(usize, Result<Vec<(String, String)>, ParseError>)>>();
+    outcomes.sort_by_key(|(i, _)| *i);
+    outcomes.into_iter().map(|(_, x)| x).collect()
+}
+
+/// `scheesim batch`: runs `default` and every profile the circuit declares side by side on a
+/// thread pool, then prints one report with a column per profile.
+fn run_batch(args: Vec<String>) {
+    let options = match Options::from(args) {
+        Ok(options) => options,
+        Err(message) => error_out(&message),
+    };
+
+    if options.profile.is_some() {
+        error_out("A batch runs every profile of the circuit, --profile cannot be given");
+    }
+
+    let (source_map, circuits) = load(&options);
+
+    let circuit = match select_circuit(&circuits, options.circuit.as_ref()) {
+        Ok(circuit) => Arc::new(circuit.clone()),
+        Err(message) => error_out(&message),
+    };
+
+    let mut profiles = vec!["default".to_string()];
+    profiles.extend(circuit.get_profiles().iter().filter(|x| x.as_str() != "default").cloned());
+
+    let outcomes = probe_profiles(&circuit, &profiles);
+
+    let mut failed = vec![];
+
+    for (profile, outcome) in profiles.iter().zip(outcomes.iter()) {
+        if let Err(e) = outcome {
+            eprint!("{}", source_map.render(e));
+            failed.push(profile.as_str());
+        }
+    }
+
+    println!("Circuit '{}' ({} profiles)", circuit.get_name(), profiles.len());
+    print!("{}", format_report(&profiles, &outcomes));
+
+    if !failed.is_empty() {
+        error_out(&format!("Profiles {} of '{}' failed", failed.join(", "), circuit.get_name()));
+    }
+}
+
+/// `scheesim fmt`: rewrites netlists in canonical form, or with `--check` only lists the ones
+/// that are not and fails if there are any.
+fn format_files(args: Vec<String>) {
+    let mut check = false;
+    let mut files = vec![];
+
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--help" | "-h" => error_out(USAGE),
+            _ if arg.starts_with('-') => error_out(&format!("Unknown option '{}'\n{}", arg, USAGE)),
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        error_out(USAGE);
+    }
+
+    let mut unformatted = vec![];
+
+    for file in files {
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(e) => error_out(&format!("Reading file '{}' to string: {}", file, e)),
+        };
+
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprint!("{}", e.render(&source));
+                error_out(&format!("Could not parse '{}'", file));
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+
+        match check {
+            true => unformatted.push(file),
+            false => if let Err(e) = fs::write(&file, formatted) {
+                error_out(&format!("Writing file '{}': {}", file, e));
+            },
+        }
+    }
+
+    if !unformatted.is_empty() {
+        for file in unformatted.iter() {
+            eprintln!("Would reformat '{}'", file);
+        }
+
+        process::exit(1);
+    }
+}
+
+/// `scheesim import`: prints a SPICE deck as a netlist, reporting the cards that were skipped.
+fn import_deck(args: Vec<String>) {
+    let deck = match args.as_slice() {
+        [deck] if !deck.starts_with('-') => deck,
+        _ => error_out(USAGE),
+    };
+
+    let source = match fs::read_to_string(deck) {
+        Ok(source) => source,
+        Err(e) => error_out(&format!("Reading file '{}' to string: {}", deck, e)),
+    };
+
+    let (circuits, diagnostics) = import(&source);
+
+    if !diagnostics.is_empty() {
+        eprint!("{}", ParseError::new(diagnostics).render(&source));
+    }
+
+    print!("{}", format_netlist(&circuits));
+}
+
+/// Reads, parses and elaborates the netlist the options name, bailing out on any error. Only the
+/// circuit named with `--circuit`, if there is one, has to have its profiles in order.
+fn load(options: &Options) -> (SourceMap, Vec<ElectroCircuit>) {
+    let source_map = match SourceMap::from_file(&options.netlist) {
+        Ok(source_map) => source_map,
+        Err(e) => error_out(e.get_message()),
+    };
+
+    if !source_map.get_diagnostics().is_empty() {
+        eprint!("{}", source_map.render(&ParseError::new(source_map.get_diagnostics().clone())));
+        error_out(&format!("Could not include the netlists '{}' asks for", options.netlist));
+    }
+
+    let circuits = match parse(source_map.get_text()) {
+        Ok(circuits) => circuits,
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("Could not parse '{}'", options.netlist));
+        }
+    };
+
+    let mut errors = vec![];
+    let mut warnings = vec![];
+
+    // The profiles of circuits other than the one asked for don't stop it from running.
+    let selected = options.circuit.as_ref().and_then(|x| circuits.find_circuit(x)).map(|x| x.get_name().to_string());
+
+    for circuit in circuits.iter() {
+        let is_selected = selected.as_ref().is_none_or(|x| x == circuit.get_name());
+
+        match (circuit.check_profiles(), is_selected) {
+            (Ok(found), _) => warnings.extend(found),
+            (Err(e), true) => errors.extend(e.get_diagnostics().iter().cloned()),
+            (Err(e), false) => warnings.extend(e.get_diagnostics().iter().cloned().map(|x| x.as_warning())),
+        }
+    }
+
+    if !warnings.is_empty() {
+        eprint!("{}", source_map.render(&ParseError::new(warnings)));
+    }
+
+    if !errors.is_empty() {
+        eprint!("{}", source_map.render(&ParseError::new(errors)));
+        error_out(&format!("The profiles of '{}' don't add up", options.netlist));
+    }
+
+    match elaborate(&circuits) {
+        Ok(circuits) => (source_map, circuits),
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("Could not elaborate '{}'", options.netlist));
+        }
+    }
+}
+
+/// `scheesim export`: prints a circuit of a netlist as a SPICE deck.
+fn export_deck(args: Vec<String>) {
+    let options = match Options::from(args) {
+        Ok(options) => options,
+        Err(message) => error_out(&message),
+    };
+
+    let (source_map, circuits) = load(&options);
+
+    let circuit = match select_circuit(&circuits, options.circuit.as_ref()) {
+        Ok(circuit) => circuit,
+        Err(message) => error_out(&message),
+    };
+
+    match export(circuit, options.profile.as_deref().unwrap_or("default")) {
+        Ok(deck) => print!("{}", deck),
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("Could not export '{}'", circuit.get_name()));
+        }
+    }
+}
+
 fn main() {
-   
+    let mut args = env::args().skip(1).collect::<Vec<String>>();
+
+    match args.first().map(|x| x.as_str()) {
+        Some("batch") => return run_batch(args.split_off(1)),
+        Some("fmt") => return format_files(args.split_off(1)),
+        Some("import") => return import_deck(args.split_off(1)),
+        Some("export") => return export_deck(args.split_off(1)),
+        Some("lsp") => {
+            if let Err(e) = scheesim_lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+                error_out(&format!("Language server stopped: {}", e));
+            }
+
+            return;
+        }
+        _ => (),
+    }
+
+    let options = match Options::from(args) {
+        Ok(options) => options,
+        Err(message) => error_out(&message),
+    };
+
+    let (source_map, circuits) = load(&options);
+
+    let circuit = match select_circuit(&circuits, options.circuit.as_ref()) {
+        Ok(circuit) => circuit,
+        Err(message) => error_out(&message),
+    };
+
+    let profile = options.profile.as_deref().unwrap_or("default");
+
+    let graph = match NetGraph::from(circuit, profile) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("Could not connect '{}'", circuit.get_name()));
+        }
+    };
+
+    match check_topology(&graph) {
+        Ok(warnings) if !warnings.is_empty() => eprint!("{}", source_map.render(&ParseError::new(warnings))),
+        Ok(_) => (),
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("'{}' cannot be solved as it is", circuit.get_name()));
+        }
+    }
+
+    describe(circuit, profile, &graph);
+
+    let solution = match MnaSolution::from(&graph) {
+        Ok(solution) => solution,
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("Could not solve '{}'", circuit.get_name()));
+        }
+    };
+
+    match check_ratings(&graph, &solution) {
+        Ok(warnings) if !warnings.is_empty() => eprint!("{}", source_map.render(&ParseError::new(warnings))),
+        Ok(_) => (),
+        Err(e) => {
+            eprint!("{}", source_map.render(&e));
+            error_out(&format!("Could not check the ratings of '{}'", circuit.get_name()));
+        }
+    }
+
+    report(&graph, &solution);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_CIRCUITS: &str = ";a\n;;r1 -in=ground -out=ground,\n;;;default .resistor -resistance=1\n;\n\
+                                ;b\n;;r1 -in=ground -out=ground,\n;;;default .resistor -resistance=2\n;\n";
+
+    #[test]
+    fn circuit_is_chosen_by_name_or_when_alone() {
+        let circuits = parse(TWO_CIRCUITS).unwrap();
+
+        assert_eq!(select_circuit(&circuits, Some(&"b".to_string())).map(|x| x.get_line_number()), Ok(5));
+        assert_eq!(
+            select_circuit(&circuits, Some(&"c".to_string())).map(|x| x.get_name()),
+            Err("There is no circuit named 'c', available circuits: a, b".to_string())
+        );
+        assert_eq!(
+            select_circuit(&circuits, None).map(|x| x.get_name()),
+            Err("The netlist holds several circuits, choose one with --circuit: a, b".to_string())
+        );
+
+        let alone = parse(";only\n;\n").unwrap();
+        assert_eq!(select_circuit(&alone, None).map(|x| x.get_name()), Ok("only"));
+    }
+
+    #[test]
+    fn batch_reports_every_profile_side_by_side() {
+        let netlist = ";divider -profile=half -profile=open\n\
+                       ;;src -in=ground -out=next,\n;;;default .dcsource -voltage=10\n\
+                       ;;top -in=prev -out=next,\n;;;default .resistor -resistance=1k\n;;;half .resistor -resistance=3k\n\
+                       ;;bottom -in=prev -out=ground,\n;;;default .resistor -resistance=1k\n;;;open .capacitor -capacitance=1u\n\
+                       ;;probe -in=top -out=$PROBE\n;\n";
+
+        let circuit = Arc::new(parse(netlist).unwrap().remove(0));
+        let profiles = ["default", "half", "open", "missing"].map(|x| x.to_string());
+        let outcomes = probe_profiles(&circuit, &profiles);
+
+        assert_eq!(outcomes[0].as_ref().ok(), Some(&vec![("v(probe)".to_string(), "5V".to_string())]));
+        assert_eq!(outcomes[1].as_ref().ok(), Some(&vec![("v(probe)".to_string(), "2.5V".to_string())]));
+        assert_eq!(outcomes[2].as_ref().ok(), Some(&vec![("v(probe)".to_string(), "10V".to_string())]));
+        assert!(outcomes[3].is_err());
+
+        assert_eq!(
+            format_report(&profiles, &outcomes),
+            "probe     default  half  open  missing\nv(probe)  5V       2.5V  10V   -\n"
+        );
+    }
 }